@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+drop table if exists role_permissions;
+drop table if exists user_roles;
+drop table if exists permissions;
+drop table if exists roles;
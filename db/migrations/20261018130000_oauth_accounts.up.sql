@@ -0,0 +1,16 @@
+-- Links accounts at OAuth2/OpenID Connect providers to local users. `provider` is the name of the
+-- provider in server_config.toml and `subject` is the `sub` claim from its ID tokens, which is the
+-- only thing a provider promises will never change. The email is just a note of what the provider
+-- said at the time, it isn't used to match anybody.
+
+create table oauth_accounts (
+    id integer primary key not null,
+    user_id integer not null references users(id) on delete cascade,
+    provider text not null,
+    subject text not null,
+    email text,
+    created_at integer not null,
+    unique (provider, subject)
+);
+
+create index oauth_accounts_user_id on oauth_accounts (user_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+drop table if exists magic_link_tokens;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop index if exists webauthn_credentials_user_id;
+drop table if exists webauthn_credentials;
@@ -0,0 +1,19 @@
+-- Passkeys (WebAuthn credentials). `passkey` is the credential as serialized by webauthn-rs, which
+-- includes the public key and the signature counter. `credential_id` is the hex encoded id the
+-- authenticator gave the credential. `user_handle` is the random id the authenticator stores for
+-- the user (the same for all of a user's passkeys), which is how a passkey login finds the user
+-- without asking for a username.
+
+create table webauthn_credentials (
+    id integer primary key not null,
+    user_id integer not null references users(id) on delete cascade,
+    user_handle text not null,
+    credential_id text not null,
+    name text not null,
+    passkey text not null,
+    created_at integer not null,
+    last_used_at integer,
+    unique(credential_id)
+);
+
+create index webauthn_credentials_user_id on webauthn_credentials (user_id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop index if exists user_sessions_user_id;
+drop table if exists user_sessions;
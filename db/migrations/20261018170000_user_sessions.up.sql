@@ -0,0 +1,17 @@
+-- Which user each session belongs to, and what we saw of the client. tower-sessions keeps the
+-- sessions themselves (in their own table, as an opaque blob), so this is an index next to it:
+-- `session_id` is the id tower-sessions gave the session, the same one that is in the cookie. It is
+-- written as sessions get used, so `last_seen_at` is only as fresh as the last request.
+
+create table user_sessions (
+    id integer primary key not null,
+    session_id text not null,
+    user_id integer not null references users(id) on delete cascade,
+    user_agent text,
+    ip text,
+    created_at integer not null,
+    last_seen_at integer not null,
+    unique(session_id)
+);
+
+create index user_sessions_user_id on user_sessions (user_id);
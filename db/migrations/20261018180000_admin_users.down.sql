@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+drop index if exists admin_audit_created_at;
+drop table if exists admin_audit;
+delete from permissions where name = 'admin.users';
+alter table users drop column disabled_at;
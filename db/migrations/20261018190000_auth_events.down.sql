@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+drop index if exists auth_events_user_id;
+drop index if exists auth_events_created_at;
+drop table if exists auth_events;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+create table users (
+    id bigint generated by default as identity primary key,
+    username text not null,
+    pass_hash text not null,
+    unique(username)
+);
@@ -0,0 +1,32 @@
+-- Roles and permissions for the AuthzBackend. A user gets every permission granted to any of
+-- their roles.
+
+create table roles (
+    id bigint generated by default as identity primary key,
+    name text not null,
+    unique(name)
+);
+
+create table permissions (
+    id bigint generated by default as identity primary key,
+    name text not null,
+    unique(name)
+);
+
+create table user_roles (
+    user_id bigint not null references users(id) on delete cascade,
+    role_id bigint not null references roles(id) on delete cascade,
+    primary key (user_id, role_id)
+);
+
+create table role_permissions (
+    role_id bigint not null references roles(id) on delete cascade,
+    permission_id bigint not null references permissions(id) on delete cascade,
+    primary key (role_id, permission_id)
+);
+
+insert into roles (name) values ('admin');
+insert into permissions (name) values ('admin.access');
+insert into role_permissions (role_id, permission_id)
+    select roles.id, permissions.id from roles, permissions
+    where roles.name = 'admin' and permissions.name = 'admin.access';
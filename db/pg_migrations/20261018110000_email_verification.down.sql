@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+drop table if exists email_verification_tokens;
+drop index if exists users_email;
+alter table users drop column verified_at;
+alter table users drop column email;
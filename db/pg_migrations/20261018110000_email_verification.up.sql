@@ -0,0 +1,16 @@
+-- Optional email addresses for users, and proof that they can read mail sent there. verified_at is
+-- a unix timestamp (seconds), and stays null until the user follows a verification link.
+
+alter table users add column email text;
+alter table users add column verified_at bigint;
+
+create unique index users_email on users(email) where email is not null;
+
+create table email_verification_tokens (
+    id bigint generated by default as identity primary key,
+    user_id bigint not null references users(id) on delete cascade,
+    token_hash text not null,
+    expires_at bigint not null,
+    used_at bigint,
+    unique(token_hash)
+);
@@ -0,0 +1,13 @@
+-- TOTP (RFC 6238) second factor. The secret is encrypted with the server's totp_encryption_key,
+-- and recovery_codes holds SHA-256 hashes of the unused recovery codes, one per line.
+-- enabled_at stays null until the user proves their app works by entering a code, and
+-- last_used_step is the most recent 30 second step a code was accepted for, so codes can't be
+-- replayed.
+
+create table user_totp (
+    user_id bigint primary key not null references users(id) on delete cascade,
+    secret text not null,
+    recovery_codes text not null default '',
+    enabled_at bigint,
+    last_used_step bigint
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop index if exists oauth_accounts_user_id;
+drop table if exists oauth_accounts;
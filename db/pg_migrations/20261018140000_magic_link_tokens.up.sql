@@ -0,0 +1,11 @@
+-- Single-use login links. Like the password reset tokens, only a SHA-256 hash of the token is
+-- stored; the token itself only exists in the link that gets mailed to the user.
+
+create table magic_link_tokens (
+    id bigint generated by default as identity primary key,
+    user_id bigint not null references users(id) on delete cascade,
+    token_hash text not null,
+    expires_at bigint not null,
+    used_at bigint,
+    unique(token_hash)
+);
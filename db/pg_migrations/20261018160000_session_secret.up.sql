@@ -0,0 +1,8 @@
+-- A random secret per user that sessions are tied to (it's what `session_auth_hash` is made from).
+-- Changing it logs the user out everywhere. Existing users get a fresh one each, which logs them
+-- out once, since their sessions were tied to their password hash before.
+
+alter table users add column session_secret text not null default '';
+
+update users set session_secret =
+    replace(gen_random_uuid()::text, '-', '') || replace(gen_random_uuid()::text, '-', '');
@@ -0,0 +1,23 @@
+-- For the admin console at /admin/users. Disabled users can't log in, and their sessions stop
+-- working. Everything an admin does there goes into admin_audit. The names are copied in, so the
+-- record still says who did what to whom after either of them is deleted.
+
+alter table users add column disabled_at bigint;
+
+insert into permissions (name) values ('admin.users');
+insert into role_permissions (role_id, permission_id)
+    select roles.id, permissions.id from roles, permissions
+    where roles.name = 'admin' and permissions.name = 'admin.users';
+
+create table admin_audit (
+    id bigint generated by default as identity primary key,
+    created_at bigint not null,
+    actor_id bigint references users(id) on delete set null,
+    actor_name text not null,
+    action text not null,
+    target_id bigint,
+    target_name text,
+    detail text
+);
+
+create index admin_audit_created_at on admin_audit (created_at);
@@ -0,0 +1,18 @@
+-- A record of logins (and failed ones), registrations, logouts, password changes and lockouts, with
+-- where they came from. The username is copied in, so failed logins for names that don't exist
+-- still say what was tried, and events outlive the users they're about. Events older than
+-- `auth_event_retention_days` get deleted.
+
+create table auth_events (
+    id bigint generated by default as identity primary key,
+    created_at bigint not null,
+    kind text not null,
+    user_id bigint references users(id) on delete set null,
+    username text,
+    ip text,
+    user_agent text,
+    detail text
+);
+
+create index auth_events_created_at on auth_events (created_at);
+create index auth_events_user_id on auth_events (user_id);
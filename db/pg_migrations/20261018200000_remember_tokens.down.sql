@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop index if exists remember_tokens_user_id;
+drop table if exists remember_tokens;
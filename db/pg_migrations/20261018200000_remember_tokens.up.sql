@@ -0,0 +1,18 @@
+-- "Remember me" logins. Each row is one browser that asked to stay logged in. Its cookie holds
+-- the selector, which finds the row, and a validator, which is only stored hashed. The validator
+-- changes every time the cookie is used to log back in; the one before it is kept for a moment,
+-- so requests that were already on their way with it don't look like somebody replaying a stolen
+-- cookie.
+
+create table remember_tokens (
+    id bigint generated by default as identity primary key,
+    user_id bigint not null references users(id) on delete cascade,
+    selector text not null unique,
+    validator_hash text not null,
+    previous_validator_hash text,
+    rotated_at bigint,
+    created_at bigint not null,
+    expires_at bigint not null
+);
+
+create index remember_tokens_user_id on remember_tokens (user_id);
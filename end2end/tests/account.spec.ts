@@ -0,0 +1,91 @@
+import { test, expect, Page } from "@playwright/test";
+import { readFile } from "fs/promises";
+
+async function register(page: Page, username: string, password: string) {
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.locator('input[name="password2"]').fill(password);
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+}
+
+// Sessions remember users by id, so renaming yourself shouldn't log you out. Logs in through the
+// mock OpenID Connect provider, like oauth.spec.ts, so it needs the same server config.
+test("change username without losing the session", async ({ page }) => {
+  const subject = `e2e-${Date.now()}`;
+
+  await page.goto("http://localhost:3000/login");
+  await page.getByText("Sign in with Mock OIDC").click();
+  await page.locator('input[name="subject"]').fill(subject);
+  await page.locator('input[name="email"]').fill(`${subject}@example.com`);
+  await page.getByRole("button", { name: "sign in" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+
+  await page.goto("http://localhost:3000/account");
+  await page.locator('input[name="username"]').fill(`${subject}-renamed`);
+  await page.getByRole("button", { name: "change username" }).click();
+  await expect(page.getByText("Your username has been changed.")).toBeVisible();
+
+  await page.goto("http://localhost:3000/");
+  await expect(page.locator("h1")).toHaveText(`Welcome home, ${subject}-renamed`);
+});
+
+// Changing the password logs out every other session, but not the one that changed it.
+test("change password keeps this session and ends the others", async ({ browser }) => {
+  const username = `pw${Date.now()}`;
+  const oldPassword = "correct horse battery staple";
+  const newPassword = "tremendous walrus on a unicycle";
+
+  const page = await browser.newPage();
+  await register(page, username, oldPassword);
+
+  const other = await browser.newPage();
+  await other.goto("http://localhost:3000/login");
+  await other.locator('input[name="username"]').first().fill(username);
+  await other.locator('input[name="password"]').fill(oldPassword);
+  await other.getByRole("button", { name: "sign in", exact: true }).click();
+  await expect(other).toHaveURL("http://localhost:3000/");
+
+  await page.goto("http://localhost:3000/account");
+  await page.locator('input[name="current_password"]').fill("not my password");
+  await page.locator('input[name="new_password"]').fill(newPassword);
+  await page.getByRole("button", { name: "change password" }).click();
+  await expect(page.getByText("That isn't your current password")).toBeVisible();
+
+  await page.locator('input[name="current_password"]').fill(oldPassword);
+  await page.locator('input[name="new_password"]').fill(newPassword);
+  await page.getByRole("button", { name: "change password" }).click();
+  await expect(page.getByText("Your password has been changed.")).toBeVisible();
+
+  await page.goto("http://localhost:3000/");
+  await expect(page.locator("h1")).toHaveText(`Welcome home, ${username}`);
+  await other.goto("http://localhost:3000/");
+  await expect(other.locator("h1")).not.toHaveText(`Welcome home, ${username}`);
+});
+
+test("download the account data, then delete the account", async ({ page }) => {
+  const username = `gone${Date.now()}`;
+  const password = "correct horse battery staple";
+  await register(page, username, password);
+
+  await page.goto("http://localhost:3000/account");
+  const downloading = page.waitForEvent("download");
+  await page.getByText("Download everything we have about you").click();
+  const exported = JSON.parse(await readFile(await (await downloading).path(), "utf8"));
+  expect(exported.user.username).toBe(username);
+  expect(exported.user.pass_hash).toBeUndefined();
+  expect(exported.sessions.some((s: { current: boolean }) => s.current)).toBe(true);
+  expect(exported.login_history.map((e: { kind: string }) => e.kind)).toContain("registered");
+
+  await page.locator("#delete_password").fill(password);
+  await page.getByRole("button", { name: "delete my account" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+  await expect(page.locator("h1")).not.toHaveText(`Welcome home, ${username}`);
+
+  await page.goto("http://localhost:3000/login");
+  await page.locator('input[name="username"]').first().fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.getByRole("button", { name: "sign in", exact: true }).click();
+  await expect(page).toHaveURL("http://localhost:3000/login");
+});
@@ -0,0 +1,57 @@
+import { test, expect } from "@playwright/test";
+
+// Making somebody an admin takes a row in user_roles (see the README), so this only covers what
+// everybody else sees: the console turns them away, and its server functions refuse them too.
+test("admin console is closed to normal users", async ({ page }) => {
+  const username = `notadmin${Date.now()}`;
+  const password = "correct horse battery staple";
+
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.locator('input[name="password2"]').fill(password);
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+  await expect(page.getByText("Manage users")).toHaveCount(0);
+
+  await page.goto("http://localhost:3000/admin/users");
+  await expect(page.getByText("You aren't allowed in here.")).toBeVisible();
+  await expect(page.locator("#search")).toHaveCount(0);
+
+  const response = await page.request.post("http://localhost:3000/api/admin/search_users", {
+    form: { query: "", page: "0" },
+  });
+  expect(response.ok()).toBe(false);
+  expect(await response.text()).toContain("admin.users");
+});
+
+// The event log is for admins only, but a failed login still has to land in it; the user's own
+// export is where a normal user can see that.
+test("failed logins show up in the login history", async ({ page }) => {
+  const username = `history${Date.now()}`;
+  const password = "correct horse battery staple";
+
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.locator('input[name="password2"]').fill(password);
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+
+  const other = await page.context().browser()!.newPage();
+  await other.goto("http://localhost:3000/login");
+  await other.locator('input[name="username"]').first().fill(username);
+  await other.locator('input[name="password"]').fill("not the password");
+  await other.getByRole("button", { name: "sign in", exact: true }).click();
+
+  await expect(async () => {
+    const response = await page.request.get("http://localhost:3000/account/export");
+    const exported = await response.json();
+    const kinds = exported.login_history.map((e: { kind: string }) => e.kind);
+    expect(kinds).toContain("login_failed");
+    expect(kinds).toContain("registered");
+  }).toPass();
+
+  await page.goto("http://localhost:3000/admin/auth-events");
+  await expect(page.getByText("You aren't allowed in here.")).toBeVisible();
+});
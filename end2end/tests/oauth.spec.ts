@@ -0,0 +1,19 @@
+import { test, expect } from "@playwright/test";
+
+// Needs the server running with the [mock_oidc] section and the "mock" provider uncommented in
+// server_config.toml. They ship commented out, since the mock lets anybody sign in as anyone.
+test("log in through the mock OpenID Connect provider", async ({ page }) => {
+  const subject = `e2e-${Date.now()}`;
+
+  await page.goto("http://localhost:3000/login");
+  await page.getByText("Sign in with Mock OIDC").click();
+
+  await expect(page.locator("h1")).toHaveText("Mock OpenID Connect provider");
+  await page.locator('input[name="subject"]').fill(subject);
+  await page.locator('input[name="email"]').fill(`${subject}@example.com`);
+  await page.getByRole("button", { name: "sign in" }).click();
+
+  await expect(page).toHaveURL("http://localhost:3000/");
+  await page.goto("http://localhost:3000/login");
+  await expect(page.getByText(`Logged in as ${subject}`)).toBeVisible();
+});
@@ -0,0 +1,38 @@
+import { test, expect } from "@playwright/test";
+
+// Passkeys need an authenticator, so this one uses Chrome's virtual authenticator through the
+// DevTools protocol. It stands in for a phone or security key that has user verification (a
+// fingerprint, say) and keeps its credentials on the device, which is what a passkey needs.
+test("register with a passkey, then log in with it", async ({ page, browserName }) => {
+  test.skip(browserName !== "chromium", "the virtual authenticator is a Chrome thing");
+
+  const cdp = await page.context().newCDPSession(page);
+  await cdp.send("WebAuthn.enable");
+  await cdp.send("WebAuthn.addVirtualAuthenticator", {
+    options: {
+      protocol: "ctap2",
+      transport: "internal",
+      hasResidentKey: true,
+      hasUserVerification: true,
+      isUserVerified: true,
+    },
+  });
+
+  const username = `passkey${Date.now()}`;
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill("correct horse battery staple");
+  await page.locator('input[name="password2"]').fill("correct horse battery staple");
+  await page.getByLabel("Set up a passkey too").check();
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+
+  await page.goto("http://localhost:3000/account/passkeys");
+  await expect(page.getByText(`${username}'s passkey`)).toBeVisible();
+
+  await page.goto("http://localhost:3000/logout");
+  await page.goto("http://localhost:3000/login");
+  await expect(page.getByText("Not logged in")).toBeVisible();
+  await page.getByRole("button", { name: "Sign in with a passkey" }).click();
+  await expect(page.getByText(`Logged in as ${username}`)).toBeVisible();
+});
@@ -0,0 +1,32 @@
+import { test, expect } from "@playwright/test";
+
+// Relies on the [password_policy] in server_config.toml as shipped: at least 8 characters, the
+// blocklist in db/common-passwords.txt, and no usernames in passwords.
+test("a password that breaks the policy lists every rule it broke", async ({ page }) => {
+  const username = `policy${Date.now()}`;
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill(username);
+  await page.locator('input[name="password2"]').fill(username);
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page.getByText("That password won't do:")).toBeVisible();
+  await expect(page.getByText("It can't contain your username")).toBeVisible();
+
+  await page.locator('input[name="password"]').fill("letmein");
+  await page.locator('input[name="password2"]').fill("letmein");
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page.getByText("It has to be at least 8 characters long")).toBeVisible();
+  await expect(page.getByText("It's one of the most common passwords")).toBeVisible();
+});
+
+test("the strength meter explains what's wrong, and the server agrees", async ({ page }) => {
+  const username = `strength${Date.now()}`;
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill("abcdefghijklmnop");
+  await expect(page.locator("#strength-feedback")).toContainText("Avoid sequences");
+
+  await page.locator('input[name="password2"]').fill("abcdefghijklmnop");
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page.getByText("It's too easy to guess")).toBeVisible();
+});
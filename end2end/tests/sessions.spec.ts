@@ -0,0 +1,121 @@
+import { test, expect, Browser, BrowserContext, Page } from "@playwright/test";
+
+async function logIn(page: Page, username: string, password: string) {
+  await page.goto("http://localhost:3000/login");
+  await page.locator('input[name="username"]').first().fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.getByRole("button", { name: "sign in", exact: true }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+}
+
+// A session revoked from the Active sessions page stops working right away.
+test("revoke another session", async ({ browser }) => {
+  const username = `sessions${Date.now()}`;
+  const password = "correct horse battery staple";
+
+  const page = await browser.newPage();
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.locator('input[name="password2"]').fill(password);
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+
+  const other = await browser.newPage();
+  await logIn(other, username, password);
+  await expect(other.locator("h1")).toHaveText(`Welcome home, ${username}`);
+
+  await page.goto("http://localhost:3000/account/sessions");
+  await expect(page.getByText("this one")).toBeVisible();
+  await expect(page.getByRole("button", { name: "revoke" })).toHaveCount(1);
+  await page.getByRole("button", { name: "revoke" }).click();
+  await expect(page.getByRole("button", { name: "revoke" })).toHaveCount(0);
+
+  await other.reload();
+  await expect(other.locator("h1")).not.toHaveText(`Welcome home, ${username}`);
+  await page.goto("http://localhost:3000/");
+  await expect(page.locator("h1")).toHaveText(`Welcome home, ${username}`);
+});
+
+// Playwright can't let a session cookie expire on its own, so these throw away every cookie but
+// the "remember me" one, which is what a browser has left once its session is over.
+async function dropSession(context: BrowserContext) {
+  const remember = (await context.cookies()).filter((c) => c.name === "remember_me");
+  await context.clearCookies();
+  await context.addCookies(remember);
+}
+
+async function logInRemembered(context: BrowserContext, username: string, password: string) {
+  const page = await context.newPage();
+  await page.goto("http://localhost:3000/login");
+  await page.locator('input[name="username"]').first().fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.getByLabel("Remember me").check();
+  await page.getByRole("button", { name: "sign in", exact: true }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+  return page;
+}
+
+async function registerUser(browser: Browser, username: string, password: string) {
+  const page = await browser.newPage();
+  await page.goto("http://localhost:3000/register");
+  await page.locator('input[name="username"]').fill(username);
+  await page.locator('input[name="password"]').fill(password);
+  await page.locator('input[name="password2"]').fill(password);
+  await page.getByRole("button", { name: "Register" }).click();
+  await expect(page).toHaveURL("http://localhost:3000/");
+  await page.close();
+}
+
+// With "remember me" ticked, a browser whose session is gone logs itself back in, and logging out
+// makes it forget.
+test("remember me outlasts the session", async ({ browser }) => {
+  const username = `remember${Date.now()}`;
+  const password = "correct horse battery staple";
+  await registerUser(browser, username, password);
+
+  const context = await browser.newContext();
+  const page = await logInRemembered(context, username, password);
+  const first = (await context.cookies()).find((c) => c.name === "remember_me");
+  expect(first?.httpOnly).toBe(true);
+
+  await dropSession(context);
+  await page.reload();
+  await expect(page.locator("h1")).toHaveText(`Welcome home, ${username}`);
+  // Using the cookie swaps it for a new one.
+  const second = (await context.cookies()).find((c) => c.name === "remember_me");
+  expect(second?.value).not.toBe(first?.value);
+
+  await page.goto("http://localhost:3000/logout");
+  await expect(page.getByText("You have been logged out.")).toBeVisible();
+  expect((await context.cookies()).find((c) => c.name === "remember_me")).toBeUndefined();
+  await dropSession(context);
+  await page.goto("http://localhost:3000/");
+  await expect(page.locator("h1")).not.toHaveText(`Welcome home, ${username}`);
+});
+
+// A copy of the cookie that gets used after the real one has moved on gives the game away, and
+// both browsers get logged out.
+test("reusing a replaced remember me cookie logs everybody out", async ({ browser }) => {
+  test.slow();
+  const username = `stolen${Date.now()}`;
+  const password = "correct horse battery staple";
+  await registerUser(browser, username, password);
+
+  const victim = await browser.newContext();
+  const victimPage = await logInRemembered(victim, username, password);
+  const thief = await browser.newContext();
+  await thief.addCookies((await victim.cookies()).filter((c) => c.name === "remember_me"));
+
+  const thiefPage = await thief.newPage();
+  await thiefPage.goto("http://localhost:3000/");
+  await expect(thiefPage.locator("h1")).toHaveText(`Welcome home, ${username}`);
+
+  // Right after a swap, the old cookie still works for a moment, so wait that out.
+  await victimPage.waitForTimeout(31_000);
+  await dropSession(victim);
+  await victimPage.reload();
+  await expect(victimPage.locator("h1")).not.toHaveText(`Welcome home, ${username}`);
+  await thiefPage.reload();
+  await expect(thiefPage.locator("h1")).not.toHaveText(`Welcome home, ${username}`);
+});
@@ -0,0 +1,166 @@
+use std::str::FromStr;
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_login::AuthSession;
+use serde::Serialize;
+use tower_sessions::session::Id;
+use tower_sessions::{Session, SessionStore};
+use crate::backend::AppBackend;
+use crate::error_template::AppError;
+use crate::state::AppState;
+use crate::store::{AuthEventFilter, SessionEntry};
+use crate::tokens::now;
+use crate::user::DatabaseId;
+
+// Self service for the data we keep about a user: downloading all of it, and taking their
+// sessions along when the account gets deleted.
+//
+// tower-sessions has no idea which user a session belongs to, so a user's sessions are found
+// through the `user_sessions` table (see `AuthBackend::list_sessions`), the same list the Active
+// sessions page shows.
+
+/// Remove these sessions from the session store, and return how many there were. Rotating the
+/// session secret already makes them useless; this is for when they shouldn't even be left
+/// sitting in the database, like when the account is deleted.
+pub async fn delete_sessions(state: &AppState, sessions: &[SessionEntry]) -> Result<usize, AppError> {
+    for entry in sessions {
+        // An id that doesn't parse can't be in the session store either.
+        if let Ok(session_id) = Id::from_str(&entry.session_id) {
+            state.session_store.delete(&session_id).await
+                .map_err(|e| AppError::InternalError(format!("Delete session: {e}")))?;
+        }
+    }
+    Ok(sessions.len())
+}
+
+/// Everything we know about a user, as it goes out in the download. The password hash and the
+/// session secret are left out; they're not about the user, they're how we check it's them, and a
+/// copy of them lying around in somebody's downloads folder doesn't help anybody.
+#[derive(Clone,Debug,Serialize)]
+pub struct AccountExport {
+    pub exported_at: i64,
+    pub user: ExportedUser,
+    pub roles: Vec<String>,
+    pub two_factor_enabled: bool,
+    pub passkeys: Vec<ExportedPasskey>,
+    pub sessions: Vec<ExportedSession>,
+    /// Newest first, for as long as the event log keeps them (`auth_event_retention_days`)
+    pub login_history: Vec<ExportedAuthEvent>,
+}
+
+/// The user's row from the users table. The timestamps here and below are Unix times.
+#[derive(Clone,Debug,Serialize)]
+pub struct ExportedUser {
+    pub id: DatabaseId,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified_at: Option<i64>,
+}
+
+/// A passkey, without the key itself.
+#[derive(Clone,Debug,Serialize)]
+pub struct ExportedPasskey {
+    pub name: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+/// A session, without its id. The id is what's in the session cookie, so it's as good as a
+/// password for as long as the session lasts.
+#[derive(Clone,Debug,Serialize)]
+pub struct ExportedSession {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    /// True for the session the download was made with
+    pub current: bool,
+}
+
+/// Something from the authentication event log: a login, a failed one, a logout, a password
+/// change and so on. `kind` is one of `AuthEventKind::as_str`.
+#[derive(Clone,Debug,Serialize)]
+pub struct ExportedAuthEvent {
+    pub at: i64,
+    pub kind: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// How many login history events `export_account` reads at once.
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// Gather up everything for the download.
+pub async fn export_account(auth: &AuthSession<AppBackend>, session: &Session, user_id: DatabaseId)
+-> Result<AccountExport, AppError> {
+    use crate::store::UserStore;
+    let backend = &auth.backend;
+    let user = backend.store.find_by_id(user_id).await?.ok_or(AppError::NotFound)?;
+    let passkeys = backend.list_passkeys(user_id).await?.into_iter()
+        .map(|p| ExportedPasskey{ name: p.name, created_at: p.created_at, last_used_at: p.last_used_at })
+        .collect();
+    let current = session.id().map(|id| id.to_string());
+    let sessions = backend.list_sessions(user_id).await?.into_iter()
+        .map(|entry| ExportedSession{
+            current: current.as_deref() == Some(entry.session_id.as_str()),
+            user_agent: entry.user_agent,
+            ip: entry.ip,
+            created_at: entry.created_at,
+            last_seen_at: entry.last_seen_at,
+        })
+        .collect();
+    let filter = AuthEventFilter{ user_id: Some(user_id), ..Default::default() };
+    // The whole history goes in, read a batch at a time rather than with one query that has no
+    // real limit.
+    let mut login_history = Vec::new();
+    loop {
+        let (events, _) = backend.list_auth_events(&filter, login_history.len() as i64, EXPORT_BATCH_SIZE).await?;
+        let done = (events.len() as i64) < EXPORT_BATCH_SIZE;
+        login_history.extend(events.into_iter()
+            .map(|e| ExportedAuthEvent{ at: e.created_at, kind: e.kind, ip: e.ip, user_agent: e.user_agent, detail: e.detail }));
+        if done {
+            break;
+        }
+    }
+    Ok(AccountExport {
+        exported_at: now(),
+        user: ExportedUser{
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            email_verified_at: user.verified_at,
+        },
+        roles: backend.get_user_roles(user_id).await?,
+        two_factor_enabled: backend.totp_enabled(user_id).await?,
+        passkeys,
+        sessions,
+        login_history,
+    })
+}
+
+/// `GET /account/export` downloads the logged-in user's data as a JSON file. It's a plain route
+/// instead of a server function so the browser treats the answer as a file to save.
+pub async fn account_export(
+    auth: AuthSession<AppBackend>,
+    session: Session,
+) -> Response {
+    let Some(user) = auth.user.clone() else {
+        return (StatusCode::UNAUTHORIZED, "You have to be logged in for this").into_response();
+    };
+    let json = export_account(&auth, &session, user.id).await
+        .and_then(|export| serde_json::to_string_pretty(&export)
+            .map_err(|e| AppError::InternalError(format!("Write export: {e}"))));
+    match json {
+        Ok(json) => (
+            [
+                (header::CONTENT_TYPE, "application/json"),
+                (header::CONTENT_DISPOSITION, r#"attachment; filename="account.json""#),
+            ],
+            json,
+        ).into_response(),
+        Err(e) => (e.status_code(), e.to_string()).into_response(),
+    }
+}
@@ -1,7 +1,11 @@
 use leptos::prelude::*;
 use leptos_meta::Title;
 use leptos_router_macro::path;
-use crate::pages::{Login,Register};
+use crate::pages::{
+    AccountSettings,ActiveSessions,AdminAudit,AdminAuthEventLog,AdminUsers,ForgotPassword,ImpersonationBanner,
+    Login,Logout,MagicLink,Passkeys,Register,ResetPassword,TwoFactor,VerifyEmail,
+};
+use crate::components::{LogoutButton,RequirePermission,SignOutEverywhereButton};
 use leptos_router::components::{Router,Routes,Route};
 
 #[component]
@@ -17,6 +21,18 @@ pub fn App() -> impl IntoView {
                 <Route path=path!("/") view=HomePage/>
                 <Route path=path!("/register") view=Register/>
                 <Route path=path!("/login") view=Login/>
+                <Route path=path!("/logout") view=Logout/>
+                <Route path=path!("/forgot-password") view=ForgotPassword/>
+                <Route path=path!("/reset-password") view=ResetPassword/>
+                <Route path=path!("/verify-email") view=VerifyEmail/>
+                <Route path=path!("/account") view=AccountSettings/>
+                <Route path=path!("/account/two-factor") view=TwoFactor/>
+                <Route path=path!("/magic") view=MagicLink/>
+                <Route path=path!("/account/passkeys") view=Passkeys/>
+                <Route path=path!("/account/sessions") view=ActiveSessions/>
+                <Route path=path!("/admin/users") view=AdminUsers/>
+                <Route path=path!("/admin/audit") view=AdminAudit/>
+                <Route path=path!("/admin/auth-events") view=AdminAuthEventLog/>
             </Routes>
         </Router>
     }
@@ -55,7 +71,22 @@ fn HomePage() -> impl IntoView {
                         Either::Left(
                             view! {
                                 <Title text=move || format!("This is home.")/>
+                                <ImpersonationBanner/>
                                 <h1>"Welcome home, " {user.username}</h1>
+                                // Only users with a role that grants `admin.access` see this. See
+                                // the `roles_permissions` migration for where that comes from.
+                                <RequirePermission permission="admin.access">
+                                    <p>"You're an admin, by the way."</p>
+                                </RequirePermission>
+                                <RequirePermission permission="admin.users">
+                                    <p><a href="/admin/users" class="text-indigo-600">"Manage users"</a></p>
+                                </RequirePermission>
+                                <p><a href="/account" class="text-indigo-600">"Account settings"</a></p>
+                                <p><a href="/account/two-factor" class="text-indigo-600">"Two-factor authentication"</a></p>
+                                <p><a href="/account/passkeys" class="text-indigo-600">"Passkeys"</a></p>
+                                <p><a href="/account/sessions" class="text-indigo-600">"Active sessions"</a></p>
+                                <LogoutButton/>
+                                <SignOutEverywhereButton/>
                             },
                         )
                     }
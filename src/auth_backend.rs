@@ -0,0 +1,758 @@
+use std::collections::HashSet;
+use argon2::PasswordHash;
+use axum_login::{AuthnBackend, AuthzBackend, UserId};
+use tracing::{info, warn};
+use totp_rs::TOTP;
+use webauthn_rs::prelude::{DiscoverableAuthentication, Passkey, PublicKeyCredential, Uuid};
+use crate::user::*;
+use crate::config::ServerConfig;
+use crate::credentials::Credentials;
+use crate::error_template::AppError;
+use crate::passkeys::{Passkeys, StoredPasskey};
+use crate::passwords::Passwords;
+use crate::secret::Secret;
+use crate::auth_events::AuthEventKind;
+use crate::state::ClientInfo;
+use crate::store::{
+    AuditEntry, AuthEvent, AuthEventFilter, NewAuditEntry, NewAuthEvent, NewRememberToken, NewUser, SessionEntry, Store,
+    TokenKind,
+};
+use crate::throttle::LoginThrottle;
+use crate::tokens::{generate_token, hash_token, now, to_hex};
+use crate::totp::{matching_step, new_recovery_codes, new_secret, totp_for, TotpCipher};
+
+/// The authentication backend. This is where the decisions get made: hashing and checking
+/// passwords, making and checking tokens and codes, and telling axum_login who's logged in. Where
+/// the users actually live is up to the store (see store.rs), so the same backend runs on SQLite,
+/// Postgres, or a plain in-memory store for tests. The app uses `backend::AppBackend`, which is
+/// this with whichever store the cargo features picked.
+#[derive(Clone,Debug)]
+pub struct AuthBackend<S> {
+    pub store: S,
+    /// Token lifetimes and such come from here
+    pub config: ServerConfig,
+    /// Counts failed logins. This is shared between clones of the backend, so every request sees
+    /// the same counts.
+    pub throttle: LoginThrottle,
+    /// Checks passkey responses. See passkeys.rs.
+    pub passkeys: Passkeys,
+    /// Hashes and checks passwords. See passwords.rs.
+    pub passwords: Passwords,
+}
+
+impl<S: Store> AuthBackend<S> {
+    pub fn new(store: S, config: ServerConfig) -> Self {
+        let throttle = LoginThrottle::new(&config);
+        let passkeys = Passkeys::from_config(&config).expect("Couldn't set up passkeys, check public_url in the config");
+        let passwords = Passwords::from_config(&config).expect("Couldn't set up password hashing, check the argon2 settings in the config");
+        AuthBackend{store, config, throttle, passkeys, passwords}
+    }
+
+    /// Make sure the database is up to date with the expected schema.
+    pub async fn migrate(&self) -> Result<(),AppError> {
+        self.store.migrate().await
+    }
+
+    /// Insert a new user into the database. Success only if the user doesn't already exist
+    /// and the data meets criteria. The password has to follow the password policy from the config.
+    pub async fn add_user(&self, username: String, password: String, email: Option<String>) -> Result<Option<User>, AppError> {
+        // First validate the data.
+        self.check_new_user(&username, email.as_deref())?;
+        self.passwords.check_policy(&username, &password)?;
+        let pass_hash = self.passwords.hash(&password)?;
+        self.insert_user(username, pass_hash, email).await
+    }
+
+    /// Insert a new user who doesn't get a password, like somebody signing up through an OAuth
+    /// provider. They actually get a random one that nobody knows. That never goes through the
+    /// password policy, since a policy strict enough to turn it down would make every such sign-up
+    /// fail. A password reset gives them a real one if they want it.
+    pub async fn add_user_without_password(&self, username: String, email: Option<String>) -> Result<Option<User>, AppError> {
+        self.check_new_user(&username, email.as_deref())?;
+        let pass_hash = self.passwords.hash(&generate_token())?;
+        self.insert_user(username, pass_hash, email).await
+    }
+
+    fn check_new_user(&self, username: &str, email: Option<&str>) -> Result<(), AppError> {
+        if username.len() < 2 {
+            return Err(AppError::InvalidData("Usernames have to be at least 2 characters!".into()));
+        }
+        // Nobody can verify an address they didn't give us.
+        if self.config.require_verified_email && email.is_none() {
+            return Err(AppError::InvalidData("An email address is required".into()));
+        }
+        if email.is_some_and(|e| !e.contains('@')) {
+            return Err(AppError::InvalidData("That doesn't look like an email address".into()));
+        }
+        Ok(())
+    }
+
+    async fn insert_user(&self, username: String, pass_hash: String, email: Option<String>) -> Result<Option<User>, AppError> {
+        let id = self.store.insert(NewUser{ username, pass_hash: Secret::new(pass_hash), email, session_secret: Secret::new(generate_token()) }).await?;
+        self.find_user_by_id(id).await
+    }
+
+    /// Look a user up by name. This is for flows like password resets where somebody claims to be
+    /// a user but hasn't proven it yet, so don't log anybody in based on this alone.
+    pub async fn find_user_by_name(&self, username: &str) -> Result<Option<User>, AppError> {
+        self.store.find_by_username(username).await?.map(SqlUser::to_user).transpose()
+    }
+
+    /// Look a user up by email address. Addresses are unique, so there's at most one.
+    pub async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        self.store.find_by_email(email).await?.map(SqlUser::to_user).transpose()
+    }
+
+    /// Look a user up by their database id.
+    pub async fn find_user_by_id(&self, user_id: DatabaseId) -> Result<Option<User>, AppError> {
+        self.store.find_by_id(user_id).await?.map(SqlUser::to_user).transpose()
+    }
+
+    /// Replace a user's password. It has to follow the password policy, same as in `add_user`.
+    /// Anybody who got in with the old password shouldn't stay in, so this also ends every session
+    /// the user has open.
+    pub async fn set_password(&self, user_id: DatabaseId, password: String) -> Result<(), AppError> {
+        let user = self.store.find_by_id(user_id).await?.ok_or(AppError::NotFound)?;
+        self.passwords.check_policy(&user.username, &password)?;
+        let pass_hash = self.passwords.hash(&password)?;
+        if !self.store.update_password(user_id, &pass_hash).await? {
+            return Err(AppError::NotFound);
+        }
+        self.rotate_session_secret(user_id).await
+    }
+
+    /// Change a user's password, for a user who knows their current one. Like `set_password`, this
+    /// ends every session the user has, including the one asking; see `change_password` in
+    /// pages/account for how that one stays logged in.
+    pub async fn change_password(&self, user_id: DatabaseId, current: &str, new: String) -> Result<(), AppError> {
+        self.check_current_password(user_id, current).await?;
+        self.set_password(user_id, new).await
+    }
+
+    /// Delete a user's own account, for a user who knows their password. Everything in the store
+    /// that belongs to them goes with it; their sessions are in the session store, which the
+    /// backend can't see, so see `delete_account` in pages/account for those.
+    pub async fn delete_account(&self, user_id: DatabaseId, password: &str) -> Result<(), AppError> {
+        self.check_current_password(user_id, password).await?;
+        self.delete_user(user_id).await
+    }
+
+    /// Make a logged-in user prove it's really them before something drastic. Wrong guesses count
+    /// toward the same lockout as logging in, otherwise somebody who found a logged-in browser
+    /// could guess the password here instead.
+    async fn check_current_password(&self, user_id: DatabaseId, password: &str) -> Result<(), AppError> {
+        let user = self.store.find_by_id(user_id).await?.ok_or(AppError::NotFound)?;
+        let user_key = LoginThrottle::user_key(&user.username);
+        self.throttle.check(&[&user_key])?;
+        if !self.passwords.verify(password, &user.pass_hash)? {
+            self.throttle.record_failure(&[&user_key]);
+            return Err(AppError::InvalidData("That isn't your current password".into()));
+        }
+        self.throttle.record_success(&[&user_key]);
+        Ok(())
+    }
+
+    /// Rename a user. Sessions remember users by id, so this doesn't log anybody out. The same
+    /// (weak!) length rule as `add_user` applies.
+    pub async fn change_username(&self, user_id: DatabaseId, username: String) -> Result<(), AppError> {
+        if username.len() < 2 {
+            return Err(AppError::InvalidData("Usernames have to be at least 2 characters!".into()));
+        }
+        if !self.store.update_username(user_id, &username).await? {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Give the user a new session secret. Every session they have, on every device, stops
+    /// working the next time it's used, since its `session_auth_hash` no longer matches. They
+    /// come off the user's list of sessions right away. Their "remember me" tokens go too, or
+    /// those browsers would just log themselves back in.
+    pub async fn rotate_session_secret(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        if !self.store.set_session_secret(user_id, &generate_token()).await? {
+            return Err(AppError::NotFound);
+        }
+        self.store.delete_user_remember_tokens(user_id).await?;
+        self.store.remove_user_sessions(user_id).await
+    }
+
+    /// Keep the user's list of sessions up to date. main.rs calls this on every request from a
+    /// logged-in user, with the session's id from the session store.
+    pub async fn touch_session(&self, user_id: DatabaseId, session_id: &str, user_agent: Option<&str>, ip: &str) -> Result<(), AppError> {
+        self.store.touch_session(user_id, session_id, user_agent, ip, now()).await
+    }
+
+    /// The user's sessions that haven't expired yet, most recently used first. Sessions expire
+    /// after `session_timeout_seconds` without a request, and `last_seen_at` is kept to within a
+    /// minute, so that's close enough to tell.
+    pub async fn list_sessions(&self, user_id: DatabaseId) -> Result<Vec<SessionEntry>, AppError> {
+        self.store.list_sessions(user_id, now() - self.config.session_timeout_seconds).await
+    }
+
+    /// Take one of the user's sessions off their list and return it, so the caller can delete the
+    /// session itself from the session store. None if the user has no such session.
+    pub async fn revoke_session(&self, user_id: DatabaseId, id: i64) -> Result<Option<SessionEntry>, AppError> {
+        self.store.remove_session(user_id, id).await
+    }
+
+    /// A session is over (logged out, or deleted from the session store), so stop listing it.
+    pub async fn forget_session(&self, session_id: &str) -> Result<(), AppError> {
+        self.store.forget_session(session_id).await
+    }
+
+    /// Find the user an old session was for, from the days when sessions kept the username and
+    /// the `session_auth_hash` was the raw hash bytes out of the user's password hash. Those
+    /// sessions only ever held that, so that's what has to match, not the session secret. A user
+    /// who changed their password since then doesn't match, same as back then.
+    pub async fn find_legacy_session_user(&self, username: &str, auth_hash: &[u8]) -> Result<Option<User>, AppError> {
+        let Some(user) = self.store.find_by_username(username).await? else {
+            return Ok(None);
+        };
+        let matches = PasswordHash::new(user.pass_hash.expose()).ok()
+            .and_then(|hash| hash.hash)
+            .is_some_and(|hash| hash.as_bytes() == auth_hash);
+        if !matches {
+            return Ok(None);
+        }
+        Ok(Some(user.to_user()?))
+    }
+
+    /// Remove a user and everything that belongs to them.
+    pub async fn delete_user(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        if !self.store.delete(user_id).await? {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Make a new token of the given kind for the user and return it. This is the only time the
+    /// token is visible, the store only gets its hash. Any older tokens of the same kind are thrown
+    /// away, so only the most recent link works.
+    async fn issue_token(&self, kind: TokenKind, user_id: DatabaseId, ttl_seconds: i64) -> Result<String, AppError> {
+        let token = generate_token();
+        let now = now();
+        self.store.replace_token(kind, user_id, &hash_token(&token), now + ttl_seconds, now).await?;
+        Ok(token)
+    }
+
+    /// Make a new password reset token for the user and return it. It expires after
+    /// `password_reset_token_ttl_seconds`.
+    pub async fn issue_password_reset_token(&self, user_id: DatabaseId) -> Result<String, AppError> {
+        self.issue_token(TokenKind::PasswordReset, user_id, self.config.password_reset_token_ttl_seconds).await
+    }
+
+    /// Return the id of the user a reset token belongs to, as long as it hasn't expired or been
+    /// used. This doesn't use the token up, it's just for checking a link before showing the form.
+    pub async fn verify_password_reset_token(&self, token: &str) -> Result<Option<DatabaseId>, AppError> {
+        self.store.token_user(TokenKind::PasswordReset, &hash_token(token), now()).await
+    }
+
+    /// Use up a reset token. If it was still good, this returns the id of the user it belongs to.
+    pub async fn consume_password_reset_token(&self, token: &str) -> Result<Option<DatabaseId>, AppError> {
+        self.store.consume_token(TokenKind::PasswordReset, &hash_token(token), now()).await
+    }
+
+    /// Make a new login link token for the user and return it. It expires after
+    /// `magic_link_token_ttl_seconds`.
+    pub async fn issue_magic_link_token(&self, user_id: DatabaseId) -> Result<String, AppError> {
+        self.issue_token(TokenKind::MagicLink, user_id, self.config.magic_link_token_ttl_seconds).await
+    }
+
+    /// Make a new email verification token for the user and return it. It expires after
+    /// `email_verification_token_ttl_seconds`.
+    pub async fn issue_email_verification_token(&self, user_id: DatabaseId) -> Result<String, AppError> {
+        self.issue_token(TokenKind::EmailVerification, user_id, self.config.email_verification_token_ttl_seconds).await
+    }
+
+    /// Use up a verification token and mark the user's email address as verified. Returns the
+    /// user's id, or `None` if the token was no good.
+    pub async fn verify_email(&self, token: &str) -> Result<Option<DatabaseId>, AppError> {
+        self.store.consume_email_verification(&hash_token(token), now()).await
+    }
+
+    /// Mark the user's email address as verified without a verification link. This is for
+    /// addresses that somebody we trust (like an OpenID Connect provider) has verified already.
+    pub async fn mark_email_verified(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        self.store.mark_email_verified(user_id, now()).await
+    }
+
+    /// The cipher for the TOTP secrets. It's an error to use two-factor auth without a key in the
+    /// server config.
+    fn totp_cipher(&self) -> Result<TotpCipher, AppError> {
+        let key = self.config.totp_encryption_key.as_ref().map(|key| key.expose().as_str())
+            .ok_or_else(|| AppError::InvalidData("Two-factor authentication isn't set up on this server".into()))?;
+        TotpCipher::from_hex_key(key)
+    }
+
+    /// True if the user has finished turning on TOTP, meaning a password alone won't log them in.
+    pub async fn totp_enabled(&self, user_id: DatabaseId) -> Result<bool, AppError> {
+        Ok(self.store.totp(user_id).await?.is_some_and(|row| row.enabled_at.is_some()))
+    }
+
+    /// Start turning on TOTP for the user: make a new secret and store it (encrypted), but don't
+    /// require it for logins yet. The returned `TOTP` is what the enrollment page turns into the
+    /// `otpauth://` URI and QR code. Starting over before confirming just replaces the secret.
+    pub async fn begin_totp_enrollment(&self, user: &User) -> Result<TOTP, AppError> {
+        if self.totp_enabled(user.id).await? {
+            return Err(AppError::InvalidData("Two-factor authentication is already turned on".into()));
+        }
+        let secret = new_secret();
+        let stored = self.totp_cipher()?.encrypt(&secret)?;
+        // The otpauth URI uses ':' as a separator, so it can't be in the account name.
+        let totp = totp_for(secret, &self.config.totp_issuer, &user.username.replace(':', "_"))?;
+        self.store.set_totp_secret(user.id, &stored).await?;
+        Ok(totp)
+    }
+
+    /// Finish turning on TOTP. The user has to send a working code, which proves their app got
+    /// the secret. If it works, TOTP is required from now on and the new recovery codes are
+    /// returned; this is the only time they can be seen. A wrong code gives `None`.
+    pub async fn confirm_totp_enrollment(&self, user_id: DatabaseId, code: &str) -> Result<Option<Vec<String>>, AppError> {
+        let Some(row) = self.store.totp(user_id).await? else {
+            return Ok(None);
+        };
+        if row.enabled_at.is_some() {
+            return Err(AppError::InvalidData("Two-factor authentication is already turned on".into()));
+        }
+        let totp = totp_for(self.totp_cipher()?.decrypt(&row.secret)?, &self.config.totp_issuer, "")?;
+        let now = now();
+        let Some(step) = matching_step(&totp, code, now) else {
+            return Ok(None);
+        };
+        let codes = new_recovery_codes();
+        let hashes = codes.iter().map(|c| hash_token(c)).collect::<Vec<_>>().join("\n");
+        self.store.enable_totp(user_id, now, step, &hashes).await?;
+        Ok(Some(codes))
+    }
+
+    /// Turn TOTP off again, recovery codes and all.
+    pub async fn disable_totp(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        self.store.delete_totp(user_id).await
+    }
+
+    /// Check a second factor for a user who already got their password right. `code` can be the
+    /// current TOTP code or one of the recovery codes. Either way it only works once: TOTP codes
+    /// are tied to their time step, which has to be later than the last one used, and recovery
+    /// codes are deleted when they're used.
+    ///
+    /// Six digit codes don't take long to guess, so wrong ones count toward a lockout just like
+    /// wrong passwords do. While the user is locked out, this returns `TooManyAttempts`.
+    pub async fn verify_second_factor(&self, user_id: DatabaseId, code: &str) -> Result<bool, AppError> {
+        let key = LoginThrottle::second_factor_key(user_id);
+        self.throttle.check(&[&key])?;
+        let ok = self.check_second_factor(user_id, code).await?;
+        if ok {
+            self.throttle.record_success(&[&key]);
+        } else {
+            self.throttle.record_failure(&[&key]);
+        }
+        Ok(ok)
+    }
+
+    /// The actual checking for `verify_second_factor`, without the throttling.
+    async fn check_second_factor(&self, user_id: DatabaseId, code: &str) -> Result<bool, AppError> {
+        let Some(row) = self.store.totp(user_id).await?.filter(|row| row.enabled_at.is_some()) else {
+            return Ok(false);
+        };
+        let totp = totp_for(self.totp_cipher()?.decrypt(&row.secret)?, &self.config.totp_issuer, "")?;
+        if let Some(step) = matching_step(&totp, code, now()) {
+            return self.store.use_totp_step(user_id, step).await;
+        }
+        let code_hash = hash_token(&code.trim().to_lowercase());
+        if row.recovery_codes.lines().any(|hash| hash == code_hash) {
+            let remaining = row.recovery_codes.lines()
+                .filter(|hash| *hash != code_hash)
+                .collect::<Vec<_>>()
+                .join("\n");
+            return self.store.replace_recovery_codes(user_id, &row.recovery_codes, &remaining).await;
+        }
+        Ok(false)
+    }
+
+    /// Connect an account at an OAuth provider to a local user, so that logging in with that
+    /// provider logs in as this user from now on. Fails if the provider account is already linked
+    /// to somebody.
+    pub async fn link_oauth_account(&self, user_id: DatabaseId, provider: &str, subject: &str, email: Option<String>) -> Result<(), AppError> {
+        self.store.link_oauth_account(user_id, provider, subject, email, now()).await
+    }
+
+    /// The WebAuthn user handle for this user. Every passkey a user has shares one handle, so
+    /// this is the handle from their existing passkeys, or a new random one for their first.
+    pub async fn passkey_user_handle(&self, user_id: DatabaseId) -> Result<Uuid, AppError> {
+        Ok(self.store.passkey_user_handle(user_id).await?.unwrap_or_else(Uuid::new_v4))
+    }
+
+    /// All of the user's passkeys, oldest first.
+    pub async fn list_passkeys(&self, user_id: DatabaseId) -> Result<Vec<StoredPasskey>, AppError> {
+        self.store.list_passkeys(user_id).await
+    }
+
+    /// Save a newly registered passkey.
+    pub async fn add_passkey(&self, user_id: DatabaseId, user_handle: Uuid, name: &str, passkey: &Passkey) -> Result<(), AppError> {
+        self.store.insert_passkey(user_id, user_handle, name, passkey, now()).await
+    }
+
+    /// Remove one of the user's passkeys. Returns false if they don't have one with that id.
+    pub async fn delete_passkey(&self, user_id: DatabaseId, passkey_id: i64) -> Result<bool, AppError> {
+        self.store.delete_passkey(user_id, passkey_id).await
+    }
+
+    /// Return the names of all of the roles that have been given to this user.
+    pub async fn get_user_roles(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        self.store.user_roles(user_id).await
+    }
+
+    /// Give a role to a user. The role has to exist already in the `roles` table. Granting a role
+    /// that the user already has is not an error.
+    pub async fn grant_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        self.store.grant_role(user_id, role).await
+    }
+
+    /// Take a role away from a user. Nothing happens if they didn't have it.
+    pub async fn revoke_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        self.store.revoke_role(user_id, role).await
+    }
+}
+
+// The admin console (pages/admin) uses these. They don't check who's asking; the server functions
+// there do that before calling in.
+impl<S: Store> AuthBackend<S> {
+    /// Page through the users whose username or email address contains `query`. Returns the page
+    /// and the total number of matches.
+    pub async fn search_users(&self, query: &str, offset: i64, limit: i64) -> Result<(Vec<SqlUser>, i64), AppError> {
+        self.store.search_users(query.trim(), offset, limit).await
+    }
+
+    /// Disable or enable a user. A disabled user can't log in any way at all, and disabling also
+    /// ends every session they have.
+    pub async fn set_disabled(&self, user_id: DatabaseId, disabled: bool) -> Result<(), AppError> {
+        if !self.store.set_disabled(user_id, disabled.then(now)).await? {
+            return Err(AppError::NotFound);
+        }
+        if disabled {
+            self.rotate_session_secret(user_id).await?;
+        }
+        Ok(())
+    }
+
+    /// True if an admin has disabled the user
+    pub async fn is_disabled(&self, user_id: DatabaseId) -> Result<bool, AppError> {
+        Ok(self.store.find_by_id(user_id).await?.is_some_and(|user| user.disabled_at.is_some()))
+    }
+
+    /// Make a user pick a new password. Their current one stops working (it's replaced with a
+    /// random one nobody knows), every session they have ends, and a fresh reset token comes back
+    /// to be mailed to them.
+    pub async fn force_password_reset(&self, user_id: DatabaseId) -> Result<String, AppError> {
+        let pass_hash = self.passwords.hash(&generate_token())?;
+        if !self.store.update_password(user_id, &pass_hash).await? {
+            return Err(AppError::NotFound);
+        }
+        self.rotate_session_secret(user_id).await?;
+        self.issue_password_reset_token(user_id).await
+    }
+
+    /// Write down something an admin did. `target` is the user it was done to, if any.
+    pub async fn audit(&self, actor: &User, action: &str, target: Option<(DatabaseId, &str)>, detail: Option<String>) -> Result<(), AppError> {
+        self.store.insert_audit(NewAuditEntry {
+            created_at: now(),
+            actor_id: actor.id,
+            actor_name: actor.username.clone(),
+            action: action.to_string(),
+            target_id: target.map(|(id, _)| id),
+            target_name: target.map(|(_, name)| name.to_string()),
+            detail,
+        }).await
+    }
+
+    /// The admin audit log, newest first, and how many entries there are in all.
+    pub async fn list_audit(&self, offset: i64, limit: i64) -> Result<(Vec<AuditEntry>, i64), AppError> {
+        self.store.list_audit(offset, limit).await
+    }
+}
+
+// The authentication event log, see auth_events.rs.
+impl<S: Store> AuthBackend<S> {
+    /// Write an event into the log. `username` is who it's about, or for a failed login, whatever
+    /// was typed in, if anything was. `client` is None when there's no request to take it from.
+    ///
+    /// This doesn't return an error, because not being able to write the log shouldn't stop
+    /// anybody from logging in or out. It shows up in the server log instead.
+    pub async fn record_event(&self, kind: AuthEventKind, user_id: Option<DatabaseId>, username: Option<&str>,
+        client: Option<&ClientInfo>, detail: Option<&str>)
+    {
+        let recorded = self.store.insert_auth_event(NewAuthEvent {
+            created_at: now(),
+            kind: kind.as_str().to_string(),
+            user_id,
+            username: username.map(String::from),
+            ip: client.map(|c| c.ip.to_string()),
+            user_agent: client.and_then(|c| c.user_agent.clone()),
+            detail: detail.map(String::from),
+        }).await;
+        if let Err(e) = recorded {
+            warn!(kind = kind.as_str(), ?user_id, ?username, error = %e, "Couldn't record an authentication event");
+        }
+    }
+
+    /// Page through the event log, newest first. Returns the page and the total number of matches.
+    pub async fn list_auth_events(&self, filter: &AuthEventFilter, offset: i64, limit: i64) -> Result<(Vec<AuthEvent>, i64), AppError> {
+        self.store.list_auth_events(filter, offset, limit).await
+    }
+
+    /// Delete the events older than `auth_event_retention_days`, unless that's 0. Returns how many
+    /// went. main.rs calls this once an hour.
+    pub async fn prune_auth_events(&self) -> Result<u64, AppError> {
+        let days = self.config.auth_event_retention_days;
+        if days <= 0 {
+            return Ok(0);
+        }
+        self.store.prune_auth_events(now() - days * 24 * 60 * 60).await
+    }
+}
+
+/// How long after a "remember me" token is rotated the validator before it still works. Requests
+/// that left the browser before the new cookie came back carry the old one, and they shouldn't be
+/// taken for a stolen cookie.
+const REMEMBER_TOKEN_GRACE_SECONDS: i64 = 30;
+
+/// What came of a "remember me" cookie, see `use_remember_token`.
+#[derive(Clone,Debug)]
+pub enum RememberedLogin {
+    /// It checked out. `cookie` is what to replace the cookie with, or None if it stays the same
+    /// (because another request with the same cookie is already replacing it).
+    Valid { user: User, cookie: Option<String> },
+    /// Unknown, expired, garbled, or for a user who can't log in anymore. The browser can forget it.
+    Invalid,
+    /// A validator that had already been replaced. Somebody has a copy of the cookie, so all of the
+    /// user's sessions and remembered logins have been thrown out.
+    Reused { user_id: DatabaseId },
+}
+
+// "Remember me" tokens. The cookie is `selector:validator`. The selector finds the row and never
+// changes; the validator is only stored hashed, and is replaced every time the cookie logs the
+// browser back in. Whoever presents the current validator gets in. A stolen cookie and the real
+// one can't both stay current, so once one of them has been used, the other one shows up with an
+// old validator, and that's taken as theft.
+impl<S: Store> AuthBackend<S> {
+    /// Make a token for the user that lasts `remember_me_ttl_seconds`, and return the cookie
+    /// value for it. This is the only time the validator is visible.
+    pub async fn issue_remember_token(&self, user_id: DatabaseId) -> Result<String, AppError> {
+        let selector = generate_token();
+        let validator = generate_token();
+        let now = now();
+        self.store.insert_remember_token(NewRememberToken {
+            user_id,
+            selector: selector.clone(),
+            validator_hash: hash_token(&validator),
+            created_at: now,
+            expires_at: now + self.config.remember_me_ttl_seconds,
+        }, now).await?;
+        Ok(format!("{selector}:{validator}"))
+    }
+
+    /// Check a cookie value from `issue_remember_token` (or an earlier call to this), and rotate
+    /// its validator.
+    pub async fn use_remember_token(&self, cookie: &str) -> Result<RememberedLogin, AppError> {
+        let Some((selector, validator)) = cookie.split_once(':') else {
+            return Ok(RememberedLogin::Invalid);
+        };
+        let now = now();
+        let Some(token) = self.store.find_remember_token(selector, now).await? else {
+            return Ok(RememberedLogin::Invalid);
+        };
+        let hash = hash_token(validator);
+        let cookie = if hash == token.validator_hash {
+            let new_validator = generate_token();
+            // If this doesn't work, another request with the same cookie got there first, and
+            // its answer carries the new cookie.
+            self.store.rotate_remember_token(selector, &hash, &hash_token(&new_validator), now).await?
+                .then(|| format!("{selector}:{new_validator}"))
+        } else if token.previous_validator_hash.as_deref() == Some(hash.as_str())
+            && token.rotated_at.is_some_and(|at| now - at <= REMEMBER_TOKEN_GRACE_SECONDS)
+        {
+            None
+        } else {
+            self.rotate_session_secret(token.user_id).await?;
+            return Ok(RememberedLogin::Reused{ user_id: token.user_id });
+        };
+        // `get_user` leaves out disabled users.
+        match self.get_user(&token.user_id).await? {
+            Some(user) => Ok(RememberedLogin::Valid{ user, cookie }),
+            None => {
+                self.store.delete_remember_token(selector).await?;
+                Ok(RememberedLogin::Invalid)
+            },
+        }
+    }
+
+    /// Throw away the token behind a cookie value, like when its browser logs out.
+    pub async fn forget_remember_token(&self, cookie: &str) -> Result<(), AppError> {
+        match cookie.split_once(':') {
+            Some((selector, _)) => self.store.delete_remember_token(selector).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: Store> AuthBackend<S> {
+    /// Check a username and password. This looks up the user by name, then checks the given
+    /// password against the salted hash in the database to see if it matches.
+    async fn authenticate_password(&self, username: String, password: String) -> Result<Option<User>, AppError> {
+        // Refuse to even look at the password while the username is locked out, or the lockout
+        // would do nothing to slow down guessing.
+        let user_key = LoginThrottle::user_key(&username);
+        self.throttle.check(&[&user_key])?;
+        if let Some(user) = self.store.find_by_username(&username).await? {
+            if self.passwords.verify(&password, &user.pass_hash)? {
+                // The password is right, but the switch in the config says that isn't enough.
+                if self.config.require_verified_email && user.verified_at.is_none() {
+                    return Err(AppError::EmailNotVerified);
+                }
+                self.throttle.record_success(&[&user_key]);
+                self.upgrade_password_hash(&user, &password).await;
+                return Ok(Some(user.to_user()?))
+            }
+        } else {
+            self.passwords.verify_nobody(&password);
+        }
+        // Count failures for usernames that don't exist too, so the lockout doesn't reveal which
+        // ones do.
+        self.throttle.record_failure(&[&user_key]);
+        Ok(None)
+    }
+
+    /// Redo the user's password hash if it's weaker than what the config asks for now. This is
+    /// the only time the plaintext password is around to do it with. It's `update_password`
+    /// rather than `set_password` because it's still the same password, so nobody's sessions
+    /// should end. A failure here only gets logged; the login itself already worked.
+    async fn upgrade_password_hash(&self, user: &SqlUser, password: &str) {
+        if !self.passwords.needs_rehash(&user.pass_hash) {
+            return;
+        }
+        let result = match self.passwords.hash(password) {
+            Ok(pass_hash) => self.store.update_password(user.id, &pass_hash).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => info!(user_id = user.id, username = %user.username, "Upgraded a password hash"),
+            Err(e) => warn!(user_id = user.id, username = %user.username, error = %e, "Couldn't upgrade a password hash"),
+        }
+    }
+
+    /// Use up a login link token and return the user it was for. A link only ever works once. The
+    /// link went to the user's email address, so following it proves that the address works;
+    /// that counts as verifying it.
+    async fn authenticate_magic_link(&self, token: String) -> Result<Option<User>, AppError> {
+        let Some(user_id) = self.store.consume_token(TokenKind::MagicLink, &hash_token(&token), now()).await? else {
+            return Ok(None);
+        };
+        let Some(user) = self.find_user_by_id(user_id).await? else {
+            return Ok(None);
+        };
+        if user.email.is_some() && !user.email_verified {
+            self.mark_email_verified(user_id).await?;
+            return self.find_user_by_id(user_id).await;
+        }
+        if self.config.require_verified_email && !user.email_verified {
+            return Err(AppError::EmailNotVerified);
+        }
+        Ok(Some(user))
+    }
+
+    /// Check a passkey login. The response says which credential it came from; that passkey has
+    /// to belong to the user handle in the response, and its signature has to check out. The
+    /// stored passkey gets its signature counter updated afterwards, which is how cloned
+    /// authenticators get noticed.
+    async fn authenticate_passkey(&self, response: PublicKeyCredential, state: DiscoverableAuthentication) -> Result<Option<User>, AppError> {
+        let (handle, credential_id) = self.passkeys.identify(&response)?;
+        let Some((user_id, stored)) = self.store.find_passkey(&to_hex(&credential_id), handle).await? else {
+            return Ok(None);
+        };
+        let mut passkey = stored.passkey;
+        let Ok(result) = self.passkeys.finish_login(&response, state, &passkey) else {
+            return Ok(None);
+        };
+        passkey.update_credential(&result);
+        self.store.update_passkey(stored.id, &passkey, now()).await?;
+        let Some(user) = self.find_user_by_id(user_id).await? else {
+            return Ok(None);
+        };
+        if self.config.require_verified_email && !user.email_verified {
+            return Err(AppError::EmailNotVerified);
+        }
+        Ok(Some(user))
+    }
+
+    /// Find the user linked to an account at an OAuth provider. The provider has already done the
+    /// checking (see oauth.rs), so there's no secret to compare here.
+    async fn authenticate_oauth(&self, provider: String, subject: String) -> Result<Option<User>, AppError> {
+        let Some(user) = self.store.find_by_oauth(&provider, &subject).await? else {
+            return Ok(None);
+        };
+        if self.config.require_verified_email && user.verified_at.is_none() {
+            return Err(AppError::EmailNotVerified);
+        }
+        Ok(Some(user.to_user()?))
+    }
+}
+
+/// The `AuthnBackend` is the part that handles autheNtication (proving that a user's identity is
+/// valid). The `AuthzBackend` handles authoriZation (permissions granted to a user whose identity
+/// is already known), and it's implemented further down in this file.
+impl<S: Store> AuthnBackend for AuthBackend<S> {
+    type User = crate::user::User;
+    type Credentials = Credentials;
+    type Error = crate::error_template::AppError;
+
+    /// `authenticate` figures out what kind of credentials it was given and passes them on to the
+    /// method that checks that kind. If they check out, you get the user back. If not, you get
+    /// Ok(None). An Err value means something went wrong with the process, not that the
+    /// authentication failed.
+    async fn authenticate(&self, credentials: Self::Credentials)
+    -> Result<Option<Self::User>,Self::Error> {
+        let user = match credentials {
+            Credentials::Password{username, password} => self.authenticate_password(username, password).await?,
+            Credentials::OAuth{provider, subject} => self.authenticate_oauth(provider, subject).await?,
+            Credentials::MagicLink(token) => self.authenticate_magic_link(token).await?,
+            Credentials::Passkey{response, state} => self.authenticate_passkey(response, state).await?,
+            // Nothing hands these out yet.
+            Credentials::ApiToken(_) =>
+                return Err(AppError::InvalidData("That kind of login isn't available here".into())),
+        };
+        // This comes after the credentials are checked, whatever kind they were, so that only
+        // somebody who could otherwise log in finds out the account is disabled.
+        if let Some(user) = &user {
+            if self.is_disabled(user.id).await? {
+                return Err(AppError::AccountDisabled);
+            }
+        }
+        Ok(user)
+    }
+
+    /// Return Some(user) if the user exists, otherwise return None. Only return an Err value if
+    /// something actually goes wrong in the process.
+    ///
+    /// A disabled user counts as not existing here, so sessions they already had stop working
+    /// even if they somehow survived the session secret being changed.
+    async fn get_user(&self, user_id: &UserId<Self>)
+    -> Result<Option<Self::User>,Self::Error> {
+        match self.store.find_by_id(*user_id).await? {
+            Some(user) if user.disabled_at.is_none() => Ok(Some(user.to_user()?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Permissions are just their names from the `permissions` table. Users don't get permissions
+/// directly, they get roles (`user_roles`) and the roles carry the permissions
+/// (`role_permissions`). That's why only `get_group_permissions` is implemented here; the
+/// default `get_user_permissions` returns an empty set, and `has_perm` combines the two.
+impl<S: Store> AuthzBackend for AuthBackend<S> {
+    type Permission = String;
+
+    async fn get_group_permissions(&self, user: &Self::User)
+    -> Result<HashSet<Self::Permission>,Self::Error> {
+        Ok(self.store.user_permissions(user.id).await?.into_iter().collect())
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+use serde::{Deserialize,Serialize};
+
+// The authentication event log: every login (and failed one), registration, logout, password
+// change and lockout goes into the `auth_events` table, with when it happened, who it was about
+// and where the request came from. Admins can browse it at /admin/auth-events, and users get their
+// own in the account download. Old events are deleted after `auth_event_retention_days`.
+//
+// The kinds are here rather than with the store so that the admin page can offer them as filters.
+
+/// What happened.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Serialize,Deserialize)]
+pub enum AuthEventKind {
+    LoginSucceeded,
+    /// Wrong password or code, or an account that isn't allowed to log in
+    LoginFailed,
+    /// A login was refused without even checking, because of too many failures before it
+    LockedOut,
+    Registered,
+    LoggedOut,
+    /// Changed from the account page, or with a reset link
+    PasswordChanged,
+    /// A "remember me" cookie showed up with a validator that had already been replaced, which
+    /// means somebody else has a copy of it. All of the user's sessions and remembered logins get
+    /// thrown out when this happens.
+    RememberTokenReused,
+}
+
+impl AuthEventKind {
+    pub const ALL: [AuthEventKind; 7] = [
+        AuthEventKind::LoginSucceeded,
+        AuthEventKind::LoginFailed,
+        AuthEventKind::LockedOut,
+        AuthEventKind::Registered,
+        AuthEventKind::LoggedOut,
+        AuthEventKind::PasswordChanged,
+        AuthEventKind::RememberTokenReused,
+    ];
+
+    /// What goes in the `kind` column. Don't change these, the table is full of them.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuthEventKind::LoginSucceeded => "login_succeeded",
+            AuthEventKind::LoginFailed => "login_failed",
+            AuthEventKind::LockedOut => "locked_out",
+            AuthEventKind::Registered => "registered",
+            AuthEventKind::LoggedOut => "logged_out",
+            AuthEventKind::PasswordChanged => "password_changed",
+            AuthEventKind::RememberTokenReused => "remember_token_reused",
+        }
+    }
+
+    /// The other way around from `as_str`. None for anything it doesn't know.
+    pub fn parse(kind: &str) -> Option<AuthEventKind> {
+        AuthEventKind::ALL.into_iter().find(|k| k.as_str() == kind)
+    }
+}
+
+/// For people: "Logged in", "Login failed" and so on.
+impl fmt::Display for AuthEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AuthEventKind::LoginSucceeded => "Logged in",
+            AuthEventKind::LoginFailed => "Login failed",
+            AuthEventKind::LockedOut => "Locked out",
+            AuthEventKind::Registered => "Registered",
+            AuthEventKind::LoggedOut => "Logged out",
+            AuthEventKind::PasswordChanged => "Changed password",
+            AuthEventKind::RememberTokenReused => "Stolen remember-me cookie",
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use cfg_if::cfg_if;
+use sqlx::pool::PoolOptions;
+use crate::config::ServerConfig;
+use crate::error_template::AppError;
+
+// Which database the server runs on is picked at compile time with the `sqlite` or `postgres`
+// feature. Everything else in the app uses the names in here (`AppBackend`, `DbPool`,
+// `SessionStore`) instead of the concrete types, so it doesn't care which one it got. The backend
+// itself is the same either way, only the store under it changes (see store.rs).
+
+#[cfg(all(feature="sqlite", feature="postgres"))]
+compile_error!("Turn on only one of the `sqlite` and `postgres` features");
+#[cfg(not(any(feature="sqlite", feature="postgres")))]
+compile_error!("The server needs a database: turn on the `sqlite` or the `postgres` feature");
+
+cfg_if!{
+    if #[cfg(feature="postgres")] {
+        /// The database the app was built for
+        pub type Db = sqlx::Postgres;
+        /// Where the users and everything about them are kept
+        pub type AppStore = crate::postgres_store::PostgresUserStore;
+        /// Where tower-sessions keeps the sessions. It lives in the same database as the users.
+        pub type SessionStore = tower_sessions_sqlx_store::PostgresStore;
+        /// The URL schemes `database_connect` will take
+        const URL_SCHEMES: &[&str] = &["postgres://", "postgresql://"];
+    } else {
+        pub type Db = sqlx::Sqlite;
+        pub type AppStore = crate::sqlite_store::SqliteUserStore;
+        pub type SessionStore = tower_sessions_sqlx_store::SqliteStore;
+        const URL_SCHEMES: &[&str] = &["sqlite:"];
+    }
+}
+
+/// The authentication backend the app was built for
+pub type AppBackend = crate::auth_backend::AuthBackend<AppStore>;
+
+/// A pool of connections to whichever database the app was built for.
+pub type DbPool = sqlx::Pool<Db>;
+
+/// This is where the connection pool to the database gets built. The URL comes from
+/// `database_url` in the config, or for SQLite it can be made from `database_file`. A URL for the
+/// wrong kind of database is refused here, with a message that says so, rather than failing in
+/// some confusing way further along.
+pub async fn database_connect(config: &ServerConfig) -> Result<DbPool, AppError> {
+    let db_url = config.connection_url();
+    if !URL_SCHEMES.iter().any(|scheme| db_url.starts_with(scheme)) {
+        return Err(AppError::DatabaseError(format!(
+            "This server was built for URLs starting with {}, check database_url in the config", URL_SCHEMES.join(" or ")
+        )));
+    }
+    std::env::set_var("DATABASE_URL", db_url.clone());
+
+    PoolOptions::<Db>::new()
+        .max_connections(5)
+        .connect(&db_url).await
+        .map_err(|e| AppError::DatabaseError(format!("{e}")))
+}
+
+/// Make the session store, using the table name from the config. Call `migrate` on it before
+/// handing it to the session layer.
+pub fn session_store(pool: DbPool, config: &ServerConfig) -> Result<SessionStore, AppError> {
+    SessionStore::new(pool)
+        .with_table_name(config.session_table_name.clone())
+        .map_err(|e| AppError::InternalError(format!("Session store: {e}")))
+}
@@ -0,0 +1,79 @@
+use leptos::prelude::*;
+use leptos::either::Either;
+use leptos_router::hooks::use_navigate;
+use crate::server::{has_permission, LogoutUser, SignOutEverywhere};
+
+/// Only render `children` if the logged-in user has `permission`. Otherwise, render `fallback`
+/// (which is nothing if you don't give one). This is just for showing and hiding things in the
+/// UI, it doesn't protect anything by itself: the server functions behind the children still have
+/// to call `server::ensure_permission`, because anybody can call those directly.
+#[component]
+pub fn RequirePermission(
+    /// The permission name, as it appears in the `permissions` table
+    #[prop(into)]
+    permission: String,
+    /// What to show instead of the children if the user isn't allowed to see them
+    #[prop(optional, into)]
+    fallback: ViewFn,
+    children: ChildrenFn,
+) -> impl IntoView {
+    let allowed = Resource::new(move || permission.clone(), has_permission);
+    view! {
+        <Suspense fallback=|| ()>
+            {move || {
+                let children = children.clone();
+                let fallback = fallback.clone();
+                Suspend::new(async move {
+                    match allowed.await {
+                        Ok(true) => Either::Left(children()),
+                        _ => Either::Right(fallback.run()),
+                    }
+                })
+            }}
+        </Suspense>
+    }
+}
+
+/// A button that logs the current user out (see `server::logout_user`) and then sends them to the
+/// login page.
+#[component]
+pub fn LogoutButton() -> impl IntoView {
+    let logout:ServerAction<LogoutUser> = ServerAction::new();
+    let nav = use_navigate();
+    Effect::new(move || {
+        if let Some(Ok(())) = logout.value().get() {
+            nav("/login", Default::default());
+        }
+    });
+    view! {
+        <ActionForm action=logout>
+            <input
+                type="submit"
+                class="rounded-md bg-gray-200 px-3 py-1.5 text-sm font-semibold text-gray-900 shadow-sm hover:bg-gray-300 cursor-pointer"
+                value="Log out"
+            />
+        </ActionForm>
+    }
+}
+
+/// Like `LogoutButton`, but logs the user out on every device they're logged in on (see
+/// `server::sign_out_everywhere`).
+#[component]
+pub fn SignOutEverywhereButton() -> impl IntoView {
+    let sign_out:ServerAction<SignOutEverywhere> = ServerAction::new();
+    let nav = use_navigate();
+    Effect::new(move || {
+        if let Some(Ok(())) = sign_out.value().get() {
+            nav("/login", Default::default());
+        }
+    });
+    view! {
+        <ActionForm action=sign_out>
+            <input
+                type="submit"
+                class="rounded-md bg-gray-200 px-3 py-1.5 text-sm font-semibold text-gray-900 shadow-sm hover:bg-gray-300 cursor-pointer"
+                value="Sign out of all devices"
+            />
+        </ActionForm>
+    }
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+use webauthn_rs::prelude::{DiscoverableAuthentication, PublicKeyCredential};
+
+/// Everything that somebody can hand to `AppBackend::authenticate` to prove who they are. Each
+/// way of logging in gets its own variant, and `authenticate` hands it off to the code that knows
+/// how to check it. Adding a new login method means adding a variant here and a branch there,
+/// without touching the `AuthnBackend` impl's signature or any of the existing callers.
+#[derive(Clone)]
+pub enum Credentials {
+    /// The classic username and password from the login form
+    Password { username: String, password: String },
+    /// A long-lived token handed out for scripts and other programs
+    ApiToken(String),
+    /// The token from a login link that was mailed to the user
+    MagicLink(String),
+    /// An identity that an OAuth2/OpenID Connect provider has already vouched for. By the time one
+    /// of these gets built, the provider's response has been checked; all that's left is to find
+    /// the local account linked to it.
+    OAuth { provider: String, subject: String },
+    /// The browser's answer to a passkey login challenge, along with the challenge state that was
+    /// kept in the session when it was sent
+    Passkey { response: PublicKeyCredential, state: DiscoverableAuthentication },
+}
+
+impl Credentials {
+    /// Shorthand for the most common case.
+    pub fn password(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials::Password { username: username.into(), password: password.into() }
+    }
+}
+
+/// Written by hand so that passwords and tokens can't end up in a log by way of `{:?}`.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Password { username, .. } => f.debug_struct("Password")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Credentials::ApiToken(_) => f.debug_tuple("ApiToken").field(&"<redacted>").finish(),
+            Credentials::MagicLink(_) => f.debug_tuple("MagicLink").field(&"<redacted>").finish(),
+            Credentials::OAuth { provider, subject } => f.debug_struct("OAuth")
+                .field("provider", provider)
+                .field("subject", subject)
+                .finish(),
+            Credentials::Passkey { .. } => f.debug_struct("Passkey").finish_non_exhaustive(),
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use http::status::StatusCode;
 use leptos::prelude::*;
+use leptos::server_fn::codec::JsonEncoding;
+use leptos::server_fn::error::{FromServerFnError, ServerFnErrorErr};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use crate::password_policy::PolicyViolation;
 
-#[derive(Clone, Debug, Error)]
+#[derive(Clone, Debug, Error, Serialize, Deserialize)]
 pub enum AppError {
     #[error("Not Found")]
     NotFound,
@@ -14,6 +18,33 @@ pub enum AppError {
     DatabaseError(String),
     #[error("Invalid data provided: {0}")]
     InvalidData(String),
+    #[error("Not logged in")]
+    Unauthorized,
+    #[error("Missing permission: {0}")]
+    Forbidden(String),
+    #[error("Please verify your email address before logging in")]
+    EmailNotVerified,
+    #[error("This account has been disabled")]
+    AccountDisabled,
+    #[error("Too many attempts, try again in {0} seconds")]
+    TooManyAttempts(i64),
+    #[error("That password isn't allowed: {}", list_violations(.0))]
+    WeakPassword(Vec<PolicyViolation>),
+}
+
+fn list_violations(violations: &[PolicyViolation]) -> String {
+    violations.iter().map(ToString::to_string).collect::<Vec<_>>().join(". ")
+}
+
+/// Server functions usually return `ServerFnError`, which turns every error into a string on the
+/// way to the browser. The ones that return `AppError` instead get the whole thing, so the page can
+/// look at the variant (the Register page lists each `WeakPassword` violation, for example).
+impl FromServerFnError for AppError {
+    type Encoder = JsonEncoding;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        AppError::InternalError(value.to_string())
+    }
 }
 
 impl AppError {
@@ -24,6 +55,12 @@ impl AppError {
             AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::InvalidData(_) => StatusCode::NOT_ACCEPTABLE,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::EmailNotVerified => StatusCode::FORBIDDEN,
+            AppError::AccountDisabled => StatusCode::FORBIDDEN,
+            AppError::TooManyAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::WeakPassword(_) => StatusCode::NOT_ACCEPTABLE,
         }
     }
 }
@@ -50,7 +87,7 @@ pub fn ErrorTemplate(
         .into_iter()
         .filter_map(|(_k, v)| v.downcast_ref::<AppError>().cloned())
         .collect();
-    println!("Errors: {errors:#?}");
+    tracing::debug!(?errors, "Rendering errors");
 
     // Only the response code for the first error is actually sent from the server
     // this may be customized by the specific application
@@ -52,7 +52,7 @@ async fn get_static_file(
     uri: Uri,
     root: &str,
 ) -> Result<Response<Body>, (StatusCode, String)> {
-    use leptos::logging::log;
+    use tracing::trace;
     if uri.path().ends_with(".webmanifest") { // special case, send the web app manifest
         let resp = Response::builder()
             .status(200)
@@ -60,7 +60,7 @@ async fn get_static_file(
             .body(Body::from(MANIFEST));
         return Ok(resp.unwrap());
     }
-    log!("Requesting static file: {uri}");
+    trace!(%uri, "Requesting static file");
     let req = Request::builder()
         .uri(uri.clone())
         .body(Body::empty())
@@ -69,7 +69,7 @@ async fn get_static_file(
     // This path is relative to the cargo root
     match ServeDir::new(root).oneshot(req).await {
         Ok(res) => {
-            log!("response status: {}",res.status());
+            trace!(status = %res.status(), "Static file response");
             Ok(res.into_response())
         },
     }
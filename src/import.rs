@@ -0,0 +1,123 @@
+use std::path::Path;
+use std::str::FromStr;
+use serde::Deserialize;
+use crate::error_template::AppError;
+use crate::passwords::HashKind;
+use crate::secret::Secret;
+use crate::store::{NewUser, Store};
+use crate::tokens::{generate_token, now};
+
+// Bulk loading of users from another system, for `import-users` on the server's command line (see
+// main.rs). The password hashes are taken as they are: anything `passwords::HashKind` knows
+// (argon2, scrypt, PBKDF2 or bcrypt) works for logging in, and gets upgraded to Argon2id the first
+// time each user logs in.
+
+/// One user from a dump. In a CSV file these are the column names, in the header row; in JSON,
+/// the dump is an array of objects with these fields.
+#[derive(Clone,Debug,Deserialize)]
+pub struct ImportedUser {
+    pub username: String,
+    /// The hash from the old system, in PHC format (`$pbkdf2-sha256$...`, `$scrypt$...`, ...) or
+    /// bcrypt's `$2b$...`
+    pub pass_hash: Secret<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Set this if the old system had already checked the email address. It's an Option so a
+    /// CSV file can leave it empty.
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+}
+
+/// What the dump file looks like.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+impl ImportFormat {
+    /// Guess from the file extension.
+    pub fn from_path(path: &Path) -> Option<ImportFormat> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ImportFormat::Csv),
+            "json" => Ok(ImportFormat::Json),
+            other => Err(format!("Unknown import format {other}, use csv or json")),
+        }
+    }
+}
+
+/// How an import went.
+#[derive(Clone,Debug,Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Usernames that weren't imported, and why
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Read all the users out of a dump file.
+pub fn read_users(path: &Path, format: ImportFormat) -> Result<Vec<ImportedUser>, AppError> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| AppError::InternalError(format!("Open {}: {e}", path.display())))?;
+    match format {
+        ImportFormat::Csv => csv::Reader::from_reader(file)
+            .deserialize()
+            .collect::<Result<Vec<ImportedUser>, _>>()
+            .map_err(|e| AppError::InvalidData(format!("Read CSV: {e}"))),
+        ImportFormat::Json => serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| AppError::InvalidData(format!("Read JSON: {e}"))),
+    }
+}
+
+/// Why a user can't be imported, or None if they look fine. These are the same rules that
+/// `AuthBackend::add_user` applies, except for the password, which we can't see.
+fn check(user: &ImportedUser) -> Option<String> {
+    if user.username.len() < 2 {
+        return Some("username is shorter than 2 characters".into());
+    }
+    if HashKind::of(user.pass_hash.expose()).is_none() {
+        return Some("password hash is in a format we can't check".into());
+    }
+    if user.email.as_ref().is_some_and(|e| !e.contains('@')) {
+        return Some("email address doesn't look like one".into());
+    }
+    None
+}
+
+/// Add the users to the store. Users that break the rules, or whose username or email address is
+/// taken already, are skipped and listed in the report; everybody else gets in. Only a problem with
+/// the store itself stops the import partway.
+pub async fn import_users<S: Store>(store: &S, users: Vec<ImportedUser>) -> Result<ImportReport, AppError> {
+    let mut report = ImportReport::default();
+    for user in users {
+        if let Some(reason) = check(&user) {
+            report.skipped.push((user.username, reason));
+            continue;
+        }
+        let new_user = NewUser {
+            username: user.username.clone(),
+            pass_hash: user.pass_hash,
+            email: user.email.filter(|e| !e.is_empty()),
+            session_secret: Secret::new(generate_token()),
+        };
+        let id = match store.insert(new_user).await {
+            Ok(id) => id,
+            Err(AppError::InvalidData(reason)) => {
+                report.skipped.push((user.username, reason));
+                continue;
+            },
+            Err(e) => return Err(e),
+        };
+        if user.email_verified == Some(true) {
+            store.mark_email_verified(id, now()).await?;
+        }
+        report.imported += 1;
+    }
+    Ok(report)
+}
@@ -5,18 +5,43 @@
 
 pub mod app;
 pub mod auth;
+pub mod auth_events;
+pub mod components;
 pub mod user;
 pub mod error_template;
 pub mod state;
 pub mod config;
+pub mod password_policy;
+pub mod strength;
 pub mod pages;
 pub mod prelude;
+pub mod secret;
 pub mod server;
 
 cfg_if::cfg_if! {
     if #[cfg(feature="ssr")] {
+        pub mod account_data;
+        pub mod auth_backend;
+        pub mod backend;
+        pub mod credentials;
         pub mod fallback;
-        pub mod sqlite_backend;
+        pub mod import;
+        pub mod logging;
+        pub mod mail;
+        pub mod memory_store;
+        pub mod mock_oidc;
+        pub mod oauth;
+        pub mod passkeys;
+        pub mod passwords;
+        pub mod remember_me;
+        #[cfg(feature="postgres")]
+        pub mod postgres_store;
+        #[cfg(feature="sqlite")]
+        pub mod sqlite_store;
+        pub mod store;
+        pub mod throttle;
+        pub mod tokens;
+        pub mod totp;
     }
 }
 
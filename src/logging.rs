@@ -0,0 +1,28 @@
+use tracing_subscriber::{fmt, EnvFilter};
+use crate::config::{LogFormat, LoggingConfig};
+
+// Everything the server logs goes through `tracing`. Events carry their details as fields
+// (`user_id`, `username`, `ip` and so on) rather than baked into the message, so the JSON format
+// hands a log collector something it can search. Requests to server functions get a span (see
+// `server_func_handler` in main.rs), so every event from inside one says which function it was and
+// who asked.
+//
+// Passwords, hashes, tokens and keys never go into an event. Anything that holds one is a
+// `Secret` (see secret.rs) or has a `Debug` that leaves it out, so even `?user` is safe.
+
+/// Set up the global subscriber from the `[logging]` table. This can only happen once, so it's for
+/// `main` to call before anything else gets logged.
+pub fn init(config: &LoggingConfig) {
+    // RUST_LOG wins, so the level can be turned up for one run without editing the config.
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.level))
+        .unwrap_or_else(|e| {
+            eprintln!("Bad log level {:?} in the config ({e}), using info", config.level);
+            EnvFilter::new("info")
+        });
+    let subscriber = fmt().with_env_filter(filter);
+    match config.format {
+        LogFormat::Pretty => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(false).init(),
+    }
+}
@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use tracing::info;
+use crate::config::ServerConfig;
+use crate::error_template::AppError;
+
+/// A message for a user. Plain text only, this is about sending people links, not newsletters.
+#[derive(Clone,Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// The future returned by `MailSender::send`. It's boxed so that `MailSender` can be used as a
+/// `dyn` object in the `AppState`.
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output=Result<(),AppError>> + Send + 'a>>;
+
+/// Anything that can deliver a `Mail`. The app only ever uses this through the `mailer` in
+/// `AppState`, so plugging in a real SMTP or API-based sender means implementing this and
+/// returning it from `mailer_from_config`.
+pub trait MailSender: Send + Sync + std::fmt::Debug {
+    fn send<'a>(&'a self, mail: &'a Mail) -> SendFuture<'a>;
+}
+
+/// Writes every message to the server log. This is the default, and it's enough to click the
+/// links by hand during development. Those links log people in or reset their passwords, so this
+/// is the one place a working token goes into the log on purpose; use a real sender (or the file
+/// one) anywhere that matters.
+#[derive(Clone,Debug)]
+pub struct LogMailer;
+
+impl MailSender for LogMailer {
+    fn send<'a>(&'a self, mail: &'a Mail) -> SendFuture<'a> {
+        Box::pin(async move {
+            info!(to = %mail.to, subject = %mail.subject, body = %mail.body, "Mail");
+            Ok(())
+        })
+    }
+}
+
+/// Drops every message into a directory as its own file, which is handy for tests that need to
+/// fish a link out of a message.
+#[derive(Clone,Debug)]
+pub struct FileMailer {
+    pub dir: PathBuf,
+}
+
+impl MailSender for FileMailer {
+    fn send<'a>(&'a self, mail: &'a Mail) -> SendFuture<'a> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await
+                .map_err(|e| AppError::InternalError(format!("Create mail dir: {e}")))?;
+            // Keep the file name boring so that it's valid everywhere.
+            let to:String = mail.to.chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '@' { c } else { '_' })
+                .collect();
+            let stamp = time::OffsetDateTime::now_utc().unix_timestamp_nanos();
+            let path = self.dir.join(format!("{stamp}-{to}.eml"));
+            let contents = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+            tokio::fs::write(&path, contents).await
+                .map_err(|e| AppError::InternalError(format!("Write mail to {}: {e}", path.display())))?;
+            info!(to = %mail.to, path = %path.display(), "Mail written to a file");
+            Ok(())
+        })
+    }
+}
+
+/// Pick the sender based on the server configuration: a `FileMailer` if `mail_drop_dir` is set,
+/// otherwise a `LogMailer`.
+pub fn mailer_from_config(config: &ServerConfig) -> Arc<dyn MailSender> {
+    match &config.mail_drop_dir {
+        Some(dir) => Arc::new(FileMailer{ dir: dir.into() }),
+        None => Arc::new(LogMailer),
+    }
+}
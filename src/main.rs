@@ -2,11 +2,11 @@
 use cfg_if::cfg_if;
 cfg_if!{
     if #[cfg(feature="ssr")] {
-        use leptos_axum_login::sqlite_backend::{self,*};
+        use leptos_axum_login::backend::{self,*};
         use leptos_axum_login::server::{self,*};
         use leptos_axum_login::user::{self,*};
         
-        pub type AuthSession = axum_login::AuthSession<SqliteBackend>;
+        pub type AuthSession = axum_login::AuthSession<AppBackend>;
     }
 }
 
@@ -28,34 +28,55 @@ cfg_if::cfg_if! {
         use tower::ServiceBuilder;
         use axum::{
             body::Body as AxumBody,
-            extract::{Path, State},
+            extract::{ConnectInfo, Path, State},
             http::Request,
             response::{Html, IntoResponse, Response},
             routing::{get, post},
             Router,
         };
         use axum_login::AuthManagerLayerBuilder;
-        use tower_sessions_sqlx_store::SqliteStore;
         /// This is the default location of the configuration file that tells the server where to
         /// find the database file. 
         static CONFIG_PATH:&str = concat!(env!("CARGO_MANIFEST_DIR"), "/server_config.toml");
 
         use structopt::StructOpt;
-        /// Command line args for the server. Well, arg. You can specify a different config file if
-        /// you don't like the default path
+        use leptos_axum_login::import::ImportFormat;
+        /// Command line args for the server. You can specify a different config file if you don't
+        /// like the default path, and there are a couple of chores it can do instead of serving.
         #[derive(StructOpt,Clone,Debug)]
         pub struct ServerOpts {
             /// Specify a different place to find the config file.
             #[structopt(short="c", long="conf", about="Specify the path to the configuration file", default_value=CONFIG_PATH)]
             config_path: String,
+            #[structopt(subcommand)]
+            command: Option<Command>,
+        }
+
+        /// Things to do instead of running the server. Without one of these, it just serves.
+        #[derive(StructOpt,Clone,Debug)]
+        pub enum Command {
+            /// Load users from another system out of a CSV or JSON dump, then exit. See import.rs
+            /// for the fields.
+            ImportUsers {
+                /// The dump file
+                #[structopt(parse(from_os_str))]
+                file: std::path::PathBuf,
+                /// csv or json. Guessed from the file extension if you leave it out.
+                #[structopt(long)]
+                format: Option<ImportFormat>,
+            },
         }
         use leptos::prelude::*;
         use leptos_axum::{generate_route_list, LeptosRoutes,handle_server_fns_with_context};
         use leptos_axum_login::{
+            account_data::account_export,
             fallback::file_or_index_handler, *,
+            mock_oidc::MockOidc,
+            oauth::{oauth_callback, oauth_start, OAuthClients},
             auth::*,
-            state::AppState,
+            state::{AppState, ClientInfo},
         };
+        use std::net::SocketAddr;
     }
 }
 
@@ -68,52 +89,73 @@ cfg_if::cfg_if! {
 /// your browser console will complain about eof while deserializing stuff.
 #[cfg(feature="ssr")]
 async fn server_func_handler(
-    auth_session: AuthSession,
+    mut auth_session: AuthSession,
     session: tower_sessions::Session,
     State(app_state):State<AppState>,
+    ConnectInfo(addr):ConnectInfo<SocketAddr>,
     req:Request<axum::body::Body>,
 ) -> impl IntoResponse {
+    use tracing::Instrument;
+    // The login throttle needs to know where attempts come from, among other things.
+    let client_info = ClientInfo::new(addr, req.headers());
+    // Everything logged while handling this request happens inside this span, so it all says which
+    // server function it was for and who called it. The user id gets filled in once we know it.
+    let span = tracing::info_span!("server_fn",
+        path = %req.uri().path(),
+        ip = %client_info.ip,
+        user_id = tracing::field::Empty,
+    );
+    async move {
+        // Sessions that were made before users were identified by id need to be moved over. This is
+        // a no-op for everybody else.
+        if let Err(e) = upgrade_legacy_session(&mut auth_session, &session).await {
+            tracing::warn!(error = %e, "Couldn't upgrade an old session");
+        }
+        // A browser whose session ran out can log itself back in with its "remember me" cookie.
+        // That changes the cookie, so the new one has to go out with the response.
+        let remember_cookie = match remember_me::restore_login(&mut auth_session, req.headers(), &client_info).await {
+            Ok(cookie) => cookie,
+            Err(e) => {
+                tracing::warn!(error = %e, "Couldn't log in with a remember-me cookie");
+                None
+            },
+        };
+        if let Some(user) = &auth_session.user {
+            tracing::Span::current().record("user_id", user.id);
+        }
+        // Keep the user's list of active sessions current. A session that was only just made (by a
+        // login in this very request) doesn't have an id yet; it shows up on its next request.
+        if let (Some(user), Some(session_id)) = (&auth_session.user, session.id()) {
+            let touched = auth_session.backend.touch_session(
+                user.id, &session_id.to_string(), client_info.user_agent.as_deref(), &client_info.ip.to_string(),
+            ).await;
+            if let Err(e) = touched {
+                tracing::warn!(error = %e, "Couldn't update the session list");
+            }
+        }
     
-    handle_server_fns_with_context(move || {
-        // AuthSession has a session within it, but you can still use the session extractor
-        // directly to get access to the same session. This holds the `user` field, which will be
-        // `Some(<userdata>)` if somebody is logged in, or `None` otherwise.
-        provide_context(auth_session.clone());
-        // This isn't strictly necessary, but if you want to use tower-sessions without axum_login,
-        // this is how you would pass the session objects into your server functions.
-        provide_context(session.clone());
-        // This holds the connection pool and leptos options
-        provide_context(app_state.clone());
-        // This is the data from the `server_config.toml` file
-        provide_context(app_state.server_config.clone());
-    }, req).await
-}
-
-cfg_if::cfg_if! {
-    if #[cfg(feature="ssr")]  {
-
-        use sqlx::sqlite::SqlitePoolOptions;
-        use sqlx::SqlitePool;
-        use crate::config::ServerConfig;
-        use crate::error_template::AppError;
-
-        /// This is where the connection pool to the database gets built. For sqlite, it's super
-        /// simple (just pass in the path). For postgres, you'll need the user, database, db_host,
-        /// and password fields that you'd use to connect from any other tool. In this case, the
-        /// path for sqlite is coming from the ServerConfig struct, which is mostly here as an
-        /// example of how to use one.
-        pub async fn database_connect(config: &ServerConfig) -> Result<SqlitePool,AppError> {
-            use std::env;
-            let ServerConfig{database_file,..} = config;
-            let db_url = format!("sqlite://{database_file}");
-            env::set_var("DATABASE_URL",db_url.clone());
-
-            Ok(SqlitePoolOptions::new()
-                .max_connections(5)
-                .connect(&db_url).await
-                .map_err(|e| AppError::DatabaseError(format!("{e}")))?)
+        let mut response = handle_server_fns_with_context(move || {
+            // AuthSession has a session within it, but you can still use the session extractor
+            // directly to get access to the same session. This holds the `user` field, which will be
+            // `Some(<userdata>)` if somebody is logged in, or `None` otherwise.
+            provide_context(auth_session.clone());
+            // This isn't strictly necessary, but if you want to use tower-sessions without axum_login,
+            // this is how you would pass the session objects into your server functions.
+            provide_context(session.clone());
+            // This holds the connection pool and leptos options
+            provide_context(app_state.clone());
+            // This is the data from the `server_config.toml` file
+            provide_context(app_state.server_config.clone());
+            provide_context(client_info.clone());
+        }, req).await.into_response();
+        // Unless the server function set the cookie itself, like logging out does.
+        if let Some(cookie) = remember_cookie {
+            if !remember_me::sets_cookie(response.headers()) {
+                response.headers_mut().append(axum::http::header::SET_COOKIE, cookie);
+            }
         }
-    }
+        response
+    }.instrument(span).await
 }
 
 /// main is what does the launching of the server. It's only relevant on server-side stuff, and its
@@ -124,7 +166,7 @@ cfg_if::cfg_if! {
 #[tokio::main]
 async fn main() {
     use axum::Router;
-    use leptos::logging::log;
+    use tracing::{info, warn};
     let opts:ServerOpts = structopt::StructOpt::from_args();
     // I have a simple config file defined to make it easier to adapt this to a more realistic
     // situation. Add stuff to it by editing config.rs, and server_config.toml
@@ -136,14 +178,17 @@ async fn main() {
         "Couldn't parse configuration file from {}!",
         CONFIG_PATH
     ));
+    // Nothing gets logged before this, so it goes first.
+    logging::init(&server_config.logging);
 
     // this one is part of leptos, it digs around in the mystical dark spaces and finds things that
     // only leptos knows.
     let conf = get_configuration(None).unwrap();
     let addr = conf.leptos_options.site_addr;
     let leptos_options = conf.leptos_options;
-    // For sqlite, this just opens the file. However, a similar app with postgres might actually
-    // need to do some network stuff here, hence the async.
+    // For sqlite, this just opens the file. With postgres, it actually has to do some network
+    // stuff, hence the async. Which of the two it is was decided by the cargo features, see
+    // backend.rs.
     let pool = database_connect(&server_config)
         .await
         .expect("Failed to connect to database");
@@ -151,17 +196,16 @@ async fn main() {
     // Set up sessions tables. This is used by TowerSessions to keep track of its data, and the
     // table format is managed by it as well. You don't need to do anything but call the migrate
     // method after you get the session store, and it will make sure to set things up correctly.
-    let session_store = SqliteStore::new(pool.clone())
-        .with_table_name(server_config.session_table_name.clone())
+    let session_store = backend::session_store(pool.clone(), &server_config)
         .expect("Failed to create session store");
 
-    log!("Applying session store migration...");
+    info!("Applying session store migration...");
     // build the session table and stuff if needed.
     session_store
         .migrate()
         .await
         .expect("Failed to apply database migration");
-    log!("Migration complete");
+    info!("Migration complete");
 
     // This is a middleware layer in axum that handles putting the session cookie into the
     // responses when appropriate. It will only do that if there is actually data in the session,
@@ -179,37 +223,91 @@ async fn main() {
 
     // Finally, make the actual database backend that's going to be used by the auth layer to keep
     // track of login status. This is where you'll keep your usernames, password hashes, and other
-    // account stuff.
-    let backend = SqliteBackend::new(pool.clone());
+    // account stuff. The store does the keeping, and the backend does the deciding.
+    let backend = AppBackend::new(AppStore::new(pool.clone()), server_config.clone());
+
+    // The users, roles and permissions tables belong to the backend, so it gets to build them.
+    info!("Applying backend migrations...");
+    backend
+        .migrate()
+        .await
+        .expect("Failed to apply backend migrations");
+
+    if let Some(Command::ImportUsers{file, format}) = opts.command {
+        let format = format.or_else(|| ImportFormat::from_path(&file))
+            .expect("Couldn't tell the format from the file name, use --format");
+        let users = import::read_users(&file, format).expect("Couldn't read the import file");
+        let report = import::import_users(&backend.store, users).await.expect("Import failed");
+        for (username, reason) in &report.skipped {
+            warn!(%username, %reason, "Skipped a user");
+        }
+        info!(imported = report.imported, skipped = report.skipped.len(), "Import finished");
+        return;
+    }
+
+    // Throw out authentication events once they're older than `auth_event_retention_days`.
+    let pruning_backend = backend.clone();
+    let _pruning_task = tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60*60));
+        loop {
+            interval.tick().await;
+            match pruning_backend.prune_auth_events().await {
+                Ok(0) => {},
+                Ok(pruned) => info!(pruned, "Deleted old authentication events"),
+                Err(e) => warn!(error = %e, "Couldn't delete old authentication events"),
+            }
+        }
+    });
 
     // This builds on the session layer to keep track of the authentication status of a user. When
     // a user is authenticated (which happens when you tell it to be so), that fact is recorded in
     // the session_store table and the cookie that goes to the browser now has a session_id (if it
-    // didn't have data before, it does now).
+    // didn't have data before, it does now). The data key is set so sessions from before users
+    // were identified by id don't get misread, see `server::upgrade_legacy_session`.
     let auth_session_layer = ServiceBuilder::new()
-        .layer(AuthManagerLayerBuilder::new(backend,session_layer).build());
+        .layer(AuthManagerLayerBuilder::new(backend,session_layer).with_data_key(AUTH_DATA_KEY).build());
 
     // This is some semi-global stuff that will be useful in many places on the server, so it gets
     // passed around as a use_context (explicity by me) and also with an axum extractor.
     let app_state = AppState {
         pool,
+        session_store,
         leptos_options,
-        server_config,
+        mailer: mail::mailer_from_config(&server_config),
+        oauth: OAuthClients::new(),
+        server_config: server_config.clone(),
     };
 
     // Now we get to the part where leptos is going to take control. The Router here is part of
     // axum, and we're telling it to send all api calls to the server_func_handler we defined
     // before. That one will then give the request to leptos via `handle_server_fns_with_context`.
-    let app = Router::new()
+    //
+    // Logging in with an OAuth provider involves the browser getting redirected around, which
+    // doesn't fit server functions, so those get plain routes of their own. So does the account
+    // data download, since the browser has to see it as a file.
+    let mut app = Router::new()
         .route("/api/{*fn_name}", post(server_func_handler))
+        .route("/auth/oauth/{provider}/start", get(oauth_start))
+        .route("/auth/oauth/{provider}/callback", get(oauth_callback))
+        .route("/account/export", get(account_export));
+
+    // The pretend OpenID Connect provider, if the config asks for it.
+    if let Some(mock_config) = &server_config.mock_oidc {
+        let issuer = format!("{}/mock-oidc", server_config.public_url);
+        warn!(%issuer, "Mock OpenID Connect provider running. Don't do this in production!");
+        app = app.nest("/mock-oidc", MockOidc::new(issuer, mock_config).router());
+    }
+
+    let app = app
         .fallback(file_or_index_handler)
         .layer(auth_session_layer)
         .with_state(app_state);
 
     // run our app with axum
-    log!("listening on http://{}", &addr);
+    info!("listening on http://{}", &addr);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
+    // The connect info is how server_func_handler finds out the client's address.
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
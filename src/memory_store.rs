@@ -0,0 +1,606 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use webauthn_rs::prelude::{Passkey, Uuid};
+use crate::auth_backend::AuthBackend;
+use crate::error_template::AppError;
+use crate::passkeys::StoredPasskey;
+use crate::store::*;
+use crate::tokens::to_hex;
+use crate::user::{DatabaseId, SqlUser};
+
+// A store that keeps everything in a few maps and forgets it all when the process ends. It's here
+// for tests, and for trying out the backend without setting up a database. It tries to behave the
+// same as the SQL stores: usernames and email addresses are unique, tokens expire, deleting a user
+// takes everything of theirs along, and the `admin` role exists from the start like the
+// migrations make it.
+
+/// The auth backend on top of the memory store.
+pub type MemoryBackend = AuthBackend<MemoryUserStore>;
+
+/// A user store that lives in memory. Clones share the same data.
+#[derive(Clone,Debug)]
+pub struct MemoryUserStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct StoredToken {
+    user_id: DatabaseId,
+    expires_at: i64,
+    used_at: Option<i64>,
+}
+
+#[derive(Debug)]
+struct StoredTotp {
+    row: TotpRow,
+    last_used_step: Option<i64>,
+}
+
+#[derive(Debug)]
+struct MemoryPasskey {
+    user_id: DatabaseId,
+    user_handle: Uuid,
+    credential_id: String,
+    stored: StoredPasskey,
+}
+
+#[derive(Debug)]
+struct Inner {
+    users: BTreeMap<DatabaseId, SqlUser>,
+    next_user_id: DatabaseId,
+    /// Keyed on the token hash
+    tokens: HashMap<(TokenKind, String), StoredToken>,
+    totp: HashMap<DatabaseId, StoredTotp>,
+    /// (provider, subject) to user
+    oauth: HashMap<(String, String), DatabaseId>,
+    passkeys: BTreeMap<i64, MemoryPasskey>,
+    next_passkey_id: i64,
+    /// Role name to the permissions it carries
+    roles: HashMap<String, Vec<String>>,
+    user_roles: HashMap<DatabaseId, BTreeSet<String>>,
+    /// Keyed on the entry's id
+    sessions: BTreeMap<i64, SessionEntry>,
+    next_session_id: i64,
+    /// Oldest first
+    audit: Vec<AuditEntry>,
+    /// Oldest first
+    auth_events: Vec<AuthEvent>,
+    next_auth_event_id: i64,
+    /// Keyed on the selector
+    remember_tokens: HashMap<String, RememberToken>,
+    next_remember_token_id: i64,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Inner {
+            users: BTreeMap::new(),
+            next_user_id: 1,
+            tokens: HashMap::new(),
+            totp: HashMap::new(),
+            oauth: HashMap::new(),
+            passkeys: BTreeMap::new(),
+            next_passkey_id: 1,
+            roles: HashMap::from([("admin".to_string(), vec!["admin.access".to_string(), "admin.users".to_string()])]),
+            user_roles: HashMap::new(),
+            sessions: BTreeMap::new(),
+            next_session_id: 1,
+            audit: Vec::new(),
+            auth_events: Vec::new(),
+            next_auth_event_id: 1,
+            remember_tokens: HashMap::new(),
+            next_remember_token_id: 1,
+        }
+    }
+}
+
+impl Default for MemoryUserStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryUserStore {
+    /// An empty store, apart from the `admin` role.
+    pub fn new() -> Self {
+        MemoryUserStore{ inner: Arc::new(Mutex::new(Inner::default())) }
+    }
+
+    /// Nothing in here awaits while holding the lock, so a poisoned lock means a panic in the
+    /// middle of an update. Everything after that gets an error instead of half-changed data.
+    fn lock(&self) -> Result<MutexGuard<'_, Inner>, AppError> {
+        self.inner.lock()
+            .map_err(|_| AppError::InternalError("Memory store lock poisoned".into()))
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    /// There's no schema to bring up to date.
+    async fn migrate(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<SqlUser>, AppError> {
+        Ok(self.lock()?.users.values().find(|user| user.username == username).cloned())
+    }
+
+    async fn find_by_id(&self, id: DatabaseId) -> Result<Option<SqlUser>, AppError> {
+        Ok(self.lock()?.users.get(&id).cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<SqlUser>, AppError> {
+        Ok(self.lock()?.users.values().find(|user| user.email.as_deref() == Some(email)).cloned())
+    }
+
+    async fn insert(&self, user: NewUser) -> Result<DatabaseId, AppError> {
+        let mut inner = self.lock()?;
+        let taken = inner.users.values().any(|existing| existing.username == user.username
+            || (user.email.is_some() && existing.email == user.email));
+        if taken {
+            return Err(AppError::InvalidData("That username or email address is taken".into()));
+        }
+        let id = inner.next_user_id;
+        inner.next_user_id += 1;
+        inner.users.insert(id, SqlUser {
+            id,
+            username: user.username,
+            pass_hash: user.pass_hash.into_inner(),
+            email: user.email,
+            verified_at: None,
+            session_secret: user.session_secret.into_inner(),
+            disabled_at: None,
+        });
+        Ok(id)
+    }
+
+    async fn update_password(&self, id: DatabaseId, pass_hash: &str) -> Result<bool, AppError> {
+        Ok(self.lock()?.users.get_mut(&id)
+            .map(|user| user.pass_hash = pass_hash.to_string())
+            .is_some())
+    }
+
+    async fn update_username(&self, id: DatabaseId, username: &str) -> Result<bool, AppError> {
+        let mut inner = self.lock()?;
+        if inner.users.values().any(|user| user.id != id && user.username == username) {
+            return Err(AppError::InvalidData("That username is taken".into()));
+        }
+        Ok(inner.users.get_mut(&id)
+            .map(|user| user.username = username.to_string())
+            .is_some())
+    }
+
+    async fn set_session_secret(&self, id: DatabaseId, secret: &str) -> Result<bool, AppError> {
+        Ok(self.lock()?.users.get_mut(&id)
+            .map(|user| user.session_secret = secret.to_string())
+            .is_some())
+    }
+
+    async fn delete(&self, id: DatabaseId) -> Result<bool, AppError> {
+        let mut inner = self.lock()?;
+        if inner.users.remove(&id).is_none() {
+            return Ok(false);
+        }
+        inner.tokens.retain(|_, token| token.user_id != id);
+        inner.totp.remove(&id);
+        inner.oauth.retain(|_, user_id| *user_id != id);
+        inner.passkeys.retain(|_, passkey| passkey.user_id != id);
+        inner.user_roles.remove(&id);
+        inner.sessions.retain(|_, entry| entry.user_id != id);
+        for entry in inner.audit.iter_mut().filter(|entry| entry.actor_id == Some(id)) {
+            entry.actor_id = None;
+        }
+        for event in inner.auth_events.iter_mut().filter(|event| event.user_id == Some(id)) {
+            event.user_id = None;
+        }
+        inner.remember_tokens.retain(|_, token| token.user_id != id);
+        Ok(true)
+    }
+
+    async fn mark_email_verified(&self, id: DatabaseId, at: i64) -> Result<(), AppError> {
+        if let Some(user) = self.lock()?.users.get_mut(&id) {
+            user.verified_at.get_or_insert(at);
+        }
+        Ok(())
+    }
+}
+
+impl TokenStore for MemoryUserStore {
+    async fn replace_token(&self, kind: TokenKind, user_id: DatabaseId, token_hash: &str, expires_at: i64, now: i64) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        inner.tokens.retain(|(token_kind, _), token|
+            *token_kind != kind || (token.user_id != user_id && token.expires_at > now));
+        inner.tokens.insert((kind, token_hash.to_string()), StoredToken{ user_id, expires_at, used_at: None });
+        Ok(())
+    }
+
+    async fn token_user(&self, kind: TokenKind, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        Ok(self.lock()?.tokens.get(&(kind, token_hash.to_string()))
+            .filter(|token| token.used_at.is_none() && token.expires_at > now)
+            .map(|token| token.user_id))
+    }
+
+    async fn consume_token(&self, kind: TokenKind, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        Ok(self.lock()?.tokens.get_mut(&(kind, token_hash.to_string()))
+            .filter(|token| token.used_at.is_none() && token.expires_at > now)
+            .map(|token| {
+                token.used_at = Some(now);
+                token.user_id
+            }))
+    }
+
+    async fn consume_email_verification(&self, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        let user_id = self.consume_token(TokenKind::EmailVerification, token_hash, now).await?;
+        if let Some(user_id) = user_id {
+            if let Some(user) = self.lock()?.users.get_mut(&user_id) {
+                user.verified_at = Some(now);
+            }
+        }
+        Ok(user_id)
+    }
+}
+
+impl TotpStore for MemoryUserStore {
+    async fn totp(&self, user_id: DatabaseId) -> Result<Option<TotpRow>, AppError> {
+        Ok(self.lock()?.totp.get(&user_id).map(|totp| totp.row.clone()))
+    }
+
+    async fn set_totp_secret(&self, user_id: DatabaseId, secret: &str) -> Result<(), AppError> {
+        self.lock()?.totp.insert(user_id, StoredTotp {
+            row: TotpRow{ secret: secret.to_string(), recovery_codes: String::new(), enabled_at: None },
+            last_used_step: None,
+        });
+        Ok(())
+    }
+
+    async fn enable_totp(&self, user_id: DatabaseId, enabled_at: i64, step: i64, recovery_codes: &str) -> Result<(), AppError> {
+        if let Some(totp) = self.lock()?.totp.get_mut(&user_id) {
+            totp.row.enabled_at = Some(enabled_at);
+            totp.row.recovery_codes = recovery_codes.to_string();
+            totp.last_used_step = Some(step);
+        }
+        Ok(())
+    }
+
+    async fn delete_totp(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        self.lock()?.totp.remove(&user_id);
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, user_id: DatabaseId, step: i64) -> Result<bool, AppError> {
+        Ok(match self.lock()?.totp.get_mut(&user_id) {
+            Some(totp) if totp.last_used_step.is_none_or(|last| last < step) => {
+                totp.last_used_step = Some(step);
+                true
+            },
+            _ => false,
+        })
+    }
+
+    async fn replace_recovery_codes(&self, user_id: DatabaseId, current: &str, remaining: &str) -> Result<bool, AppError> {
+        Ok(match self.lock()?.totp.get_mut(&user_id) {
+            Some(totp) if totp.row.recovery_codes == current => {
+                totp.row.recovery_codes = remaining.to_string();
+                true
+            },
+            _ => false,
+        })
+    }
+}
+
+impl OAuthStore for MemoryUserStore {
+    async fn link_oauth_account(&self, user_id: DatabaseId, provider: &str, subject: &str, _email: Option<String>, _now: i64) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        let key = (provider.to_string(), subject.to_string());
+        if inner.oauth.contains_key(&key) {
+            return Err(AppError::InvalidData("That account is already connected to another user".into()));
+        }
+        inner.oauth.insert(key, user_id);
+        Ok(())
+    }
+
+    async fn find_by_oauth(&self, provider: &str, subject: &str) -> Result<Option<SqlUser>, AppError> {
+        let inner = self.lock()?;
+        Ok(inner.oauth.get(&(provider.to_string(), subject.to_string()))
+            .and_then(|user_id| inner.users.get(user_id))
+            .cloned())
+    }
+}
+
+impl PasskeyStore for MemoryUserStore {
+    async fn passkey_user_handle(&self, user_id: DatabaseId) -> Result<Option<Uuid>, AppError> {
+        Ok(self.lock()?.passkeys.values()
+            .find(|passkey| passkey.user_id == user_id)
+            .map(|passkey| passkey.user_handle))
+    }
+
+    async fn list_passkeys(&self, user_id: DatabaseId) -> Result<Vec<StoredPasskey>, AppError> {
+        let mut passkeys:Vec<StoredPasskey> = self.lock()?.passkeys.values()
+            .filter(|passkey| passkey.user_id == user_id)
+            .map(|passkey| passkey.stored.clone())
+            .collect();
+        passkeys.sort_by_key(|passkey| (passkey.created_at, passkey.id));
+        Ok(passkeys)
+    }
+
+    async fn find_passkey(&self, credential_id: &str, user_handle: Uuid) -> Result<Option<(DatabaseId, StoredPasskey)>, AppError> {
+        Ok(self.lock()?.passkeys.values()
+            .find(|passkey| passkey.credential_id == credential_id && passkey.user_handle == user_handle)
+            .map(|passkey| (passkey.user_id, passkey.stored.clone())))
+    }
+
+    async fn insert_passkey(&self, user_id: DatabaseId, user_handle: Uuid, name: &str, passkey: &Passkey, now: i64) -> Result<(), AppError> {
+        let credential_id = to_hex(passkey.cred_id().as_ref());
+        let mut inner = self.lock()?;
+        if inner.passkeys.values().any(|existing| existing.credential_id == credential_id) {
+            return Err(AppError::InvalidData("That passkey is already registered".into()));
+        }
+        let id = inner.next_passkey_id;
+        inner.next_passkey_id += 1;
+        inner.passkeys.insert(id, MemoryPasskey {
+            user_id,
+            user_handle,
+            credential_id,
+            stored: StoredPasskey {
+                id,
+                name: name.to_string(),
+                created_at: now,
+                last_used_at: None,
+                passkey: passkey.clone(),
+            },
+        });
+        Ok(())
+    }
+
+    async fn update_passkey(&self, id: i64, passkey: &Passkey, last_used_at: i64) -> Result<(), AppError> {
+        if let Some(stored) = self.lock()?.passkeys.get_mut(&id) {
+            stored.stored.passkey = passkey.clone();
+            stored.stored.last_used_at = Some(last_used_at);
+        }
+        Ok(())
+    }
+
+    async fn delete_passkey(&self, user_id: DatabaseId, id: i64) -> Result<bool, AppError> {
+        let mut inner = self.lock()?;
+        if inner.passkeys.get(&id).is_some_and(|passkey| passkey.user_id == user_id) {
+            inner.passkeys.remove(&id);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl RoleStore for MemoryUserStore {
+    async fn user_roles(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        Ok(self.lock()?.user_roles.get(&user_id)
+            .map(|roles| roles.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn grant_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        if !inner.roles.contains_key(role) {
+            return Err(AppError::InvalidData(format!("No such role: {role}")));
+        }
+        inner.user_roles.entry(user_id).or_default().insert(role.to_string());
+        Ok(())
+    }
+
+    async fn revoke_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        if let Some(roles) = self.lock()?.user_roles.get_mut(&user_id) {
+            roles.remove(role);
+        }
+        Ok(())
+    }
+
+    async fn user_permissions(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        let inner = self.lock()?;
+        let permissions:BTreeSet<String> = inner.user_roles.get(&user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|role| inner.roles.get(role))
+            .flatten()
+            .cloned()
+            .collect();
+        Ok(permissions.into_iter().collect())
+    }
+}
+
+impl SessionIndexStore for MemoryUserStore {
+    async fn touch_session(&self, user_id: DatabaseId, session_id: &str, user_agent: Option<&str>, ip: &str, now: i64) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        if let Some(entry) = inner.sessions.values_mut().find(|entry| entry.session_id == session_id) {
+            if entry.last_seen_at < now - 60 || entry.user_id != user_id {
+                entry.user_id = user_id;
+                entry.user_agent = user_agent.map(String::from);
+                entry.ip = Some(ip.to_string());
+                entry.last_seen_at = now;
+            }
+            return Ok(());
+        }
+        let id = inner.next_session_id;
+        inner.next_session_id += 1;
+        inner.sessions.insert(id, SessionEntry {
+            id,
+            session_id: session_id.to_string(),
+            user_id,
+            user_agent: user_agent.map(String::from),
+            ip: Some(ip.to_string()),
+            created_at: now,
+            last_seen_at: now,
+        });
+        Ok(())
+    }
+
+    async fn list_sessions(&self, user_id: DatabaseId, since: i64) -> Result<Vec<SessionEntry>, AppError> {
+        let mut inner = self.lock()?;
+        inner.sessions.retain(|_, entry| entry.last_seen_at >= since);
+        let mut sessions: Vec<SessionEntry> = inner.sessions.values()
+            .filter(|entry| entry.user_id == user_id)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|entry| std::cmp::Reverse(entry.last_seen_at));
+        Ok(sessions)
+    }
+
+    async fn remove_session(&self, user_id: DatabaseId, id: i64) -> Result<Option<SessionEntry>, AppError> {
+        let mut inner = self.lock()?;
+        if inner.sessions.get(&id).is_some_and(|entry| entry.user_id == user_id) {
+            Ok(inner.sessions.remove(&id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn forget_session(&self, session_id: &str) -> Result<(), AppError> {
+        self.lock()?.sessions.retain(|_, entry| entry.session_id != session_id);
+        Ok(())
+    }
+
+    async fn remove_user_sessions(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        self.lock()?.sessions.retain(|_, entry| entry.user_id != user_id);
+        Ok(())
+    }
+}
+
+impl AdminStore for MemoryUserStore {
+    async fn search_users(&self, query: &str, offset: i64, limit: i64) -> Result<(Vec<SqlUser>, i64), AppError> {
+        let query = query.to_lowercase();
+        let inner = self.lock()?;
+        let matches: Vec<&SqlUser> = inner.users.values()
+            .filter(|user| user.username.to_lowercase().contains(&query)
+                || user.email.as_ref().is_some_and(|email| email.to_lowercase().contains(&query)))
+            .collect();
+        let total = matches.len() as i64;
+        let page = matches.into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn set_disabled(&self, id: DatabaseId, disabled_at: Option<i64>) -> Result<bool, AppError> {
+        Ok(self.lock()?.users.get_mut(&id)
+            .map(|user| user.disabled_at = disabled_at)
+            .is_some())
+    }
+
+    async fn insert_audit(&self, entry: NewAuditEntry) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        let id = inner.audit.len() as i64 + 1;
+        inner.audit.push(AuditEntry {
+            id,
+            created_at: entry.created_at,
+            actor_id: Some(entry.actor_id),
+            actor_name: entry.actor_name,
+            action: entry.action,
+            target_id: entry.target_id,
+            target_name: entry.target_name,
+            detail: entry.detail,
+        });
+        Ok(())
+    }
+
+    async fn list_audit(&self, offset: i64, limit: i64) -> Result<(Vec<AuditEntry>, i64), AppError> {
+        let inner = self.lock()?;
+        let page = inner.audit.iter().rev()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect();
+        Ok((page, inner.audit.len() as i64))
+    }
+}
+
+impl AuthEventStore for MemoryUserStore {
+    async fn insert_auth_event(&self, event: NewAuthEvent) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        let id = inner.next_auth_event_id;
+        inner.next_auth_event_id += 1;
+        inner.auth_events.push(AuthEvent {
+            id,
+            created_at: event.created_at,
+            kind: event.kind,
+            user_id: event.user_id,
+            username: event.username,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            detail: event.detail,
+        });
+        Ok(())
+    }
+
+    async fn list_auth_events(&self, filter: &AuthEventFilter, offset: i64, limit: i64) -> Result<(Vec<AuthEvent>, i64), AppError> {
+        let username = filter.username.as_ref().map(|u| u.to_lowercase());
+        let inner = self.lock()?;
+        let matches: Vec<&AuthEvent> = inner.auth_events.iter().rev()
+            .filter(|event| filter.kind.as_ref().is_none_or(|kind| &event.kind == kind))
+            .filter(|event| filter.user_id.is_none_or(|id| event.user_id == Some(id)))
+            .filter(|event| username.as_ref().is_none_or(|u| event.username.as_deref().unwrap_or("").to_lowercase().contains(u)))
+            .filter(|event| filter.ip.as_ref().is_none_or(|ip| event.ip.as_ref() == Some(ip)))
+            .collect();
+        let total = matches.len() as i64;
+        let page = matches.into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn prune_auth_events(&self, before: i64) -> Result<u64, AppError> {
+        let mut inner = self.lock()?;
+        let count = inner.auth_events.len();
+        inner.auth_events.retain(|event| event.created_at >= before);
+        Ok((count - inner.auth_events.len()) as u64)
+    }
+}
+
+impl RememberTokenStore for MemoryUserStore {
+    async fn insert_remember_token(&self, token: NewRememberToken, now: i64) -> Result<(), AppError> {
+        let mut inner = self.lock()?;
+        inner.remember_tokens.retain(|_, existing| existing.expires_at > now);
+        if inner.remember_tokens.contains_key(&token.selector) {
+            return Err(AppError::InternalError("Duplicate remember token selector".into()));
+        }
+        let id = inner.next_remember_token_id;
+        inner.next_remember_token_id += 1;
+        inner.remember_tokens.insert(token.selector.clone(), RememberToken {
+            id,
+            user_id: token.user_id,
+            selector: token.selector,
+            validator_hash: token.validator_hash,
+            previous_validator_hash: None,
+            rotated_at: None,
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+        });
+        Ok(())
+    }
+
+    async fn find_remember_token(&self, selector: &str, now: i64) -> Result<Option<RememberToken>, AppError> {
+        Ok(self.lock()?.remember_tokens.get(selector)
+            .filter(|token| token.expires_at > now)
+            .cloned())
+    }
+
+    async fn rotate_remember_token(&self, selector: &str, current_hash: &str, new_hash: &str, now: i64) -> Result<bool, AppError> {
+        Ok(self.lock()?.remember_tokens.get_mut(selector)
+            .filter(|token| token.validator_hash == current_hash)
+            .map(|token| {
+                token.previous_validator_hash = Some(std::mem::replace(&mut token.validator_hash, new_hash.to_string()));
+                token.rotated_at = Some(now);
+            })
+            .is_some())
+    }
+
+    async fn delete_remember_token(&self, selector: &str) -> Result<(), AppError> {
+        self.lock()?.remember_tokens.remove(selector);
+        Ok(())
+    }
+
+    async fn delete_user_remember_tokens(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        self.lock()?.remember_tokens.retain(|_, token| token.user_id != user_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use axum::{
+    extract::{Form, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openidconnect::{
+    core::{CoreHmacKey, CoreIdToken, CoreIdTokenClaims, CoreJwsSigningAlgorithm},
+    url::Url, AccessToken, Audience, AuthorizationCode, EmptyAdditionalClaims, EndUserEmail,
+    EndUserUsername, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, StandardClaims,
+    SubjectIdentifier,
+};
+use serde::Deserialize;
+use serde_json::json;
+use crate::config::MockOidcConfig;
+use crate::secret::Secret;
+use crate::tokens::{generate_token, now};
+
+/// Authorization codes are only good for this long.
+const CODE_TTL_SECONDS: i64 = 60;
+
+/// A fake OpenID Connect provider, just enough of one for the authorization code flow with PKCE.
+/// There are no passwords: the "login page" asks who you'd like to be and believes you. It exists
+/// so that the OAuth login (see oauth.rs) can be tried out, and tested end to end, without a
+/// network connection or an account at a real provider.
+///
+/// ID tokens are signed with HS256 using the client secret, which the OpenID Connect spec allows
+/// for confidential clients, and which saves this from needing a key pair.
+#[derive(Clone,Debug)]
+pub struct MockOidc {
+    issuer: String,
+    client_id: String,
+    client_secret: Secret<String>,
+    codes: Arc<Mutex<HashMap<String, IssuedCode>>>,
+}
+
+/// An authorization code waiting to be traded in at the token endpoint.
+#[derive(Clone,Debug)]
+struct IssuedCode {
+    redirect_uri: String,
+    nonce: Option<String>,
+    code_challenge: String,
+    subject: String,
+    email: Option<String>,
+    issued_at: i64,
+}
+
+/// The query string of a request to the authorization endpoint.
+#[derive(Clone,Debug,Deserialize)]
+struct AuthorizeParams {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+/// The form on the fake login page. The hidden fields carry the original request along.
+#[derive(Clone,Debug,Deserialize)]
+struct ApproveForm {
+    redirect_uri: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: String,
+    subject: String,
+    #[serde(default)]
+    email: String,
+}
+
+/// What a client sends to the token endpoint. The client id and secret can come either in here
+/// or in a Basic `Authorization` header.
+#[derive(Clone,Debug,Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    code_verifier: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+impl MockOidc {
+    /// `issuer` has to be the URL the router ends up mounted at, as seen from outside.
+    pub fn new(issuer: String, config: &MockOidcConfig) -> Self {
+        MockOidc {
+            issuer,
+            client_id: config.client_id.clone(),
+            client_secret: config.client_secret.clone(),
+            codes: Default::default(),
+        }
+    }
+
+    /// The routes for the provider. Nest this at the path in the issuer URL.
+    pub fn router<S>(self) -> Router<S>
+    where S: Clone + Send + Sync + 'static {
+        Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/authorize", get(authorize_page).post(approve))
+            .route("/token", axum::routing::post(token))
+            .with_state(self)
+    }
+}
+
+/// The OpenID Connect Discovery document, which tells clients where everything else is.
+async fn discovery(State(mock): State<MockOidc>) -> Json<serde_json::Value> {
+    let issuer = &mock.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+        "jwks_uri": format!("{issuer}/jwks"),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["HS256"],
+        "scopes_supported": ["openid", "email", "profile"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": ["sub", "email", "email_verified", "preferred_username"],
+    }))
+}
+
+/// There are no public keys, since the tokens are signed with the client secret.
+async fn jwks() -> Json<serde_json::Value> {
+    Json(json!({ "keys": [] }))
+}
+
+fn bad_request(message: &str) -> Response {
+    (StatusCode::BAD_REQUEST, message.to_string()).into_response()
+}
+
+/// Escape text for use inside an HTML attribute.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The authorization endpoint. A real provider would have the user log in here; this one just asks
+/// which subject and email address to put in the ID token.
+async fn authorize_page(State(mock): State<MockOidc>, Query(params): Query<AuthorizeParams>) -> Response {
+    if params.response_type != "code" {
+        return bad_request("Only response_type=code is supported");
+    }
+    if params.client_id != mock.client_id {
+        return bad_request("Unknown client_id");
+    }
+    let Some(challenge) = params.code_challenge.filter(|_| params.code_challenge_method.as_deref() == Some("S256")) else {
+        return bad_request("PKCE with S256 is required");
+    };
+    let hidden = |name: &str, value: &str| format!(r#"<input type="hidden" name="{name}" value="{}">"#, escape(value));
+    Html(format!(r#"<!DOCTYPE html>
+<html><head><title>Mock OIDC</title></head><body>
+<h1>Mock OpenID Connect provider</h1>
+<p>Sign in to {client} as anybody you like.</p>
+<form method="post" action="{issuer}/authorize">
+{redirect_uri}{state}{nonce}{challenge}
+<label>Subject <input name="subject" value="mock-user" required></label><br>
+<label>Email <input name="email" value="mock-user@example.com"></label><br>
+<input type="submit" value="sign in">
+</form>
+</body></html>"#,
+        client = escape(&mock.client_id),
+        issuer = escape(&mock.issuer),
+        redirect_uri = hidden("redirect_uri", &params.redirect_uri),
+        state = hidden("state", params.state.as_deref().unwrap_or("")),
+        nonce = hidden("nonce", params.nonce.as_deref().unwrap_or("")),
+        challenge = hidden("code_challenge", &challenge),
+    )).into_response()
+}
+
+/// The fake login page was submitted: make a code and send the browser back to the client.
+async fn approve(State(mock): State<MockOidc>, Form(form): Form<ApproveForm>) -> Response {
+    let Ok(mut redirect) = Url::parse(&form.redirect_uri) else {
+        return bad_request("Bad redirect_uri");
+    };
+    let code = generate_token();
+    mock.codes.lock().expect("mock OIDC lock poisoned").insert(code.clone(), IssuedCode {
+        redirect_uri: form.redirect_uri.clone(),
+        nonce: form.nonce.filter(|n| !n.is_empty()),
+        code_challenge: form.code_challenge,
+        subject: form.subject,
+        email: Some(form.email).filter(|e| !e.is_empty()),
+        issued_at: now(),
+    });
+    {
+        let mut query = redirect.query_pairs_mut();
+        query.append_pair("code", &code);
+        if let Some(state) = form.state.filter(|s| !s.is_empty()) {
+            query.append_pair("state", &state);
+        }
+    }
+    Redirect::to(redirect.as_str()).into_response()
+}
+
+/// Pull the client id and secret out of a Basic `Authorization` header. Both parts are form
+/// encoded before they're put together, per RFC 6749.
+fn basic_auth(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let decoded = STANDARD.decode(value.strip_prefix("Basic ")?).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (id, secret) = decoded.split_once(':')?;
+    let unescape = |s: &str| urlencoding::decode(&s.replace('+', " ")).ok().map(|s| s.into_owned());
+    Some((unescape(id)?, unescape(secret)?))
+}
+
+/// The token endpoint: trade a code (plus the PKCE verifier) for an ID token.
+async fn token(State(mock): State<MockOidc>, headers: HeaderMap, Form(form): Form<TokenForm>) -> Response {
+    let error = |error: &str| (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response();
+    let (client_id, client_secret) = match (basic_auth(&headers), form.client_id, form.client_secret) {
+        (Some(credentials), _, _) => credentials,
+        (None, Some(id), Some(secret)) => (id, secret),
+        _ => return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "invalid_client" }))).into_response(),
+    };
+    if client_id != mock.client_id || &client_secret != mock.client_secret.expose() {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "invalid_client" }))).into_response();
+    }
+    if form.grant_type != "authorization_code" {
+        return error("unsupported_grant_type");
+    }
+    // Codes only work once, right or wrong.
+    let Some(issued) = mock.codes.lock().expect("mock OIDC lock poisoned").remove(&form.code) else {
+        return error("invalid_grant");
+    };
+    let verifier_matches = form.code_verifier
+        .map(|v| PkceCodeChallenge::from_code_verifier_sha256(&PkceCodeVerifier::new(v)).as_str() == issued.code_challenge)
+        .unwrap_or(false);
+    if now() - issued.issued_at > CODE_TTL_SECONDS || issued.redirect_uri != form.redirect_uri || !verifier_matches {
+        return error("invalid_grant");
+    }
+
+    let access_token = AccessToken::new(generate_token());
+    let issuer = match IssuerUrl::new(mock.issuer.clone()) {
+        Ok(issuer) => issuer,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Bad issuer URL: {e}")).into_response(),
+    };
+    let issued_at = chrono::Utc::now();
+    let claims = CoreIdTokenClaims::new(
+        issuer,
+        vec![Audience::new(mock.client_id.clone())],
+        issued_at + chrono::Duration::minutes(5),
+        issued_at,
+        StandardClaims::new(SubjectIdentifier::new(issued.subject.clone()))
+            .set_preferred_username(Some(EndUserUsername::new(issued.subject)))
+            .set_email_verified(issued.email.as_ref().map(|_| true))
+            .set_email(issued.email.map(EndUserEmail::new)),
+        EmptyAdditionalClaims {},
+    ).set_nonce(issued.nonce.map(Nonce::new));
+    let id_token = match CoreIdToken::new(
+        claims,
+        &CoreHmacKey::new(mock.client_secret.expose().as_bytes()),
+        CoreJwsSigningAlgorithm::HmacSha256,
+        Some(&access_token),
+        Some(&AuthorizationCode::new(form.code)),
+    ) {
+        Ok(id_token) => id_token,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Sign ID token: {e}")).into_response(),
+    };
+    Json(json!({
+        "access_token": access_token.secret(),
+        "token_type": "bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    })).into_response()
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_login::{AuthSession, AuthnBackend};
+use openidconnect::{
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
+    reqwest, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet,
+    EndpointNotSet, EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier,
+    RedirectUrl, Scope, TokenResponse, url::Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tower_sessions::Session;
+use crate::auth_backend::AuthBackend;
+use crate::auth_events::AuthEventKind;
+use crate::config::OAuthProviderConfig;
+use crate::credentials::Credentials;
+use crate::error_template::AppError;
+use crate::server::{finish_login, LoginOutcome};
+use crate::backend::AppBackend;
+use crate::state::{AppState, ClientInfo};
+use crate::store::Store;
+use crate::tokens::now;
+use crate::user::User;
+
+/// The session key where `oauth_start` keeps what `oauth_callback` needs to check the answer.
+const PENDING_OAUTH_KEY: &str = "pending_oauth";
+/// How long the user gets to finish up at the provider before the login has to start over.
+const OAUTH_TIMEOUT_SECONDS: i64 = 10*60;
+
+/// What a client built from discovered provider metadata looks like. The type parameters say
+/// which endpoints it knows about; discovery always gives us the authorization endpoint, and
+/// maybe a token and userinfo endpoint.
+type OidcClient = CoreClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointMaybeSet, EndpointMaybeSet>;
+
+/// A login that has been sent off to a provider. The state, nonce and PKCE verifier are only ever
+/// stored here, on the server side, so whoever comes back to the callback has to bring the same
+/// session cookie that started the login.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct PendingOAuth {
+    provider: String,
+    csrf_state: String,
+    nonce: String,
+    pkce_verifier: String,
+    started_at: i64,
+}
+
+/// What the provider sends back to the callback, in the query string.
+#[derive(Clone,Debug,Deserialize)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Who the provider says somebody is, once the ID token has been checked.
+#[derive(Clone,Debug)]
+pub struct ProviderIdentity {
+    /// The `sub` claim, which is what the account is linked by
+    pub subject: String,
+    /// Only there if the provider says it checked the address
+    pub email: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+/// Talks to the OpenID Connect providers from the config. Discovery (fetching the provider's
+/// endpoints and keys) happens the first time a provider is used rather than at startup, so the
+/// server comes up even if a provider is down, and so the mock provider can live in this same
+/// server.
+#[derive(Clone,Debug)]
+pub struct OAuthClients {
+    http: reqwest::Client,
+    metadata: Arc<RwLock<HashMap<String, CoreProviderMetadata>>>,
+}
+
+impl OAuthClients {
+    pub fn new() -> Self {
+        let http = reqwest::ClientBuilder::new()
+            // Following redirects here opens the server up to SSRF, says the openidconnect docs.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build the HTTP client for OAuth");
+        OAuthClients{ http, metadata: Default::default() }
+    }
+
+    /// Build a client for the provider, discovering its metadata first if that hasn't happened
+    /// yet.
+    async fn client(&self, provider: &OAuthProviderConfig, public_url: &str) -> Result<OidcClient, AppError> {
+        let cached = self.metadata.read().await.get(&provider.name).cloned();
+        let metadata = match cached {
+            Some(metadata) => metadata,
+            None => {
+                let issuer = IssuerUrl::new(provider.issuer.clone())
+                    .map_err(|e| AppError::InternalError(format!("Bad issuer URL for {}: {e}", provider.name)))?;
+                let metadata = CoreProviderMetadata::discover_async(issuer, &self.http).await
+                    .map_err(|e| AppError::InternalError(format!("Discovery for {}: {e}", provider.name)))?;
+                self.metadata.write().await.insert(provider.name.clone(), metadata.clone());
+                metadata
+            }
+        };
+        let redirect = RedirectUrl::new(format!("{public_url}/auth/oauth/{}/callback", provider.name))
+            .map_err(|e| AppError::InternalError(format!("Bad redirect URL: {e}")))?;
+        Ok(CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(provider.client_id.clone()),
+            provider.client_secret.as_ref().map(|secret| ClientSecret::new(secret.expose().clone())),
+        ).set_redirect_uri(redirect))
+    }
+}
+
+impl OAuthClients {
+    /// Where to send the browser to log in at the provider, using the authorization code flow
+    /// with PKCE, and what `verify` will need to check the answer. That has to be kept on the
+    /// server side until the browser comes back.
+    pub async fn authorize_url(&self, provider: &OAuthProviderConfig, public_url: &str) -> Result<(Url, PendingOAuth), AppError> {
+        let client = self.client(provider, public_url).await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let mut request = client.authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        );
+        // `openid` is added by the library already
+        for scope in provider.scopes.iter().filter(|s| s.as_str() != "openid") {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (url, csrf_state, nonce) = request.set_pkce_challenge(pkce_challenge).url();
+        Ok((url, PendingOAuth{
+            provider: provider.name.clone(),
+            csrf_state: csrf_state.secret().clone(),
+            nonce: nonce.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+            started_at: now(),
+        }))
+    }
+
+    /// Check what the provider sent back to the callback against the login that was started.
+    /// The code gets traded for an ID token, which is checked (signature, issuer, audience, nonce,
+    /// expiry) before anything in it is believed.
+    pub async fn verify(&self, provider: &OAuthProviderConfig, public_url: &str, pending: PendingOAuth, params: CallbackParams)
+    -> Result<ProviderIdentity, AppError> {
+        if let Some(error) = params.error {
+            return Err(AppError::InvalidData(params.error_description.unwrap_or(error)));
+        }
+        if pending.provider != provider.name || params.state.as_deref() != Some(pending.csrf_state.as_str()) {
+            return Err(AppError::InvalidData("The login didn't match the one that was started, please try again".into()));
+        }
+        if now() - pending.started_at > OAUTH_TIMEOUT_SECONDS {
+            return Err(AppError::InvalidData("That took too long, please try again".into()));
+        }
+        let code = params.code.ok_or_else(|| AppError::InvalidData("The provider didn't send a code".into()))?;
+
+        let client = self.client(provider, public_url).await?;
+        let token_response = client.exchange_code(AuthorizationCode::new(code))
+            .map_err(|e| AppError::InternalError(format!("OAuth setup: {e}")))?
+            .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+            .request_async(&self.http).await
+            .map_err(|e| AppError::InternalError(format!("Exchange OAuth code: {e}")))?;
+        let id_token = token_response.id_token()
+            .ok_or_else(|| AppError::InternalError("The provider didn't send an ID token".into()))?;
+        let claims = id_token.claims(&client.id_token_verifier(), &Nonce::new(pending.nonce))
+            .map_err(|e| AppError::InvalidData(format!("Bad ID token: {e}")))?;
+        Ok(ProviderIdentity {
+            subject: claims.subject().to_string(),
+            // Only keep addresses that the provider says it checked.
+            email: claims.email()
+                .filter(|_| claims.email_verified() == Some(true))
+                .map(|e| e.to_string()),
+            preferred_username: claims.preferred_username().map(|u| u.to_string()),
+        })
+    }
+}
+
+impl Default for OAuthClients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Send whatever went wrong back to the login page, which shows it.
+fn back_to_login(message: &str) -> Response {
+    Redirect::to(&format!("/login?oauth_error={}", urlencoding::encode(message))).into_response()
+}
+
+fn find_provider<'a>(state: &'a AppState, name: &str) -> Result<&'a OAuthProviderConfig, AppError> {
+    state.server_config.oauth_providers.iter()
+        .find(|p| p.name == name)
+        .ok_or(AppError::NotFound)
+}
+
+/// `GET /auth/oauth/{provider}/start` sends the browser off to the provider's login page using the
+/// authorization code flow with PKCE. The login page links here with `rel="external"` so that the
+/// router doesn't try to handle it. If somebody is already logged in when they come through here,
+/// the provider account gets connected to theirs instead.
+pub async fn oauth_start(
+    session: Session,
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+) -> Response {
+    match start(session, state, provider).await {
+        Ok(redirect) => redirect.into_response(),
+        Err(e) => back_to_login(&e.to_string()),
+    }
+}
+
+async fn start(session: Session, state: AppState, provider: String) -> Result<Redirect, AppError> {
+    let provider = find_provider(&state, &provider)?;
+    let (url, pending) = state.oauth.authorize_url(provider, &state.server_config.public_url).await?;
+    session.insert(PENDING_OAUTH_KEY, pending).await
+        .map_err(|e| AppError::InternalError(format!("Save OAuth state: {e}")))?;
+    Ok(Redirect::to(url.as_str()))
+}
+
+/// `GET /auth/oauth/{provider}/callback` is where the provider sends the browser back to. The code
+/// gets traded for an ID token, which is checked (signature, issuer, audience, nonce, expiry), and
+/// then the `sub` claim in it decides who this is:
+///
+/// - somebody is already logged in: connect the provider account to them
+/// - the provider account is connected to a user: log in as that user
+/// - otherwise: make a new user for it
+pub async fn oauth_callback(
+    mut auth: AuthSession<AppBackend>,
+    session: Session,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    Query(params): Query<CallbackParams>,
+) -> Response {
+    let client = ClientInfo::new(addr, &headers);
+    match callback(&mut auth, &session, state, &client, provider, params).await {
+        Ok(redirect) => redirect.into_response(),
+        Err(e) => back_to_login(&e.to_string()),
+    }
+}
+
+async fn callback(
+    auth: &mut AuthSession<AppBackend>,
+    session: &Session,
+    state: AppState,
+    client: &ClientInfo,
+    provider: String,
+    params: CallbackParams,
+) -> Result<Redirect, AppError> {
+    // Whatever happens, this login attempt is over after this.
+    let pending = session.remove::<PendingOAuth>(PENDING_OAUTH_KEY).await
+        .map_err(|e| AppError::InternalError(format!("Load OAuth state: {e}")))?
+        .ok_or_else(|| AppError::InvalidData("There's no login waiting for this, please try again".into()))?;
+    let provider = find_provider(&state, &provider)?;
+    let identity = state.oauth.verify(provider, &state.server_config.public_url, pending, params).await?;
+
+    // Somebody who's logged in is connecting another way to log in.
+    if let Some(user) = auth.user.clone() {
+        auth.backend.link_oauth_account(user.id, &provider.name, &identity.subject, identity.email).await?;
+        return Ok(Redirect::to("/"));
+    }
+
+    let (user, created) = oauth_user(&auth.backend, provider, identity).await?;
+    if created {
+        let detail = format!("with {}", provider.name);
+        auth.backend.record_event(AuthEventKind::Registered, Some(user.id), Some(&user.username), Some(client), Some(&detail)).await;
+    }
+    match finish_login(auth, session, user, client, &provider.name, false).await? {
+        LoginOutcome::LoggedIn(_) => Ok(Redirect::to("/")),
+        // The login page picks it up from here, see `second_factor_pending`
+        _ => Ok(Redirect::to("/login")),
+    }
+}
+
+/// The local user for somebody the provider vouched for: whoever the provider account is linked
+/// to, or else a new user made for it. The flag says whether the user is new.
+pub async fn oauth_user<S: Store>(backend: &AuthBackend<S>, provider: &OAuthProviderConfig, identity: ProviderIdentity)
+-> Result<(User, bool), AppError> {
+    let credentials = Credentials::OAuth{ provider: provider.name.clone(), subject: identity.subject.clone() };
+    if let Some(user) = backend.authenticate(credentials).await? {
+        return Ok((user, false));
+    }
+    let user = new_oauth_user(backend, provider, &identity.subject, identity.preferred_username, identity.email).await?;
+    Ok((user, true))
+}
+
+/// Make a local user for somebody who logged in with a provider for the first time. The username
+/// is whatever the provider suggests, with a number stuck on the end if it's taken. They get a
+/// random password that nobody knows, since the users table needs one; they can set a real one
+/// with a password reset.
+async fn new_oauth_user<S: Store>(
+    backend: &AuthBackend<S>,
+    provider: &OAuthProviderConfig,
+    subject: &str,
+    preferred_username: Option<String>,
+    email: Option<String>,
+) -> Result<User, AppError> {
+    // Taking over an existing account just because a provider has the same email address for
+    // somebody would be a gift to anybody who can get a provider to say that. They have to log in
+    // first and connect the accounts themselves.
+    if let Some(email) = &email {
+        if backend.find_user_by_email(email).await?.is_some() {
+            return Err(AppError::InvalidData(
+                "There's already an account with that email address. Log in to it first, then connect this one".into()
+            ));
+        }
+    }
+    let base = preferred_username
+        .or_else(|| email.as_ref().and_then(|e| e.split('@').next().map(String::from)))
+        .filter(|name| name.len() >= 2)
+        .unwrap_or_else(|| format!("{}-user", provider.name));
+    let mut username = base.clone();
+    let mut n = 1;
+    while backend.find_user_by_name(&username).await?.is_some() {
+        n += 1;
+        username = format!("{base}{n}");
+    }
+    let user = backend.add_user_without_password(username, email.clone()).await?
+        .ok_or_else(|| AppError::InternalError("Couldn't create the user".into()))?;
+    // Linking can still fail, say if the same provider account is being signed up twice at once.
+    // Don't leave a user behind that nothing can log in as.
+    if let Err(e) = backend.link_oauth_account(user.id, &provider.name, subject, email.clone()).await {
+        backend.delete_user(user.id).await?;
+        return Err(e);
+    }
+    if email.is_some() {
+        backend.mark_email_verified(user.id).await?;
+    }
+    // Read it back so that the email_verified flag is right.
+    backend.find_user_by_id(user.id).await?.ok_or(AppError::NotFound)
+}
@@ -0,0 +1,68 @@
+use leptos::prelude::*;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use tracing::info;
+        use axum_login::AuthSession;
+        use crate::account_data::delete_sessions;
+        use crate::auth_events::AuthEventKind;
+        use crate::backend::AppBackend;
+        use crate::server::logout_user;
+        use crate::state::{AppState, ClientInfo};
+    }
+}
+
+use crate::error_template::AppError;
+
+/// Give the logged-in user a new username. The session remembers the user by their database id,
+/// so they stay logged in here and everywhere else.
+#[server(name=ChangeUsername, prefix="/api", endpoint="change_username")]
+pub async fn change_username(username: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let username = username.trim().to_string();
+    auth.backend.change_username(user.id, username.clone()).await?;
+    info!(user_id = user.id, old_username = %user.username, new_username = %username, "Renamed a user");
+    Ok(())
+}
+
+/// Change the logged-in user's password. They have to give their current one too, so a browser
+/// somebody left logged in isn't enough to take over the account. The new one has to follow the
+/// password policy; if it doesn't, the error lists what's wrong with it.
+///
+/// Changing the password rotates the user's session secret, which logs out every session they
+/// have, this one included. So this logs the user right back in here with their new secret, and
+/// only the other sessions stay logged out.
+#[server(name=ChangePassword, prefix="/api", endpoint="change_password")]
+pub async fn change_password(current_password: String, new_password: String) -> Result<(), AppError> {
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    auth.backend.change_password(user.id, &current_password, new_password).await?;
+    let user = auth.backend.find_user_by_id(user.id).await?.ok_or(AppError::NotFound)?;
+    auth.login(&user).await
+        .map_err(|e| AppError::InternalError(format!("Log in: {e}")))?;
+    let client: ClientInfo = use_context().expect("client info not provided");
+    auth.backend.record_event(AuthEventKind::PasswordChanged, Some(user.id), Some(&user.username), Some(&client), None).await;
+    info!(user_id = user.id, username = %user.username, "Changed a password");
+    Ok(())
+}
+
+/// Delete the logged-in user's account for good, after they type their password again. The users
+/// row goes, along with everything that hangs off it (roles, tokens, TOTP, passkeys, linked OAuth
+/// accounts). Then every session they had is removed from the session store, and this one is
+/// logged out like a normal logout.
+#[server(name=DeleteAccount, prefix="/api", endpoint="delete_account")]
+pub async fn delete_account(password: String) -> Result<(), AppError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    // The list of sessions goes along with the user, so it has to be read first.
+    let sessions = auth.backend.list_sessions(user.id).await?;
+    auth.backend.delete_account(user.id, &password).await?;
+    // The user is gone already, so if this fails the sessions still can't be used; they just
+    // stay in the table until they expire.
+    let sessions = delete_sessions(&app_state, &sessions).await?;
+    info!(user_id = user.id, username = %user.username, sessions, "Deleted an account");
+    logout_user().await
+        .map_err(|e| AppError::InternalError(format!("Log out: {e}")))
+}
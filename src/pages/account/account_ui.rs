@@ -0,0 +1,149 @@
+use leptos::prelude::*;
+use leptos::either::{Either, EitherOf3};
+use crate::error_template::AppError;
+use crate::server::require_login;
+use super::{ChangePassword, ChangeUsername, DeleteAccount};
+
+/// The logged-in user's account settings: their username and their password, downloading
+/// everything we know about them, and deleting the account.
+#[component]
+pub fn AccountSettings() -> impl IntoView {
+    let rename:ServerAction<ChangeUsername> = ServerAction::new();
+    let change_password:ServerAction<ChangePassword> = ServerAction::new();
+    let delete:ServerAction<DeleteAccount> = ServerAction::new();
+    // There's nothing left to see here once the account is gone.
+    Effect::new(move || {
+        if let Some(Ok(())) = delete.value().get() {
+            let nav = leptos_router::hooks::use_navigate();
+            nav("/", Default::default());
+        }
+    });
+    let user = Resource::new(move || rename.version().get(), |_| require_login(None));
+
+    let rename_result = move || match rename.value().get() {
+        Some(Ok(())) => Some(Either::Left(view! { <p class="text-sm">"Your username has been changed."</p> })),
+        Some(Err(e)) => Some(Either::Right(view! { <p class="text-sm text-red-600">{e.to_string()}</p> })),
+        None => None,
+    };
+
+    // Same as on the Register page: a password that breaks the policy gets every broken rule
+    // listed.
+    let password_result = move || match change_password.value().get() {
+        Some(Ok(())) => Some(EitherOf3::A(view! {
+            <p class="text-sm">"Your password has been changed. Any other sessions you had are logged out."</p>
+        })),
+        Some(Err(AppError::WeakPassword(violations))) => Some(EitherOf3::B(view! {
+            <div class="text-sm text-red-600">
+                <p>"That password won't do:"</p>
+                <ul class="list-disc pl-5">
+                    {violations.into_iter().map(|v| view! { <li>{v.to_string()}</li> }).collect_view()}
+                </ul>
+            </div>
+        })),
+        Some(Err(e)) => Some(EitherOf3::C(view! { <p class="text-sm text-red-600">{e.to_string()}</p> })),
+        None => None,
+    };
+
+    let content = move || Suspend::new(async move {
+        match user.await {
+            Ok(Some(user)) => Either::Left(view! {
+                <ActionForm action=rename>
+                    <div class="space-y-2">
+                        <label for="username" class="block text-sm font-medium leading-6 text-gray-900">
+                            "Username"
+                        </label>
+                        <input
+                            id="username"
+                            name="username"
+                            type="text"
+                            autocomplete="username"
+                            required
+                            value=user.username
+                            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                        />
+                        <input
+                            type="submit"
+                            class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                            value="change username"
+                        />
+                    </div>
+                </ActionForm>
+                {rename_result}
+                <ActionForm action=change_password>
+                    <div class="space-y-2">
+                        <label for="current_password" class="block text-sm font-medium leading-6 text-gray-900">
+                            "Current password"
+                        </label>
+                        <input
+                            id="current_password"
+                            name="current_password"
+                            type="password"
+                            autocomplete="current-password"
+                            required
+                            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                        />
+                        <label for="new_password" class="block text-sm font-medium leading-6 text-gray-900">
+                            "New password"
+                        </label>
+                        <input
+                            id="new_password"
+                            name="new_password"
+                            type="password"
+                            autocomplete="new-password"
+                            required
+                            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                        />
+                        <input
+                            type="submit"
+                            class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                            value="change password"
+                        />
+                    </div>
+                </ActionForm>
+                {password_result}
+                <div class="space-y-2">
+                    <h3 class="text-lg font-semibold text-gray-900">"Your data"</h3>
+                    // rel="external" keeps the router from trying to handle this as a page.
+                    <a href="/account/export" rel="external" download class="text-indigo-600">
+                        "Download everything we have about you (JSON)"
+                    </a>
+                </div>
+                <ActionForm action=delete>
+                    <div class="space-y-2">
+                        <h3 class="text-lg font-semibold text-gray-900">"Delete your account"</h3>
+                        <p class="text-sm">"This can't be undone. Type your password to confirm."</p>
+                        <input
+                            id="delete_password"
+                            name="password"
+                            type="password"
+                            autocomplete="current-password"
+                            required
+                            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                        />
+                        <input
+                            type="submit"
+                            class="rounded-md bg-red-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-red-500"
+                            value="delete my account"
+                        />
+                    </div>
+                </ActionForm>
+                {move || delete.value().get().and_then(|r| r.err()).map(|e| view! {
+                    <p class="text-sm text-red-600">{e.to_string()}</p>
+                })}
+            }),
+            _ => Either::Right(view! { <p>"You have to be logged in for this."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Account settings"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Account settings
+                </h2>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,2 @@
+mod account_ui; pub use self::account_ui::*;
+mod account_server; pub use self::account_server::*;
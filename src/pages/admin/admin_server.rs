@@ -0,0 +1,315 @@
+use leptos::prelude::*;
+use serde::{Serialize,Deserialize};
+use crate::auth_events::AuthEventKind;
+use crate::error_template::AppError;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use tracing::info;
+        use axum_login::{AuthSession, AuthzBackend};
+        use crate::account_data::delete_sessions;
+        use crate::backend::AppBackend;
+        use crate::mail::Mail;
+        use crate::server::ensure_permission;
+        use crate::state::{AppState, ClientInfo};
+        use crate::store::AuthEventFilter;
+        use crate::user::{DatabaseId, User};
+
+        /// Session key that's only there while an admin is impersonating somebody. It holds the
+        /// admin's own user id, for going back.
+        const IMPERSONATOR_KEY: &str = "impersonator";
+
+        /// The user an admin action is aimed at. Admins can't aim these at themselves; there's the
+        /// account page for that, and it's too easy to lock yourself out of the console otherwise.
+        async fn target(auth: &AuthSession<AppBackend>, admin: &User, user_id: DatabaseId) -> Result<User, AppError> {
+            if user_id == admin.id {
+                return Err(AppError::InvalidData("You can't do that to your own account here".into()));
+            }
+            auth.backend.find_user_by_id(user_id).await?.ok_or(AppError::NotFound)
+        }
+
+        /// Log the session in as `user` instead of whoever it is now. Logging in gives the session
+        /// a new id, so the Active sessions entry for the old id goes; the next request makes one
+        /// for the new id, under the new user.
+        async fn switch_user(auth: &mut AuthSession<AppBackend>, session: &tower_sessions::Session, user: &User) -> Result<(), AppError> {
+            if let Some(session_id) = session.id() {
+                auth.backend.forget_session(&session_id.to_string()).await?;
+            }
+            auth.login(user).await
+                .map_err(|e| AppError::InternalError(format!("Log in: {e}")))
+        }
+    }
+}
+
+/// The permission everything in the admin console needs. The `admin_users` migration gives it to
+/// the `admin` role.
+pub const ADMIN_USERS_PERMISSION: &str = "admin.users";
+
+/// How many users, or audit log entries, to a page
+pub const ADMIN_PAGE_SIZE: i64 = 25;
+
+/// A user as the admin console lists them.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AdminUserRow {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub disabled: bool,
+}
+
+/// One page of a user search, and how many users matched in all.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct UserPage {
+    pub users: Vec<AdminUserRow>,
+    pub total: i64,
+}
+
+/// An entry in the admin audit log.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AuditRecord {
+    pub id: i64,
+    /// Unix timestamp
+    pub created_at: i64,
+    pub actor_name: String,
+    pub action: String,
+    pub target_name: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// One page of the audit log, newest first, and how many entries there are in all.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditRecord>,
+    pub total: i64,
+}
+
+/// An entry in the authentication event log (see auth_events.rs).
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AuthEventRecord {
+    pub id: i64,
+    /// Unix timestamp
+    pub created_at: i64,
+    pub kind: AuthEventKind,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// One page of the authentication event log, newest first, and how many events matched in all.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AuthEventPage {
+    pub events: Vec<AuthEventRecord>,
+    pub total: i64,
+}
+
+/// Search the users by username or email address. `page` counts from 0. Searching isn't audited,
+/// but everything below is.
+#[server(name=AdminSearchUsers, prefix="/api", endpoint="admin/search_users")]
+pub async fn admin_search_users(query: String, page: i64) -> Result<UserPage, AppError> {
+    ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let (users, total) = auth.backend.search_users(&query, page.max(0).saturating_mul(ADMIN_PAGE_SIZE), ADMIN_PAGE_SIZE).await?;
+    Ok(UserPage {
+        users: users.into_iter()
+            .map(|user| AdminUserRow {
+                id: user.id,
+                username: user.username,
+                email: user.email,
+                email_verified: user.verified_at.is_some(),
+                disabled: user.disabled_at.is_some(),
+            })
+            .collect(),
+        total,
+    })
+}
+
+/// Make a new user. The same rules apply as when somebody registers, password policy included.
+#[server(name=AdminCreateUser, prefix="/api", endpoint="admin/create_user")]
+pub async fn admin_create_user(username: String, password: String, email: String) -> Result<(), AppError> {
+    let admin = ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let email = Some(email.trim().to_string()).filter(|e| !e.is_empty());
+    let user = auth.backend.add_user(username.trim().to_string(), password, email).await?
+        .ok_or_else(|| AppError::InternalError("The new user went missing".into()))?;
+    auth.backend.audit(&admin, "create_user", Some((user.id, &user.username)), None).await?;
+    let client: ClientInfo = use_context().expect("client info not provided");
+    let detail = format!("added by {}", admin.username);
+    auth.backend.record_event(AuthEventKind::Registered, Some(user.id), Some(&user.username), Some(&client), Some(&detail)).await;
+    info!(admin = %admin.username, user_id = user.id, username = %user.username, "Admin created a user");
+    Ok(())
+}
+
+/// Make a user pick a new password: the old one stops working, they're logged out everywhere, and
+/// they get mailed a reset link, the same one as from "forgot password". Users without an email
+/// address can't be reset this way.
+#[server(name=AdminForcePasswordReset, prefix="/api", endpoint="admin/force_password_reset")]
+pub async fn admin_force_password_reset(user_id: i64) -> Result<(), AppError> {
+    let admin = ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    let user = target(&auth, &admin, user_id).await?;
+    // Check before the password gets thrown away, since without an address the link can't go
+    // anywhere and the user would be locked out.
+    let Some(email) = user.email.clone() else {
+        return Err(AppError::InvalidData("This user has no email address".into()));
+    };
+    let token = auth.backend.force_password_reset(user.id).await?;
+    auth.backend.audit(&admin, "force_password_reset", Some((user.id, &user.username)), None).await?;
+    let link = format!("{}/reset-password?token={token}", app_state.server_config.public_url);
+    let minutes = app_state.server_config.password_reset_token_ttl_seconds / 60;
+    app_state.mailer.send(&Mail {
+        to: email,
+        subject: "Please pick a new password".into(),
+        body: format!("An administrator has reset the password for {}, so you'll need a new one.\n\n\
+            Follow this link within {minutes} minutes to pick it:\n\n{link}", user.username),
+    }).await?;
+    Ok(())
+}
+
+/// Disable a user (they're logged out everywhere and can't log back in) or enable them again.
+#[server(name=AdminSetDisabled, prefix="/api", endpoint="admin/set_disabled")]
+pub async fn admin_set_disabled(user_id: i64, disabled: bool) -> Result<(), AppError> {
+    let admin = ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = target(&auth, &admin, user_id).await?;
+    auth.backend.set_disabled(user.id, disabled).await?;
+    let action = if disabled { "disable_user" } else { "enable_user" };
+    auth.backend.audit(&admin, action, Some((user.id, &user.username)), None).await?;
+    Ok(())
+}
+
+/// Delete a user and everything of theirs, sessions included, the same as when users delete
+/// themselves.
+#[server(name=AdminDeleteUser, prefix="/api", endpoint="admin/delete_user")]
+pub async fn admin_delete_user(user_id: i64) -> Result<(), AppError> {
+    let admin = ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    let user = target(&auth, &admin, user_id).await?;
+    // The list of sessions goes along with the user, so it has to be read first.
+    let sessions = auth.backend.list_sessions(user.id).await?;
+    auth.backend.delete_user(user.id).await?;
+    let sessions = delete_sessions(&app_state, &sessions).await?;
+    auth.backend.audit(&admin, "delete_user", Some((user.id, &user.username)), Some(format!("{sessions} sessions ended"))).await?;
+    Ok(())
+}
+
+/// Log in as somebody else, to see what they see. The admin's own id is kept in the session, so
+/// `stop_impersonating` can switch back. Other admins can't be impersonated, or this would be a
+/// way to borrow their permissions.
+#[server(name=ImpersonateUser, prefix="/api", endpoint="admin/impersonate")]
+pub async fn impersonate_user(user_id: i64) -> Result<(), AppError> {
+    let admin = ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let user = target(&auth, &admin, user_id).await?;
+    if auth.backend.has_perm(&user, ADMIN_USERS_PERMISSION.to_string()).await? {
+        return Err(AppError::Forbidden("Other admins can't be impersonated".into()));
+    }
+    if auth.backend.is_disabled(user.id).await? {
+        return Err(AppError::AccountDisabled);
+    }
+    // Audit first; if that doesn't work, neither does this.
+    auth.backend.audit(&admin, "impersonate", Some((user.id, &user.username)), None).await?;
+    session.insert(IMPERSONATOR_KEY, admin.id).await
+        .map_err(|e| AppError::InternalError(format!("Save impersonator: {e}")))?;
+    switch_user(&mut auth, &session, &user).await?;
+    info!(admin = %admin.username, user_id = user.id, username = %user.username, "Admin is impersonating a user");
+    Ok(())
+}
+
+/// Go back to being the admin who started impersonating. The admin gets checked again, in case
+/// they were disabled or lost the permission in the meantime.
+#[server(name=StopImpersonating, prefix="/api", endpoint="admin/stop_impersonating")]
+pub async fn stop_impersonating() -> Result<(), AppError> {
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let admin_id = session.remove::<DatabaseId>(IMPERSONATOR_KEY).await
+        .map_err(|e| AppError::InternalError(format!("Read impersonator: {e}")))?
+        .ok_or_else(|| AppError::InvalidData("You aren't impersonating anybody".into()))?;
+    let impersonated = auth.user.clone();
+    let admin = auth.backend.find_user_by_id(admin_id).await?;
+    let admin = match admin {
+        Some(admin) if !auth.backend.is_disabled(admin.id).await?
+            && auth.backend.has_perm(&admin, ADMIN_USERS_PERMISSION.to_string()).await? => admin,
+        // Whoever this was isn't an admin anymore, so they don't get to be anybody.
+        _ => {
+            auth.logout().await
+                .map_err(|e| AppError::InternalError(format!("Log out: {e}")))?;
+            return Err(AppError::Forbidden(ADMIN_USERS_PERMISSION.into()));
+        },
+    };
+    switch_user(&mut auth, &session, &admin).await?;
+    let target = impersonated.as_ref().map(|user| (user.id, user.username.as_str()));
+    auth.backend.audit(&admin, "stop_impersonating", target, None).await?;
+    Ok(())
+}
+
+/// The username of the admin who's impersonating the current user, if that's what's going on.
+/// The banner at the top of every page asks this.
+#[server(name=ImpersonatedBy, prefix="/api", endpoint="impersonated_by")]
+pub async fn impersonated_by() -> Result<Option<String>, AppError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let admin_id = session.get::<DatabaseId>(IMPERSONATOR_KEY).await
+        .map_err(|e| AppError::InternalError(format!("Read impersonator: {e}")))?;
+    let Some(admin_id) = admin_id else {
+        return Ok(None);
+    };
+    Ok(auth.backend.find_user_by_id(admin_id).await?.map(|admin| admin.username))
+}
+
+/// Page through the admin audit log, newest first. `page` counts from 0.
+#[server(name=AdminAuditLog, prefix="/api", endpoint="admin/audit_log")]
+pub async fn admin_audit_log(page: i64) -> Result<AuditPage, AppError> {
+    ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let (entries, total) = auth.backend.list_audit(page.max(0).saturating_mul(ADMIN_PAGE_SIZE), ADMIN_PAGE_SIZE).await?;
+    Ok(AuditPage {
+        entries: entries.into_iter()
+            .map(|entry| AuditRecord {
+                id: entry.id,
+                created_at: entry.created_at,
+                actor_name: entry.actor_name,
+                action: entry.action,
+                target_name: entry.target_name,
+                detail: entry.detail,
+            })
+            .collect(),
+        total,
+    })
+}
+
+/// Page through the authentication event log, newest first. Each filter left empty matches
+/// everything: `kind` is one of `AuthEventKind::as_str`, `username` matches usernames containing
+/// it, and `ip` has to match exactly. `page` counts from 0.
+#[server(name=AdminAuthEvents, prefix="/api", endpoint="admin/auth_events")]
+pub async fn admin_auth_events(kind: String, username: String, ip: String, page: i64) -> Result<AuthEventPage, AppError> {
+    ensure_permission(ADMIN_USERS_PERMISSION).await?;
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let given = |s: String| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+    let filter = AuthEventFilter {
+        kind: given(kind),
+        user_id: None,
+        username: given(username),
+        ip: given(ip),
+    };
+    let (events, total) = auth.backend.list_auth_events(&filter, page.max(0).saturating_mul(ADMIN_PAGE_SIZE), ADMIN_PAGE_SIZE).await?;
+    Ok(AuthEventPage {
+        events: events.into_iter()
+            // Anything that isn't a kind this version knows about can't be shown properly anyway.
+            .filter_map(|event| Some(AuthEventRecord {
+                id: event.id,
+                created_at: event.created_at,
+                kind: AuthEventKind::parse(&event.kind)?,
+                username: event.username,
+                ip: event.ip,
+                user_agent: event.user_agent,
+                detail: event.detail,
+            }))
+            .collect(),
+        total,
+    })
+}
@@ -0,0 +1,400 @@
+use leptos::prelude::*;
+use leptos::either::{Either, EitherOf3};
+use leptos_router::hooks::use_navigate;
+use crate::error_template::AppError;
+use crate::pages::when;
+use crate::server::require_permission;
+use crate::auth_events::AuthEventKind;
+use super::{
+    admin_audit_log, admin_auth_events, admin_search_users, impersonated_by, AdminCreateUser, AdminDeleteUser,
+    AdminForcePasswordReset, AdminSetDisabled, ImpersonateUser, StopImpersonating,
+    ADMIN_PAGE_SIZE, ADMIN_USERS_PERMISSION,
+};
+
+const INPUT_CLASS: &str = "block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6";
+const BUTTON_CLASS: &str = "rounded-md bg-gray-200 px-2 py-1 text-xs font-semibold text-gray-900 shadow-sm hover:bg-gray-300 cursor-pointer";
+
+/// "previous" and "next" buttons for a paged list. `page` counts from 0.
+#[component]
+fn Pager(page: RwSignal<i64>, total: i64) -> impl IntoView {
+    let pages = ((total + ADMIN_PAGE_SIZE - 1) / ADMIN_PAGE_SIZE).max(1);
+    view! {
+        <div class="flex items-center gap-2 text-sm">
+            <button
+                class=BUTTON_CLASS
+                disabled=move || page.get() == 0
+                on:click=move |_| page.update(|p| *p -= 1)
+            >"previous"</button>
+            <span>"Page " {move || page.get() + 1} " of " {pages} " (" {total} " in all)"</span>
+            <button
+                class=BUTTON_CLASS
+                disabled=move || page.get() + 1 >= pages
+                on:click=move |_| page.update(|p| *p += 1)
+            >"next"</button>
+        </div>
+    }
+}
+
+/// The admin console's user list: search by username or email, a page of users at a time, and
+/// buttons to force a password reset, disable or enable, delete or impersonate each one, plus a
+/// form to make new users. Everything here is checked again on the server (see admin_server.rs)
+/// and lands in the audit log.
+#[component]
+pub fn AdminUsers() -> impl IntoView {
+    let create:ServerAction<AdminCreateUser> = ServerAction::new();
+    let force_reset:ServerAction<AdminForcePasswordReset> = ServerAction::new();
+    let set_disabled:ServerAction<AdminSetDisabled> = ServerAction::new();
+    let delete:ServerAction<AdminDeleteUser> = ServerAction::new();
+    let impersonate:ServerAction<ImpersonateUser> = ServerAction::new();
+    // Once the session belongs to somebody else, this page isn't theirs to see.
+    Effect::new(move || {
+        if let Some(Ok(())) = impersonate.value().get() {
+            let nav = use_navigate();
+            nav("/", Default::default());
+        }
+    });
+
+    let admin = Resource::new(|| (), |_| require_permission(ADMIN_USERS_PERMISSION.into(), None));
+    let query = RwSignal::new(String::new());
+    let page = RwSignal::new(0);
+    // Anything that changes a user means the list needs fetching again.
+    let changes = Memo::new(move |_| {
+        create.version().get() + set_disabled.version().get() + delete.version().get()
+    });
+    let users = Resource::new(
+        move || (query.get(), page.get(), changes.get()),
+        |(query, page, _)| admin_search_users(query, page),
+    );
+
+    let message = move || {
+        let error = [
+            force_reset.value().get(),
+            set_disabled.value().get(),
+            delete.value().get(),
+            impersonate.value().get(),
+        ].into_iter().flatten().find_map(Result::err);
+        match (error, force_reset.value().get()) {
+            (Some(e), _) => Some(Either::Left(view! { <p class="text-sm text-red-600">{e.to_string()}</p> })),
+            (None, Some(Ok(()))) => Some(Either::Right(view! { <p class="text-sm">"The reset link is on its way."</p> })),
+            _ => None,
+        }
+    };
+
+    let create_result = move || match create.value().get() {
+        Some(Ok(())) => Some(EitherOf3::A(view! { <p class="text-sm">"The user has been created."</p> })),
+        Some(Err(AppError::WeakPassword(violations))) => Some(EitherOf3::B(view! {
+            <div class="text-sm text-red-600">
+                <p>"That password won't do:"</p>
+                <ul class="list-disc pl-5">
+                    {violations.into_iter().map(|v| view! { <li>{v.to_string()}</li> }).collect_view()}
+                </ul>
+            </div>
+        })),
+        Some(Err(e)) => Some(EitherOf3::C(view! { <p class="text-sm text-red-600">{e.to_string()}</p> })),
+        None => None,
+    };
+
+    let list = move || Suspend::new(async move {
+        match users.await {
+            Ok(found) if found.users.is_empty() => EitherOf3::A(view! { <p>"Nobody matches that."</p> }),
+            Ok(found) => EitherOf3::B(view! {
+                <table class="w-full text-sm">
+                    <thead>
+                        <tr class="text-left">
+                            <th>"Username"</th>
+                            <th>"Email"</th>
+                            <th>"Status"</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {found.users.into_iter().map(|user| view! {
+                            <tr class="align-top">
+                                <td class="font-semibold">{user.username}</td>
+                                <td>
+                                    {user.email.unwrap_or_default()}
+                                    {(!user.email_verified).then(|| view! { <span class="text-gray-500">" (unverified)"</span> })}
+                                </td>
+                                <td>{if user.disabled { "disabled" } else { "active" }}</td>
+                                <td class="flex flex-wrap gap-1">
+                                    <ActionForm action=force_reset>
+                                        <input type="hidden" name="user_id" value=user.id/>
+                                        <input type="submit" class=BUTTON_CLASS value="force reset"/>
+                                    </ActionForm>
+                                    <ActionForm action=set_disabled>
+                                        <input type="hidden" name="user_id" value=user.id/>
+                                        <input type="hidden" name="disabled" value={(!user.disabled).to_string()}/>
+                                        <input type="submit" class=BUTTON_CLASS value={if user.disabled { "enable" } else { "disable" }}/>
+                                    </ActionForm>
+                                    <ActionForm action=impersonate>
+                                        <input type="hidden" name="user_id" value=user.id/>
+                                        <input type="submit" class=BUTTON_CLASS value="impersonate"/>
+                                    </ActionForm>
+                                    <ActionForm action=delete>
+                                        <input type="hidden" name="user_id" value=user.id/>
+                                        <input
+                                            type="submit"
+                                            class="rounded-md bg-red-600 px-2 py-1 text-xs font-semibold text-white shadow-sm hover:bg-red-500 cursor-pointer"
+                                            value="delete"
+                                        />
+                                    </ActionForm>
+                                </td>
+                            </tr>
+                        }).collect_view()}
+                    </tbody>
+                </table>
+                <Pager page=page total=found.total/>
+            }),
+            Err(e) => EitherOf3::C(view! { <p class="text-red-600">{e.to_string()}</p> }),
+        }
+    });
+
+    let content = move || Suspend::new(async move {
+        match admin.await {
+            Ok(Some(_)) => Either::Left(view! {
+                <input
+                    id="search"
+                    type="search"
+                    placeholder="Search by username or email"
+                    class=INPUT_CLASS
+                    prop:value=query
+                    on:input=move |ev| {
+                        query.set(event_target_value(&ev));
+                        page.set(0);
+                    }
+                />
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{list}</Transition>
+                {message}
+                <ActionForm action=create>
+                    <div class="space-y-2">
+                        <h3 class="text-lg font-semibold text-gray-900">"Add a user"</h3>
+                        <input id="new_username" name="username" type="text" placeholder="Username" required class=INPUT_CLASS/>
+                        <input id="new_email" name="email" type="email" placeholder="Email (optional)" class=INPUT_CLASS/>
+                        <input
+                            id="new_password"
+                            name="password"
+                            type="password"
+                            autocomplete="new-password"
+                            placeholder="Password"
+                            required
+                            class=INPUT_CLASS
+                        />
+                        <input
+                            type="submit"
+                            class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                            value="add user"
+                        />
+                    </div>
+                </ActionForm>
+                {create_result}
+                <p><a href="/admin/audit" class="text-indigo-600">"Audit log"</a></p>
+                <p><a href="/admin/auth-events" class="text-indigo-600">"Logins and other account events"</a></p>
+            }),
+            _ => Either::Right(view! { <p>"You aren't allowed in here."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Users"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-3xl space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Users
+                </h2>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
+
+/// The admin audit log, newest first: who did what to whom, and when.
+#[component]
+pub fn AdminAudit() -> impl IntoView {
+    let admin = Resource::new(|| (), |_| require_permission(ADMIN_USERS_PERMISSION.into(), None));
+    let page = RwSignal::new(0);
+    let entries = Resource::new(move || page.get(), admin_audit_log);
+
+    let list = move || Suspend::new(async move {
+        match entries.await {
+            Ok(log) if log.entries.is_empty() => EitherOf3::A(view! { <p>"Nothing has happened yet."</p> }),
+            Ok(log) => EitherOf3::B(view! {
+                <table class="w-full text-sm">
+                    <thead>
+                        <tr class="text-left">
+                            <th>"When"</th>
+                            <th>"Who"</th>
+                            <th>"Did"</th>
+                            <th>"To"</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {log.entries.into_iter().map(|entry| view! {
+                            <tr>
+                                <td>{when(entry.created_at)}</td>
+                                <td>{entry.actor_name}</td>
+                                <td>{entry.action}</td>
+                                <td>{entry.target_name.unwrap_or_default()}</td>
+                                <td class="text-gray-500">{entry.detail.unwrap_or_default()}</td>
+                            </tr>
+                        }).collect_view()}
+                    </tbody>
+                </table>
+                <Pager page=page total=log.total/>
+            }),
+            Err(e) => EitherOf3::C(view! { <p class="text-red-600">{e.to_string()}</p> }),
+        }
+    });
+
+    let content = move || Suspend::new(async move {
+        match admin.await {
+            Ok(Some(_)) => Either::Left(view! {
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{list}</Transition>
+                <p><a href="/admin/users" class="text-indigo-600">"Users"</a></p>
+            }),
+            _ => Either::Right(view! { <p>"You aren't allowed in here."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Audit log"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-3xl space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Audit log
+                </h2>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
+
+/// The authentication event log, newest first: logins (and failed ones), registrations, logouts,
+/// password changes and lockouts, filtered by kind, username and IP address.
+#[component]
+pub fn AdminAuthEventLog() -> impl IntoView {
+    let admin = Resource::new(|| (), |_| require_permission(ADMIN_USERS_PERMISSION.into(), None));
+    let kind = RwSignal::new(String::new());
+    let username = RwSignal::new(String::new());
+    let ip = RwSignal::new(String::new());
+    let page = RwSignal::new(0);
+    let events = Resource::new(
+        move || (kind.get(), username.get(), ip.get(), page.get()),
+        |(kind, username, ip, page)| admin_auth_events(kind, username, ip, page),
+    );
+    // Changing a filter starts over from the first page.
+    let set_filter = move |signal: RwSignal<String>, value: String| {
+        signal.set(value);
+        page.set(0);
+    };
+
+    let list = move || Suspend::new(async move {
+        match events.await {
+            Ok(log) if log.events.is_empty() => EitherOf3::A(view! { <p>"No events match that."</p> }),
+            Ok(log) => EitherOf3::B(view! {
+                <table class="w-full text-sm">
+                    <thead>
+                        <tr class="text-left">
+                            <th>"When"</th>
+                            <th>"What"</th>
+                            <th>"Who"</th>
+                            <th>"From"</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {log.events.into_iter().map(|event| view! {
+                            <tr class="align-top">
+                                <td>{when(event.created_at)}</td>
+                                <td>{event.kind.to_string()}</td>
+                                <td>{event.username.unwrap_or_default()}</td>
+                                <td title=event.user_agent.unwrap_or_default()>{event.ip.unwrap_or_default()}</td>
+                                <td class="text-gray-500">{event.detail.unwrap_or_default()}</td>
+                            </tr>
+                        }).collect_view()}
+                    </tbody>
+                </table>
+                <Pager page=page total=log.total/>
+            }),
+            Err(e) => EitherOf3::C(view! { <p class="text-red-600">{e.to_string()}</p> }),
+        }
+    });
+
+    let content = move || Suspend::new(async move {
+        match admin.await {
+            Ok(Some(_)) => Either::Left(view! {
+                <div class="flex gap-2">
+                    <select
+                        id="event_kind"
+                        class=INPUT_CLASS
+                        on:change=move |ev| set_filter(kind, event_target_value(&ev))
+                    >
+                        <option value="">"Everything"</option>
+                        {AuthEventKind::ALL.into_iter().map(|k| view! {
+                            <option value=k.as_str()>{k.to_string()}</option>
+                        }).collect_view()}
+                    </select>
+                    <input
+                        id="event_username"
+                        type="search"
+                        placeholder="Username"
+                        class=INPUT_CLASS
+                        prop:value=username
+                        on:input=move |ev| set_filter(username, event_target_value(&ev))
+                    />
+                    <input
+                        id="event_ip"
+                        type="search"
+                        placeholder="IP address"
+                        class=INPUT_CLASS
+                        prop:value=ip
+                        on:input=move |ev| set_filter(ip, event_target_value(&ev))
+                    />
+                </div>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{list}</Transition>
+                <p><a href="/admin/users" class="text-indigo-600">"Users"</a></p>
+            }),
+            _ => Either::Right(view! { <p>"You aren't allowed in here."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Account events"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-3xl space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Account events
+                </h2>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
+
+/// A reminder, while an admin is impersonating somebody, of who they really are, with a button
+/// to go back to that. It renders nothing the rest of the time.
+#[component]
+pub fn ImpersonationBanner() -> impl IntoView {
+    let stop:ServerAction<StopImpersonating> = ServerAction::new();
+    Effect::new(move || {
+        if let Some(Ok(())) = stop.value().get() {
+            let nav = use_navigate();
+            nav("/admin/users", Default::default());
+        }
+    });
+    let admin = Resource::new(|| (), |_| impersonated_by());
+    view! {
+        <Suspense fallback=|| ()>
+            {move || Suspend::new(async move {
+                admin.await.ok().flatten().map(|admin| view! {
+                    <div id="impersonation-banner" class="flex items-center gap-2 bg-yellow-100 px-4 py-2 text-sm">
+                        <span>"You're really " <b>{admin}</b> ", impersonating this user."</span>
+                        <ActionForm action=stop>
+                            <input type="submit" class=BUTTON_CLASS value="stop impersonating"/>
+                        </ActionForm>
+                    </div>
+                })
+            })}
+        </Suspense>
+    }
+}
@@ -0,0 +1,2 @@
+mod admin_ui; pub use self::admin_ui::*;
+mod admin_server; pub use self::admin_server::*;
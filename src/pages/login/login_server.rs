@@ -0,0 +1,25 @@
+use leptos::prelude::*;
+use serde::{Serialize,Deserialize};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use crate::config::ServerConfig;
+    }
+}
+
+/// Just enough about an OAuth provider to put a button for it on the login page. The rest of the
+/// provider config (the client secret, in particular) stays on the server.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct OAuthProviderInfo {
+    pub name: String,
+    pub display_name: String,
+}
+
+/// List the OAuth providers from `server_config.toml` that people can log in with.
+#[server(name=ListOAuthProviders, prefix="/api", endpoint="list_oauth_providers")]
+pub async fn list_oauth_providers() -> Result<Vec<OAuthProviderInfo>, ServerFnError> {
+    let config: ServerConfig = use_context().expect("server config not provided");
+    Ok(config.oauth_providers.iter()
+        .map(|p| OAuthProviderInfo{ name: p.name.clone(), display_name: p.display_name.clone() })
+        .collect())
+}
@@ -3,7 +3,9 @@ use leptos::prelude::*;
 use leptos::either::Either;
 use leptos_router::hooks::{use_navigate, use_query_map};
 use crate::auth::get_user;
-use crate::server::LoginUser;
+use crate::server::{second_factor_pending, CompleteLogin, LoginOutcome, LoginUser};
+use crate::pages::{login_with_passkey, RequestMagicLink};
+use super::list_oauth_providers;
 
 
 /// Render a styled login form adapted from the tailwindui.com simple login form. It provides
@@ -15,13 +17,20 @@ pub fn Login() -> impl IntoView {
     let qmap = use_query_map();
     // This will call auth::login_user
     let login:ServerAction<LoginUser> = ServerAction::new();
+    // For people who'd rather get a login link by mail, see pages/magic_link
+    let magic:ServerAction<RequestMagicLink> = ServerAction::new();
+    // This one is for the second step of a two-factor login, see server::complete_login
+    let complete:ServerAction<CompleteLogin> = ServerAction::new();
+    // Passkey logins happen in the browser first, so this one can't be a ServerAction. See
+    // pages/passkeys.
+    let passkey = Action::new_local(|_: &()| login_with_passkey());
     let show_pass = RwSignal::new(false);
     // based on the state of show_pass, this provides the `type=` attribute for the password
     // input.
     let pass_type = move || show_pass.get().then_some("text").or(Some("password")).unwrap();
     // If the user is logged in, return it.
     let logged_in_user = Resource::new(
-        move || login.version().get(), 
+        move || (login.version().get(), complete.version().get(), passkey.version().get()),
         move |_user|  async move {
             if let Ok(Some(user)) = get_user().await {
                 Some(user)
@@ -72,8 +81,96 @@ pub fn Login() -> impl IntoView {
         }
     });
 
-    view! {
-        <leptos_meta::Title text="Log in"></leptos_meta::Title>
+    // Logins through an OAuth provider come back to this page with the second factor still to
+    // go, so ask the server whether that's the case.
+    let pending = Resource::new(move || passkey.version().get(), |_| second_factor_pending());
+    let providers = Resource::new(|| (), |_| list_oauth_providers());
+
+    // The password was right but the server wants a TOTP code too. Keep asking until one works.
+    let needs_code = move || {
+        (matches!(login.value().get(), Some(Ok(LoginOutcome::SecondFactorRequired)))
+            || matches!(pending.get(), Some(Ok(true))))
+            && !matches!(complete.value().get(), Some(Ok(Some(_))))
+    };
+    // Tell the user what went wrong, if anything did. Lockouts come back as server errors, and
+    // the message from the AppError ("Too many attempts, try again in N seconds") is already
+    // fit for humans, so show it without the "error running server function" prefix.
+    let error_message = |e: ServerFnError| match e {
+        ServerFnError::ServerError(msg) => msg,
+        other => other.to_string(),
+    };
+    let message = move || match (login.value().get(), complete.value().get(), passkey.value().get()) {
+        (_, Some(Ok(None)), _) => Some("That code didn't work, try again.".to_string()),
+        (_, Some(Err(e)), _) => Some(error_message(e)),
+        (_, _, Some(Ok(LoginOutcome::Failed))) => Some("That passkey didn't work.".to_string()),
+        (_, _, Some(Err(e))) => Some(e),
+        (Some(Ok(LoginOutcome::Failed)), _, _) => Some("Wrong username or password.".to_string()),
+        (Some(Err(e)), _, _) => Some(error_message(e)),
+        // The OAuth callback sends its errors back in the query string
+        _ => qmap.get().get("oauth_error"),
+    };
+
+    // A button for each OAuth provider. These are plain links to the server, so they need
+    // rel="external" to keep the router from trying to handle them.
+    let provider_links = move || Suspend::new(async move {
+        providers.await.unwrap_or_default()
+            .into_iter()
+            .map(|p| view! {
+                <a
+                    href=format!("/auth/oauth/{}/start", p.name)
+                    rel="external"
+                    class="flex w-full justify-center rounded-md bg-white px-3 py-1.5 text-sm font-semibold leading-6 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 hover:bg-gray-50"
+                >
+                    "Sign in with " {p.display_name}
+                </a>
+            })
+            .collect_view()
+    });
+
+    let second_factor_form = move || view! {
+        <ActionForm action=complete>
+            <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+                <div class="sm:mx-auto sm:w-full sm:max-w-sm">
+                    <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                        Two-factor authentication
+                    </h2>
+                </div>
+
+                <div class="mt-10 sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                    <div>
+                        <label
+                            for="code"
+                            class="flex self-start block text-sm font-medium leading-6 text-gray-900"
+                        >
+                            Code from your authenticator app, or a recovery code
+                        </label>
+                        <div class="mt-2">
+                            <input
+                                id="code"
+                                name="code"
+                                type="text"
+                                autocomplete="one-time-code"
+                                required
+                                class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                            />
+                        </div>
+                    </div>
+
+                    <div>
+                        <input
+                            type="submit"
+                            class=r#"flex w-full justify-center rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold
+                               leading-6 text-white shadow-sm hover:bg-indigo-500 focus-visible:outline 
+                               focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600"#
+                            value="verify"
+                        />
+                    </div>
+                </div>
+            </div>
+        </ActionForm>
+    };
+
+    let password_form = move || view! {
         <ActionForm action=login>
             <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
                 <div class="sm:mx-auto sm:w-full sm:max-w-sm">
@@ -112,7 +209,7 @@ pub fn Login() -> impl IntoView {
                             </label>
                             <div class="text-sm">
                                 <a
-                                    href="#"
+                                    href="/forgot-password"
                                     class="font-semibold text-indigo-600 hover:text-indigo-500"
                                 >
                                     Forgot password?
@@ -135,6 +232,13 @@ pub fn Login() -> impl IntoView {
                         </div>
                     </div>
 
+                    // The value has to be "true" rather than the browser's usual "on" for the
+                    // server function to read it as a bool.
+                    <div class="flex items-center gap-2 text-sm text-gray-900">
+                        <input id="remember" name="remember" type="checkbox" value="true"/>
+                        <label for="remember">"Remember me"</label>
+                    </div>
+
                     <div>
                         <input
                             type="submit"
@@ -145,6 +249,19 @@ pub fn Login() -> impl IntoView {
                         />
                     </div>
 
+                    <button
+                        type="button"
+                        class="flex w-full justify-center rounded-md bg-white px-3 py-1.5 text-sm font-semibold leading-6 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 hover:bg-gray-50"
+                        disabled=move || passkey.pending().get()
+                        on:click=move |_| { passkey.dispatch(()); }
+                    >
+                        "Sign in with a passkey"
+                    </button>
+
+                    <Transition fallback=|| ()>
+                        <div class="space-y-2">{provider_links}</div>
+                    </Transition>
+
                     <p class="mt-10 text-center text-sm text-gray-500">
                         not a member?
                         <a
@@ -157,6 +274,48 @@ pub fn Login() -> impl IntoView {
                 </div>
             </div>
         </ActionForm>
+    };
+
+    // Passwordless option. It's a <details> so it stays out of the way until somebody wants it.
+    let magic_link_form = move || view! {
+        <details class="sm:mx-auto sm:w-full sm:max-w-sm px-6 lg:px-8 text-sm">
+            <summary class="cursor-pointer font-semibold text-indigo-600 hover:text-indigo-500">
+                "Email me a login link instead"
+            </summary>
+            <ActionForm action=magic>
+                <div class="mt-2 space-y-2">
+                    <label for="magic-username" class="block text-sm font-medium leading-6 text-gray-900">
+                        "Username or email address"
+                    </label>
+                    <input
+                        id="magic-username"
+                        name="username"
+                        type="text"
+                        autocomplete="username"
+                        required
+                        class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                    />
+                    <input
+                        type="submit"
+                        class="rounded-md bg-gray-200 px-3 py-1.5 text-sm font-semibold text-gray-900 shadow-sm hover:bg-gray-300"
+                        value="send link"
+                    />
+                </div>
+            </ActionForm>
+            {move || match magic.value().get() {
+                Some(Ok(())) => Some("If that account exists, a login link is on its way. Check your mail.".to_string()),
+                Some(Err(e)) => Some(error_message(e)),
+                None => None,
+            }.map(|m| view! { <p class="mt-2">{m}</p> })}
+        </details>
+    };
+
+    view! {
+        <leptos_meta::Title text="Log in"></leptos_meta::Title>
+        <Show when=needs_code fallback=move || view! { {password_form} {magic_link_form} }>
+            {second_factor_form}
+        </Show>
+        {move || message().map(|m| view! { <p class="text-center text-sm text-red-600">{m}</p> })}
         <Transition fallback=move || view! { <p>Checking login...</p> }>{login_status}</Transition>
     }
 }
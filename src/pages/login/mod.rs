@@ -1,2 +1,2 @@
 mod login_ui; pub use self::login_ui::*;
-
+mod login_server; pub use self::login_server::*;
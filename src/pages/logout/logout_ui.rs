@@ -0,0 +1,31 @@
+
+use leptos::prelude::*;
+use leptos::either::Either;
+use crate::server::logout_user;
+
+/// Visiting `/logout` logs you out, no questions asked. This is for links and bookmarks; inside the
+/// app it's nicer to use the `LogoutButton` component.
+#[component]
+pub fn Logout() -> impl IntoView {
+    let logged_out = Resource::new(|| (), |_| logout_user());
+    let status = move || Suspend::new(async move {
+        match logged_out.await {
+            Ok(()) => Either::Left(view! { <p>"You have been logged out."</p> }),
+            Err(e) => Either::Right(view! { <p>"Logging out failed: " {e.to_string()}</p> }),
+        }
+    });
+    view! {
+        <leptos_meta::Title text="Log out"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm text-center space-y-4">
+                <Suspense fallback=|| view! { <p>"Logging out..."</p> }>{status}</Suspense>
+                <a
+                    href="/login"
+                    class="font-semibold leading-6 text-indigo-600 hover:text-indigo-500"
+                >
+                    "log in again"
+                </a>
+            </div>
+        </div>
+    }
+}
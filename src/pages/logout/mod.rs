@@ -0,0 +1 @@
+mod logout_ui; pub use self::logout_ui::*;
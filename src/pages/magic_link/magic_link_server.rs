@@ -0,0 +1,62 @@
+use leptos::prelude::*;
+use crate::server::LoginOutcome;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use tracing::{debug, info};
+        use axum_login::{AuthSession, AuthnBackend};
+        use crate::auth_events::AuthEventKind;
+        use crate::credentials::Credentials;
+        use crate::server::finish_login;
+        use crate::backend::AppBackend;
+        use crate::state::{AppState, ClientInfo};
+        use crate::mail::Mail;
+    }
+}
+
+/// Mail a one-time login link to the user. `username` can also be the user's email address. Just
+/// like `request_password_reset`, this says "ok" whether or not the user exists, and users without
+/// an email address don't get anything.
+#[server(name=RequestMagicLink, prefix="/api", endpoint="request_magic_link")]
+pub async fn request_magic_link(username: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    let user = match auth.backend.find_user_by_name(&username).await? {
+        Some(user) => Some(user),
+        None if username.contains('@') => auth.backend.find_user_by_email(&username).await?,
+        None => None,
+    };
+    let Some(user) = user else {
+        info!(%username, "Login link requested for an unknown user");
+        return Ok(());
+    };
+    let Some(email) = user.email.clone() else {
+        debug!(user_id = user.id, username = %user.username, "Login link requested for a user with no email address");
+        return Ok(());
+    };
+    let token = auth.backend.issue_magic_link_token(user.id).await?;
+    let link = format!("{}/magic?token={token}", app_state.server_config.public_url);
+    let minutes = app_state.server_config.magic_link_token_ttl_seconds / 60;
+    app_state.mailer.send(&Mail {
+        to: email,
+        subject: "Your login link".into(),
+        body: format!("Somebody (hopefully you) asked for a link to log in as {}.\n\n\
+            Follow this link within {minutes} minutes to log in. It only works once:\n\n{link}\n\n\
+            If it wasn't you, you can ignore this message.", user.username),
+    }).await?;
+    Ok(())
+}
+
+/// Log in with the token from a login link. The token is used up either way. Users with two-factor
+/// auth turned on still have to send a code to `complete_login` afterwards.
+#[server(name=MagicLogin, prefix="/api", endpoint="magic_login")]
+pub async fn magic_login(token: String) -> Result<LoginOutcome, ServerFnError> {
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let client: ClientInfo = use_context().expect("client info not provided");
+    let Some(user) = auth.backend.authenticate(Credentials::MagicLink(token)).await? else {
+        auth.backend.record_event(AuthEventKind::LoginFailed, None, None, Some(&client), Some("login link: invalid or expired")).await;
+        return Ok(LoginOutcome::Failed);
+    };
+    Ok(finish_login(&mut auth, &session, user, &client, "login link", false).await?)
+}
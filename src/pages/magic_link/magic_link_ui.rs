@@ -0,0 +1,55 @@
+use leptos::prelude::*;
+use leptos::either::Either;
+use leptos_router::hooks::{use_navigate, use_query_map};
+use crate::server::LoginOutcome;
+use super::MagicLogin;
+
+/// This is where the link in the login mail goes, with the token in `?token=...`. The token isn't
+/// used until the button gets pressed: some mail programs open every link in a message to check
+/// it, and that shouldn't burn the link (or log the mail scanner in).
+#[component]
+pub fn MagicLink() -> impl IntoView {
+    let qmap = use_query_map();
+    let token = move || qmap.with(|q| q.get("token").unwrap_or_default());
+    let login:ServerAction<MagicLogin> = ServerAction::new();
+
+    // Go home once logged in, or back to the login page for the TOTP code.
+    Effect::new(move || {
+        let nav = use_navigate();
+        match login.value().get() {
+            Some(Ok(LoginOutcome::LoggedIn(_))) => nav("/", Default::default()),
+            Some(Ok(LoginOutcome::SecondFactorRequired)) => nav("/login", Default::default()),
+            _ => (),
+        }
+    });
+
+    let message = move || match login.value().get() {
+        Some(Ok(LoginOutcome::Failed)) => Some(Either::Left(view! {
+            <p>"That link has expired or was already used. " <a href="/login" class="text-indigo-600">"Get a new one"</a></p>
+        })),
+        Some(Err(e)) => Some(Either::Right(view! { <p class="text-sm text-red-600">{e.to_string()}</p> })),
+        _ => None,
+    };
+
+    view! {
+        <leptos_meta::Title text="Log in"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Log in with your link
+                </h2>
+                <ActionForm action=login>
+                    <input type="hidden" name="token" value=token/>
+                    <input
+                        type="submit"
+                        class=r#"flex w-full justify-center rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold
+                           leading-6 text-white shadow-sm hover:bg-indigo-500 focus-visible:outline
+                           focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600"#
+                        value="log me in"
+                    />
+                </ActionForm>
+                {message}
+            </div>
+        </div>
+    }
+}
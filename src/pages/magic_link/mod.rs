@@ -0,0 +1,2 @@
+mod magic_link_ui; pub use self::magic_link_ui::*;
+mod magic_link_server; pub use self::magic_link_server::*;
@@ -1,3 +1,12 @@
 
 mod register; pub use self::register::*;
 mod login; pub use self::login::*;
+mod logout; pub use self::logout::*;
+mod password_reset; pub use self::password_reset::*;
+mod verify_email; pub use self::verify_email::*;
+mod two_factor; pub use self::two_factor::*;
+mod magic_link; pub use self::magic_link::*;
+mod passkeys; pub use self::passkeys::*;
+mod account; pub use self::account::*;
+mod sessions; pub use self::sessions::*;
+mod admin; pub use self::admin::*;
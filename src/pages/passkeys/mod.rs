@@ -0,0 +1,3 @@
+mod passkeys_ui; pub use self::passkeys_ui::*;
+mod passkeys_server; pub use self::passkeys_server::*;
+mod passkeys_client; pub use self::passkeys_client::*;
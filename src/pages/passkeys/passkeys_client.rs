@@ -0,0 +1,71 @@
+use webauthn_rs_proto::{CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse};
+use crate::server::LoginOutcome;
+use super::{finish_passkey_login, finish_passkey_registration, start_passkey_login, start_passkey_registration};
+
+// The glue between the passkey server functions and the browser. Each ceremony is three steps:
+// get a challenge from the server, hand it to `navigator.credentials` (which is where the browser
+// asks the user to touch their key, or use their fingerprint, or whatever), and send the answer
+// back to the server. These are plain async functions, for use with `Action::new_local` since the
+// browser's futures aren't Send.
+
+/// Add a passkey to the logged-in user's account.
+pub async fn register_passkey(name: String) -> Result<(), String> {
+    let challenge = start_passkey_registration().await.map_err(|e| e.to_string())?;
+    let response = browser::create(challenge).await?;
+    finish_passkey_registration(response, name).await.map_err(|e| e.to_string())
+}
+
+/// Log in with whichever passkey the user picks.
+pub async fn login_with_passkey() -> Result<LoginOutcome, String> {
+    let challenge = start_passkey_login().await.map_err(|e| e.to_string())?;
+    let response = browser::get(challenge).await?;
+    finish_passkey_login(response).await.map_err(|e| e.to_string())
+}
+
+#[cfg(feature="csr")]
+mod browser {
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{CredentialCreationOptions, CredentialRequestOptions, CredentialsContainer};
+    use super::*;
+
+    fn credentials() -> Result<CredentialsContainer, String> {
+        Ok(web_sys::window().ok_or("No window")?.navigator().credentials())
+    }
+
+    /// The user cancelling shows up here as a NotAllowedError.
+    fn js_error(e: JsValue) -> String {
+        e.as_string().unwrap_or_else(|| format!("{e:?}"))
+    }
+
+    /// `navigator.credentials.create()`
+    pub async fn create(challenge: CreationChallengeResponse) -> Result<RegisterPublicKeyCredential, String> {
+        let options: CredentialCreationOptions = challenge.into();
+        let promise = credentials()?.create_with_options(&options).map_err(js_error)?;
+        let credential = JsFuture::from(promise).await.map_err(js_error)?;
+        Ok(RegisterPublicKeyCredential::from(web_sys::PublicKeyCredential::from(credential)))
+    }
+
+    /// `navigator.credentials.get()`
+    pub async fn get(challenge: RequestChallengeResponse) -> Result<PublicKeyCredential, String> {
+        let options: CredentialRequestOptions = challenge.into();
+        let promise = credentials()?.get_with_options(&options).map_err(js_error)?;
+        let credential = JsFuture::from(promise).await.map_err(js_error)?;
+        Ok(PublicKeyCredential::from(web_sys::PublicKeyCredential::from(credential)))
+    }
+}
+
+/// The server build has no browser to talk to. It never calls these, they're only here so that
+/// the components compile.
+#[cfg(not(feature="csr"))]
+mod browser {
+    use super::*;
+
+    pub async fn create(_challenge: CreationChallengeResponse) -> Result<RegisterPublicKeyCredential, String> {
+        Err("Passkeys only work in the browser".into())
+    }
+
+    pub async fn get(_challenge: RequestChallengeResponse) -> Result<PublicKeyCredential, String> {
+        Err("Passkeys only work in the browser".into())
+    }
+}
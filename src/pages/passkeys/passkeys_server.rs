@@ -0,0 +1,119 @@
+use leptos::prelude::*;
+use leptos::server_fn::codec::Json;
+use serde::{Serialize,Deserialize};
+use webauthn_rs_proto::{CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse};
+use crate::server::LoginOutcome;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use axum_login::{AuthSession, AuthnBackend};
+        use webauthn_rs::prelude::{DiscoverableAuthentication, PasskeyRegistration, Uuid};
+        use crate::auth_events::AuthEventKind;
+        use crate::credentials::Credentials;
+        use crate::error_template::AppError;
+        use crate::server::finish_login;
+        use crate::backend::AppBackend;
+        use crate::state::ClientInfo;
+
+        /// Session key for a passkey registration waiting for the browser's answer
+        const PASSKEY_REGISTRATION_KEY: &str = "passkey_registration";
+        /// Session key for a passkey login waiting for the browser's answer
+        const PASSKEY_LOGIN_KEY: &str = "passkey_login";
+
+        /// What gets kept in the session between the two halves of a registration.
+        #[derive(Serialize,Deserialize)]
+        struct PendingRegistration {
+            user_id: crate::user::DatabaseId,
+            user_handle: Uuid,
+            state: PasskeyRegistration,
+        }
+    }
+}
+
+/// One of the user's passkeys, for listing on the passkeys page.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct PasskeyInfo {
+    pub id: i64,
+    pub name: String,
+    /// Unix timestamps
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+/// Start adding a passkey to the logged-in user's account. The result goes to
+/// `navigator.credentials.create()`, see `register_passkey`.
+#[server(name=StartPasskeyRegistration, prefix="/api", endpoint="start_passkey_registration", output=Json)]
+pub async fn start_passkey_registration() -> Result<CreationChallengeResponse, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let user_handle = auth.backend.passkey_user_handle(user.id).await?;
+    let existing = auth.backend.list_passkeys(user.id).await?;
+    let (challenge, state) = auth.backend.passkeys.start_registration(user_handle, &user.username, &existing)?;
+    session.insert(PASSKEY_REGISTRATION_KEY, PendingRegistration{ user_id: user.id, user_handle, state }).await?;
+    Ok(challenge)
+}
+
+/// Check the browser's answer to `start_passkey_registration` and save the new passkey under
+/// `name`, which is only there to help the user tell their passkeys apart.
+#[server(name=FinishPasskeyRegistration, prefix="/api", endpoint="finish_passkey_registration", input=Json)]
+pub async fn finish_passkey_registration(response: RegisterPublicKeyCredential, name: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let pending = session.remove::<PendingRegistration>(PASSKEY_REGISTRATION_KEY).await?
+        .filter(|pending| pending.user_id == user.id)
+        .ok_or_else(|| AppError::InvalidData("There's no passkey registration waiting, please try again".into()))?;
+    let passkey = auth.backend.passkeys.finish_registration(&response, &pending.state)?;
+    let name = Some(name.trim().to_string()).filter(|n| !n.is_empty()).unwrap_or_else(|| "Passkey".into());
+    auth.backend.add_passkey(user.id, pending.user_handle, &name, &passkey).await?;
+    Ok(())
+}
+
+/// Start a passkey login. The result goes to `navigator.credentials.get()`, see
+/// `login_with_passkey`.
+#[server(name=StartPasskeyLogin, prefix="/api", endpoint="start_passkey_login", output=Json)]
+pub async fn start_passkey_login() -> Result<RequestChallengeResponse, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let (challenge, state) = auth.backend.passkeys.start_login()?;
+    session.insert(PASSKEY_LOGIN_KEY, state).await?;
+    Ok(challenge)
+}
+
+/// Check the browser's answer to `start_passkey_login` and log in whoever it belongs to.
+#[server(name=FinishPasskeyLogin, prefix="/api", endpoint="finish_passkey_login", input=Json)]
+pub async fn finish_passkey_login(response: PublicKeyCredential) -> Result<LoginOutcome, ServerFnError> {
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let state = session.remove::<DiscoverableAuthentication>(PASSKEY_LOGIN_KEY).await?
+        .ok_or_else(|| AppError::InvalidData("There's no passkey login waiting, please try again".into()))?;
+    let client: ClientInfo = use_context().expect("client info not provided");
+    let Some(user) = auth.backend.authenticate(Credentials::Passkey{ response, state }).await? else {
+        auth.backend.record_event(AuthEventKind::LoginFailed, None, None, Some(&client), Some("passkey: not recognized")).await;
+        return Ok(LoginOutcome::Failed);
+    };
+    Ok(finish_login(&mut auth, &session, user, &client, "passkey", false).await?)
+}
+
+/// List the logged-in user's passkeys.
+#[server(name=ListPasskeys, prefix="/api", endpoint="list_passkeys")]
+pub async fn list_passkeys() -> Result<Vec<PasskeyInfo>, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    Ok(auth.backend.list_passkeys(user.id).await?
+        .into_iter()
+        .map(|p| PasskeyInfo{ id: p.id, name: p.name, created_at: p.created_at, last_used_at: p.last_used_at })
+        .collect())
+}
+
+/// Remove one of the logged-in user's passkeys.
+#[server(name=DeletePasskey, prefix="/api", endpoint="delete_passkey")]
+pub async fn delete_passkey(id: i64) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    if !auth.backend.delete_passkey(user.id, id).await? {
+        return Err(AppError::NotFound.into());
+    }
+    Ok(())
+}
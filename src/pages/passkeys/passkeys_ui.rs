@@ -0,0 +1,131 @@
+use leptos::prelude::*;
+use leptos::either::{Either, EitherOf3};
+use crate::server::require_login;
+use super::{list_passkeys, register_passkey, DeletePasskey};
+
+/// A name box and a button that adds a passkey to the logged-in user's account. `on_added` gets
+/// called after the server has accepted the new passkey.
+#[component]
+pub fn AddPasskey(#[prop(into, optional)] on_added: Option<Callback<()>>) -> impl IntoView {
+    let name = RwSignal::new("My passkey".to_string());
+    // Local, because the browser's futures aren't Send. See passkeys_client.rs.
+    let add = Action::new_local(|name: &String| register_passkey(name.clone()));
+    Effect::new(move || {
+        if let (Some(Ok(())), Some(on_added)) = (add.value().get(), on_added) {
+            on_added.run(());
+        }
+    });
+
+    view! {
+        <div class="space-y-2">
+            <label for="passkey-name" class="block text-sm font-medium leading-6 text-gray-900">
+                "Name for the new passkey"
+            </label>
+            <input
+                id="passkey-name"
+                type="text"
+                prop:value=name
+                on:input=move |ev| name.set(event_target_value(&ev))
+                class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+            />
+            <button
+                class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                disabled=move || add.pending().get()
+                on:click=move |_| { add.dispatch(name.get_untracked()); }
+            >
+                "add a passkey"
+            </button>
+            {move || match add.value().get() {
+                Some(Ok(())) => Some(Either::Left(view! { <p class="text-sm">"Passkey added."</p> })),
+                Some(Err(e)) => Some(Either::Right(view! { <p class="text-sm text-red-600">{e}</p> })),
+                None => None,
+            }}
+        </div>
+    }
+}
+
+/// Format a unix timestamp for the list (the sessions page borrows it too). Good enough without
+/// pulling a date library into the browser.
+pub(crate) fn when(ts: i64) -> String {
+    let days = ts.div_euclid(86400);
+    let secs = ts.rem_euclid(86400);
+    // Civil-from-days, from Howard Hinnant's date algorithms.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2) / 153;
+    let d = doy - (153*mp + 2)/5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era*400 + if m <= 2 { 1 } else { 0 };
+    format!("{y:04}-{m:02}-{d:02} {:02}:{:02} UTC", secs/3600, secs%3600/60)
+}
+
+/// The logged-in user's passkeys: the list, a button to remove each one, and a way to add more.
+#[component]
+pub fn Passkeys() -> impl IntoView {
+    let delete:ServerAction<DeletePasskey> = ServerAction::new();
+    let added = RwSignal::new(0);
+    let user = Resource::new(|| (), |_| require_login(None));
+    let passkeys = Resource::new(
+        move || (delete.version().get(), added.get()),
+        |_| list_passkeys(),
+    );
+
+    let list = move || Suspend::new(async move {
+        match passkeys.await {
+            Ok(keys) if keys.is_empty() => EitherOf3::A(view! { <p>"You don't have any passkeys yet."</p> }),
+            Ok(keys) => EitherOf3::B(view! {
+                <ul class="space-y-2">
+                    {keys.into_iter().map(|key| view! {
+                        <li class="flex items-center justify-between">
+                            <div>
+                                <p class="font-semibold">{key.name}</p>
+                                <p class="text-sm text-gray-500">
+                                    "added " {when(key.created_at)} ", "
+                                    {key.last_used_at.map(|ts| format!("last used {}", when(ts))).unwrap_or("never used".into())}
+                                </p>
+                            </div>
+                            <ActionForm action=delete>
+                                <input type="hidden" name="id" value=key.id/>
+                                <input
+                                    type="submit"
+                                    class="rounded-md bg-gray-200 px-3 py-1.5 text-sm font-semibold text-gray-900 shadow-sm hover:bg-gray-300"
+                                    value="remove"
+                                />
+                            </ActionForm>
+                        </li>
+                    }).collect_view()}
+                </ul>
+            }),
+            Err(e) => EitherOf3::C(view! { <p class="text-red-600">{e.to_string()}</p> }),
+        }
+    });
+
+    let content = move || Suspend::new(async move {
+        match user.await {
+            Ok(Some(_)) => Either::Left(view! {
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{list}</Transition>
+                <AddPasskey on_added=move |_| added.update(|n| *n += 1)/>
+            }),
+            _ => Either::Right(view! { <p>"You have to be logged in for this."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Passkeys"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Passkeys
+                </h2>
+                <p class="text-sm">
+                    "A passkey lets you log in with your fingerprint, face, screen lock or security key \
+                    instead of your password."
+                </p>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,2 @@
+mod password_reset_ui; pub use self::password_reset_ui::*;
+mod password_reset_server; pub use self::password_reset_server::*;
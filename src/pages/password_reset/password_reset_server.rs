@@ -0,0 +1,70 @@
+use leptos::prelude::*;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use tracing::{debug, info};
+        use axum_login::AuthSession;
+        use crate::backend::AppBackend;
+        use crate::state::AppState;
+        use crate::mail::Mail;
+        use crate::error_template::AppError;
+        use crate::auth_events::AuthEventKind;
+        use crate::state::ClientInfo;
+    }
+}
+
+/// Mail a password reset link to the user. This says "ok" whether or not the user exists, so that
+/// it can't be used to find out which usernames are registered. Users who never gave an email
+/// address have nowhere for the link to go, so they get nothing (and the same "ok").
+#[server(name=RequestPasswordReset, prefix="/api", endpoint="request_password_reset")]
+pub async fn request_password_reset(username: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    let Some(user) = auth.backend.find_user_by_name(&username).await? else {
+        info!(%username, "Password reset requested for an unknown user");
+        return Ok(());
+    };
+    let Some(email) = user.email.clone() else {
+        debug!(user_id = user.id, username = %user.username, "Password reset requested for a user with no email address");
+        return Ok(());
+    };
+    let token = auth.backend.issue_password_reset_token(user.id).await?;
+    let link = format!("{}/reset-password?token={token}", app_state.server_config.public_url);
+    let minutes = app_state.server_config.password_reset_token_ttl_seconds / 60;
+    app_state.mailer.send(&Mail {
+        to: email,
+        subject: "Reset your password".into(),
+        body: format!("Somebody (hopefully you) asked to reset the password for {}.\n\n\
+            Follow this link within {minutes} minutes to pick a new one:\n\n{link}\n\n\
+            If it wasn't you, you can ignore this message.", user.username),
+    }).await?;
+    Ok(())
+}
+
+/// Return true if the reset token is still good. The reset page uses this to decide whether to
+/// show the form at all.
+#[server(name=CheckPasswordResetToken, prefix="/api", endpoint="check_password_reset_token")]
+pub async fn check_password_reset_token(token: String) -> Result<bool, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    Ok(auth.backend.verify_password_reset_token(&token).await?.is_some())
+}
+
+/// Use up the reset token and give its user the new password. This doesn't log anybody in; the
+/// user goes to the login page afterward like normal.
+#[server(name=ResetPasswordWithToken, prefix="/api", endpoint="reset_password")]
+pub async fn reset_password(token: String, password: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    // Check the password before the token gets used up, otherwise a password that breaks the
+    // rules would waste the link.
+    let invalid_link = || AppError::InvalidData("That reset link is invalid or has expired".into());
+    let user_id = auth.backend.verify_password_reset_token(&token).await?.ok_or_else(invalid_link)?;
+    let user = auth.backend.find_user_by_id(user_id).await?.ok_or(AppError::NotFound)?;
+    auth.backend.passwords.check_policy(&user.username, &password)?;
+    let user_id = auth.backend.consume_password_reset_token(&token).await?
+        .ok_or_else(invalid_link)?;
+    auth.backend.set_password(user_id, password).await?;
+    let client: ClientInfo = use_context().expect("client info not provided");
+    auth.backend.record_event(AuthEventKind::PasswordChanged, Some(user.id), Some(&user.username), Some(&client), Some("reset link")).await;
+    info!(user_id, "Reset a password");
+    Ok(())
+}
@@ -0,0 +1,165 @@
+
+use leptos::prelude::*;
+use leptos::either::{Either, EitherOf3};
+use leptos_router::hooks::use_query_map;
+use super::{check_password_reset_token, RequestPasswordReset, ResetPasswordWithToken};
+
+/// Ask for a username and mail a reset link to it. The response is the same whether or not the
+/// account exists, see `request_password_reset`.
+#[component]
+pub fn ForgotPassword() -> impl IntoView {
+    let request:ServerAction<RequestPasswordReset> = ServerAction::new();
+    let result = move || match request.value().get() {
+        Some(Ok(())) => Some(Either::Left(view! {
+            <p>"If that account exists, a reset link is on its way. Check your mail."</p>
+        })),
+        Some(Err(e)) => Some(Either::Right(view! { <p>"Something went wrong: " {e.to_string()}</p> })),
+        None => None,
+    };
+    view! {
+        <leptos_meta::Title text="Forgot password"></leptos_meta::Title>
+        <ActionForm action=request>
+            <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+                <div class="sm:mx-auto sm:w-full sm:max-w-sm">
+                    <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                        Reset your password
+                    </h2>
+                </div>
+
+                <div class="mt-10 sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                    <div>
+                        <label
+                            for="username"
+                            class="flex self-start block text-sm font-medium leading-6 text-gray-900"
+                        >
+                            Username
+                        </label>
+                        <div class="mt-2">
+                            <input
+                                id="username"
+                                name="username"
+                                type="text"
+                                autocomplete="username"
+                                required
+                                class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                            />
+                        </div>
+                    </div>
+
+                    <div>
+                        <input
+                            type="submit"
+                            class=r#"flex w-full justify-center rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold
+                               leading-6 text-white shadow-sm hover:bg-indigo-500 focus-visible:outline
+                               focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600"#
+                            value="send reset link"
+                        />
+                    </div>
+                    {result}
+                </div>
+            </div>
+        </ActionForm>
+    }
+}
+
+/// This is where the link in the reset mail goes. The token comes in as `?token=...`, and if it's
+/// still good the user gets to pick a new password.
+#[component]
+pub fn ResetPassword() -> impl IntoView {
+    let qmap = use_query_map();
+    let token = move || qmap.with(|q| q.get("token").unwrap_or_default());
+    let reset:ServerAction<ResetPasswordWithToken> = ServerAction::new();
+    let token_ok = Resource::new(token, check_password_reset_token);
+    let password = RwSignal::new(String::new());
+    let password2 = RwSignal::new(String::new());
+    let mismatch = move || password.with(|p| password2.with(|p2| p != p2));
+
+    let form = move || view! {
+        <ActionForm action=reset>
+            <input type="hidden" name="token" value=token/>
+            <div class="space-y-4">
+                <div>
+                    <label for="password" class="block text-sm font-medium leading-6 text-gray-900">
+                        New password
+                    </label>
+                    <div class="mt-2">
+                        <input
+                            id="password"
+                            name="password"
+                            type="password"
+                            autocomplete="new-password"
+                            required
+                            on:input=move |ev| password.set(event_target_value(&ev))
+                            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                        />
+                    </div>
+                </div>
+                <div>
+                    <label for="password2" class="block text-sm font-medium leading-6 text-gray-900">
+                        Confirm new password
+                    </label>
+                    <div class="mt-2">
+                        // No name on this one, so it stays in the browser
+                        <input
+                            id="password2"
+                            type="password"
+                            autocomplete="new-password"
+                            required
+                            on:input=move |ev| password2.set(event_target_value(&ev))
+                            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                        />
+                    </div>
+                    <Show when=mismatch>
+                        <p class="text-sm text-red-600">"The passwords don't match."</p>
+                    </Show>
+                </div>
+                <div>
+                    <input
+                        type="submit"
+                        disabled=mismatch
+                        class=r#"flex w-full justify-center rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold
+                           leading-6 text-white shadow-sm hover:bg-indigo-500 focus-visible:outline
+                           focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600"#
+                        value="change password"
+                    />
+                </div>
+            </div>
+        </ActionForm>
+    };
+
+    // Once the reset went through there's no point in showing the form again.
+    let content = move || Suspend::new(async move {
+        match (reset.value().get(), token_ok.await) {
+            (Some(Ok(())), _) => EitherOf3::A(view! {
+                <p>
+                    "Your password has been changed. "
+                    <a href="/login" class="font-semibold text-indigo-600 hover:text-indigo-500">"Log in"</a>
+                </p>
+            }),
+            (result, Ok(true)) => EitherOf3::B(view! {
+                {form}
+                {result.and_then(|r| r.err()).map(|e| view! { <p class="text-sm text-red-600">{e.to_string()}</p> })}
+            }),
+            _ => EitherOf3::C(view! {
+                <p>
+                    "This reset link is invalid or has expired. "
+                    <a href="/forgot-password" class="font-semibold text-indigo-600 hover:text-indigo-500">"Get a new one"</a>
+                </p>
+            }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Reset password"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Pick a new password
+                </h2>
+            </div>
+            <div class="mt-10 sm:mx-auto sm:w-full sm:max-w-sm">
+                <Transition fallback=|| view! { <p>"Checking your link..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
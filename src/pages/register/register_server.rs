@@ -2,8 +2,7 @@ use leptos::prelude::*;
 
 cfg_if::cfg_if! {
     if #[cfg(feature="ssr")] {
-        use leptos::logging::log;
-        use crate:: state::AppState ;
+        use tracing::debug;
         //use axum_extra::extract::cookie::{Cookie,CookieJar};
     }
 }
@@ -16,20 +15,10 @@ cfg_if::cfg_if! {
 //   that throughout the example. 
 #[server(name=UserExists, prefix="/api",endpoint="user_exists")]
 pub async fn user_exists(user:String) -> Result<bool, ServerFnError> {
-    use sqlx::{query_as,FromRow};
+    use axum_login::AuthSession;
+    use crate::backend::AppBackend;
 
-    log!("checking username {user}");
-    let pbox:AppState = use_context().expect("No database pool provided in context");
-    
-    #[derive(Clone,FromRow)]
-    struct Uid {
-        // this holds the user id, but it's never used in here. I promised myself I'd kill all the
-        // unused stuff, but this one just seems like it's useful as an example so I'm keeping it.
-        #[allow(unused)]
-        pub id: i64,
-    }
-
-    let exists:Option<Uid> = query_as!(Uid, "select id from users where username=$1", user)
-        .fetch_optional(&pbox.pool).await?;
-    Ok(exists.is_some())
+    debug!(username = %user, "Checking whether a username is taken");
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    Ok(auth.backend.find_user_by_name(&user).await?.is_some())
 }
@@ -1,47 +1,25 @@
 use leptos::prelude::*;
 use crate::auth::*;
+use crate::error_template::AppError;
 use crate::server::RegisterNewUser;
+use crate::strength::Strength;
+use crate::pages::register_passkey;
 use super::user_exists;
 
 
-/// Compute a rough password strength measure for the given password. The result is proportional to
-/// the square of the overall length, with extra bonuses added for numbers and symbols. The result
-/// will be between 0 and 100, so it can be used as a percentage for an HTML element width. This is
-/// not a smart password strength meter, but it's fun. Don't use it unless you do your own analysis
-/// and decide it's good enough. It probably isn't.
-
-pub fn password_strength(passwd: &str) -> f32 {
-    let mut special:f32 = 0.5;
-    let mut num:f32 = 0.5;
-    let mut plain:f32 = 0.5;
-    for ch in passwd.chars() {
-        if "!@#$%^&*()_+-=<>?,./\\[]}{:;\"'".contains(ch) {
-            special = 1.2;
-        } else if "0123456789".contains(ch) {
-            num = 1.1;
-        } else {
-            plain=1.3;
-        }
-    } 
-    let len = passwd.len() as f32;
-    let strength = plain*num*special*len*len/4.0;
-    if strength > 100. {
-        100.
-    } else {
-        strength
-    }
-}
-
 /// Display a styled registration form with password confirmation and password strength meter. The
 /// user can show the password as plaintext or hide it. If it's shown, the confirmation input is
-/// disabled.
+/// disabled. The meter is the same zxcvbn estimate the server checks against the policy's
+/// `min_score` (see strength.rs), with its warning and suggestions shown underneath.
 ///
 /// This will query the following server functions;
 /// - `get_user` to check whether the user is already logged in
 /// - `user_exists` to check whether a user name is already taken
-/// - `register_new_user` (`RegisterNewUser`) to add the user to the database.
+/// - `register_new_user` (`RegisterNewUser`) to add the user to the database. The email address is
+///   optional unless the server requires verified addresses.
 ///
-/// Currently, it doesn't do anything with errors.
+/// If the password breaks the server's password policy, every rule it broke gets listed under the
+/// form. Other errors just show their message.
 ///
 
 #[component]
@@ -62,9 +40,15 @@ pub fn Register() -> impl IntoView {
     // `show_pass` RwSignal. Using the `with` method avoids cloning overhead.
     let pass_type = move || show_pass.with(|show| if *show { "text" } else { "password" });
     // This returns Some(user) if the user has an active, logged-in session
+    // If this is checked, a passkey gets made as soon as the account exists. The browser's
+    // futures aren't Send, hence the local action. See pages/passkeys.
+    let with_passkey = RwSignal::new(false);
+    let add_passkey = Action::new_local(|name: &String| register_passkey(name.clone()));
     let logged_in = Resource::new(move || register.version().get(), move |ver| async move {
         if let Some(user) = get_user().await.ok().and_then(|u| u) {
-            if ver > 0 { // only redirect if a *new* registration happened
+            // only redirect if a *new* registration happened, and not while a passkey is still
+            // being made
+            if ver > 0 && !with_passkey.get_untracked() {
                 let nav = use_navigate();
                 nav("/", Default::default());
             }
@@ -74,6 +58,22 @@ pub fn Register() -> impl IntoView {
         }
     });
 
+    Effect::new(move || {
+        if let Some(Ok(Some(user))) = register.value().get() {
+            if with_passkey.get_untracked() {
+                add_passkey.dispatch(format!("{}'s passkey", user.username));
+            }
+        }
+    });
+    // Off to the home page once the passkey is done. If it didn't work, the message below says
+    // so, and the account is still there with its password.
+    Effect::new(move || {
+        if let Some(Ok(())) = add_passkey.value().get() {
+            let nav = use_navigate();
+            nav("/", Default::default());
+        }
+    });
+
     // Return true if the username provided is already taken, false if it is available
     let name_taken = Resource::new(username, move |name| async move {user_exists(name).await});
 
@@ -103,9 +103,29 @@ pub fn Register() -> impl IntoView {
         } 
     });
 
-    // Dumb password strength calculation just to have a strength meter. It may be dumb but I still 
-    // like it.
-    let pass_strength = move || password.with(move |pw| password_strength(pw) );
+    // The strength meter used to be a homemade formula that mostly liked long passwords. This is
+    // zxcvbn now, running right here in the browser, with the username counted as a dictionary word
+    // like the server does it. It's a Memo so the estimate only gets made once per keystroke, no
+    // matter how many parts of the view look at it.
+    let strength = Memo::new(move |_| {
+        password.with(|pw| username.with(|name| Strength::estimate(pw, &[name.as_str()])))
+    });
+    // Red through green, by score. Nothing at all until something is typed.
+    let meter_style = move || {
+        if password.with(String::is_empty) {
+            return "width: 0%".to_string();
+        }
+        strength.with(|s| {
+            let color = match s.score {
+                0 => "#f87171",
+                1 => "#fb923c",
+                2 => "#facc15",
+                3 => "#a3e635",
+                _ => "#4ade80",
+            };
+            format!("width: {}%; background-color: {color}", s.percent())
+        })
+    };
 
     // This is adapted from the tailwindui.com simple registration form component.
     view! {
@@ -142,6 +162,24 @@ pub fn Register() -> impl IntoView {
                         {available_ui}
                     </div>
 
+                    <div>
+                        <label
+                            for="email"
+                            class="flex self-start block text-sm font-medium leading-6 text-gray-900"
+                        >
+                            Email
+                        </label>
+                        <div class="mt-2">
+                            <input
+                                id="email"
+                                name="email"
+                                type="email"
+                                autocomplete="email"
+                                class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                            />
+                        </div>
+                    </div>
+
                     <div>
                         <div class="flex items-center justify-between">
                             <label
@@ -176,14 +214,25 @@ pub fn Register() -> impl IntoView {
                             </a>
                         </div> 
                         // This is the very cool strength meter, which is pretty advanced
-                        // technology. Might be even more advanced if I did something with the
-                        // color, but I don't want to overwhelm people with the awesomeness.
+                        // technology. It even does colors now.
                         <div class="w-full h-2 flex items-start">
                             <div
-                                class="rounded h-2 bg-green-300"
-                                style=move || format!("width: {}%", pass_strength())
+                                id="strength-meter"
+                                class="rounded h-2"
+                                style=meter_style
                             ></div>
                         </div>
+                        // zxcvbn's explanation of the score, like "This is similar to a commonly
+                        // used password" and "Avoid sequences"
+                        <div id="strength-feedback" class="text-sm text-gray-600">
+                            {move || strength.with(|s| s.warning.clone()).map(|w| view! { <p>{w}</p> })}
+                            <ul>
+                                {move || strength.with(|s| s.suggestions.clone())
+                                    .into_iter()
+                                    .map(|tip| view! { <li>{tip}</li> })
+                                    .collect_view()}
+                            </ul>
+                        </div>
                     </div>
                     // Ask for the password twice, but only if the show_password option is off. No
                     // reason to repeat it if you can just read it to see if it's right. right?
@@ -208,6 +257,16 @@ pub fn Register() -> impl IntoView {
                         </div>
                     </div>
 
+                    <div class="flex items-center gap-2 text-sm text-gray-900">
+                        <input
+                            id="with-passkey"
+                            type="checkbox"
+                            prop:checked=with_passkey
+                            on:change=move |ev| with_passkey.set(event_target_checked(&ev))
+                        />
+                        <label for="with-passkey">"Set up a passkey too"</label>
+                    </div>
+
                     <div>
                         <input
                             type="submit"
@@ -220,6 +279,29 @@ pub fn Register() -> impl IntoView {
                 </div>
             </div>
         </ActionForm>
+        // register_new_user only comes back with no user and no error when the server wants the
+        // email address verified before anybody logs in.
+        <Show when=move || matches!(register.value().get(), Some(Ok(None)))>
+            <p>"Almost there! Follow the link we mailed you to verify your address, then log in."</p>
+        </Show>
+        {move || match register.value().get() {
+            Some(Err(AppError::WeakPassword(violations))) => Some(Either::Left(view! {
+                <div class="text-sm text-red-600">
+                    <p>"That password won't do:"</p>
+                    <ul class="list-disc pl-5">
+                        {violations.into_iter().map(|v| view! { <li>{v.to_string()}</li> }).collect_view()}
+                    </ul>
+                </div>
+            })),
+            Some(Err(e)) => Some(Either::Right(view! { <p class="text-sm text-red-600">{e.to_string()}</p> })),
+            _ => None,
+        }}
+        {move || add_passkey.value().get().and_then(|r| r.err()).map(|e| view! {
+            <p>"Your account is ready, but the passkey didn't work out (" {e} "). "
+                <a href="/account/passkeys" class="text-indigo-600">"Try again"</a> " or "
+                <a href="/" class="text-indigo-600">"carry on without one"</a>"."
+            </p>
+        })}
         // Show the user what the state of their login is, if there's nowhere to redirect to after
         // this.
         <Transition fallback=|| view! { "Checking login status..." }>{login_status}</Transition>
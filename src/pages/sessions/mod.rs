@@ -0,0 +1,2 @@
+mod sessions_ui; pub use self::sessions_ui::*;
+mod sessions_server; pub use self::sessions_server::*;
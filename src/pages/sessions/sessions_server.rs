@@ -0,0 +1,72 @@
+use leptos::prelude::*;
+use serde::{Serialize,Deserialize};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use std::str::FromStr;
+        use axum_login::AuthSession;
+        use tower_sessions::SessionStore;
+        use tower_sessions::session::Id;
+        use crate::backend::AppBackend;
+        use crate::error_template::AppError;
+        use crate::state::AppState;
+    }
+}
+
+/// One of the user's sessions, for the Active sessions page. The session's real id never leaves
+/// the server, since it's what's in the cookie; `id` only means something to `revoke_session`.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct ActiveSession {
+    pub id: i64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Unix timestamps
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    /// True for the session this request came from
+    pub current: bool,
+}
+
+/// List the logged-in user's sessions, most recently used first.
+#[server(name=ListSessions, prefix="/api", endpoint="list_sessions")]
+pub async fn list_sessions() -> Result<Vec<ActiveSession>, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let current = session.id().map(|id| id.to_string());
+    Ok(auth.backend.list_sessions(user.id).await?
+        .into_iter()
+        .map(|entry| ActiveSession{
+            id: entry.id,
+            current: current.as_deref() == Some(entry.session_id.as_str()),
+            user_agent: entry.user_agent,
+            ip: entry.ip,
+            created_at: entry.created_at,
+            last_seen_at: entry.last_seen_at,
+        })
+        .collect())
+}
+
+/// End one of the logged-in user's other sessions. It's deleted from the session store, so its
+/// cookie stops working right away, instead of whenever it would have expired. The current session
+/// can't be revoked here; that's what logging out is for.
+#[server(name=RevokeSession, prefix="/api", endpoint="revoke_session")]
+pub async fn revoke_session(id: i64) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let current = session.id().map(|id| id.to_string());
+    let is_current = auth.backend.list_sessions(user.id).await?
+        .iter()
+        .any(|entry| entry.id == id && current.as_deref() == Some(entry.session_id.as_str()));
+    if is_current {
+        return Err(AppError::InvalidData("That's the session you're using, log out instead".into()).into());
+    }
+    let entry = auth.backend.revoke_session(user.id, id).await?.ok_or(AppError::NotFound)?;
+    // An id that doesn't parse can't be in the session store either, so there's nothing more to do.
+    if let Ok(session_id) = Id::from_str(&entry.session_id) {
+        app_state.session_store.delete(&session_id).await?;
+    }
+    Ok(())
+}
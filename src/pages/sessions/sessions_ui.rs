@@ -0,0 +1,81 @@
+use leptos::prelude::*;
+use leptos::either::{Either, EitherOf3};
+use crate::pages::when;
+use crate::server::require_login;
+use super::{list_sessions, RevokeSession};
+
+/// The logged-in user's active sessions: where and when each one was last used, and a button to
+/// end any of them except the one in use. A session the user doesn't recognize could be somebody
+/// with a stolen cookie, so ending it here is better than waiting for it to time out.
+#[component]
+pub fn ActiveSessions() -> impl IntoView {
+    let revoke:ServerAction<RevokeSession> = ServerAction::new();
+    let user = Resource::new(|| (), |_| require_login(None));
+    let sessions = Resource::new(move || revoke.version().get(), |_| list_sessions());
+
+    let list = move || Suspend::new(async move {
+        match sessions.await {
+            // The session making this request is always on the list, but it only gets there after
+            // its first request, so right after logging in the list can still be empty.
+            Ok(sessions) if sessions.is_empty() => EitherOf3::A(view! { <p>"No sessions to show yet."</p> }),
+            Ok(sessions) => EitherOf3::B(view! {
+                <ul class="space-y-2">
+                    {sessions.into_iter().map(|s| view! {
+                        <li class="flex items-center justify-between">
+                            <div>
+                                <p class="font-semibold">{s.user_agent.unwrap_or("Unknown browser".into())}</p>
+                                <p class="text-sm text-gray-500">
+                                    {s.ip.unwrap_or("unknown address".into())} ", signed in " {when(s.created_at)}
+                                    ", last seen " {when(s.last_seen_at)}
+                                </p>
+                            </div>
+                            {if s.current {
+                                Either::Left(view! { <span class="text-sm font-semibold">"this one"</span> })
+                            } else {
+                                Either::Right(view! {
+                                    <ActionForm action=revoke>
+                                        <input type="hidden" name="id" value=s.id/>
+                                        <input
+                                            type="submit"
+                                            class="rounded-md bg-gray-200 px-3 py-1.5 text-sm font-semibold text-gray-900 shadow-sm hover:bg-gray-300"
+                                            value="revoke"
+                                        />
+                                    </ActionForm>
+                                })
+                            }}
+                        </li>
+                    }).collect_view()}
+                </ul>
+            }),
+            Err(e) => EitherOf3::C(view! { <p class="text-red-600">{e.to_string()}</p> }),
+        }
+    });
+
+    let content = move || Suspend::new(async move {
+        match user.await {
+            Ok(Some(_)) => Either::Left(view! {
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{list}</Transition>
+                {move || revoke.value().get().and_then(|r| r.err()).map(|e| view! {
+                    <p class="text-sm text-red-600">{e.to_string()}</p>
+                })}
+            }),
+            _ => Either::Right(view! { <p>"You have to be logged in for this."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Active sessions"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Active sessions
+                </h2>
+                <p class="text-sm">
+                    "Everywhere you're logged in right now. If you don't recognize one, revoke it and \
+                    change your password."
+                </p>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
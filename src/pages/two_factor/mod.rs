@@ -0,0 +1,2 @@
+mod two_factor_ui; pub use self::two_factor_ui::*;
+mod two_factor_server; pub use self::two_factor_server::*;
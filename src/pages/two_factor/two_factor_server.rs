@@ -0,0 +1,67 @@
+use leptos::prelude::*;
+use serde::{Serialize,Deserialize};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use axum_login::AuthSession;
+        use crate::backend::AppBackend;
+        use crate::error_template::AppError;
+    }
+}
+
+/// Everything the enrollment page needs to get the secret into an authenticator app.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct TotpEnrollment {
+    /// The `otpauth://totp/...` URI that the QR code contains
+    pub otpauth_url: String,
+    /// The QR code as a base64 encoded PNG, ready for a `data:` URL
+    pub qr_code_png: String,
+    /// The secret in base32, for typing in by hand when scanning doesn't work
+    pub secret: String,
+}
+
+/// Return true if the logged-in user has two-factor auth turned on.
+#[server(name=TwoFactorEnabled, prefix="/api", endpoint="two_factor_enabled")]
+pub async fn two_factor_enabled() -> Result<bool, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    Ok(auth.backend.totp_enabled(user.id).await?)
+}
+
+/// Make a new TOTP secret for the logged-in user. It isn't required for logging in until the user
+/// sends a code to `confirm_two_factor_enrollment`.
+#[server(name=BeginTwoFactorEnrollment, prefix="/api", endpoint="begin_two_factor_enrollment")]
+pub async fn begin_two_factor_enrollment() -> Result<TotpEnrollment, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let totp = auth.backend.begin_totp_enrollment(&user).await?;
+    Ok(TotpEnrollment {
+        otpauth_url: totp.get_url(),
+        qr_code_png: totp.get_qr_base64().map_err(AppError::InternalError)?,
+        secret: totp.get_secret_base32(),
+    })
+}
+
+/// Check the first code from the user's app and turn two-factor auth on for real. Returns the
+/// recovery codes, which the user has to write down now because they can't be shown again.
+#[server(name=ConfirmTwoFactorEnrollment, prefix="/api", endpoint="confirm_two_factor_enrollment")]
+pub async fn confirm_two_factor_enrollment(code: String) -> Result<Vec<String>, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    let codes = auth.backend.confirm_totp_enrollment(user.id, &code).await?
+        .ok_or_else(|| AppError::InvalidData("That code didn't work, check your app's clock and try again".into()))?;
+    Ok(codes)
+}
+
+/// Turn two-factor auth off. This takes a current code (or a recovery code), so somebody who
+/// walks up to an unlocked computer can't do it.
+#[server(name=DisableTwoFactor, prefix="/api", endpoint="disable_two_factor")]
+pub async fn disable_two_factor(code: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    if !auth.backend.verify_second_factor(user.id, &code).await? {
+        return Err(AppError::InvalidData("That code didn't work".into()).into());
+    }
+    auth.backend.disable_totp(user.id).await?;
+    Ok(())
+}
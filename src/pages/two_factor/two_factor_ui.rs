@@ -0,0 +1,115 @@
+
+use leptos::prelude::*;
+use leptos::either::{Either, EitherOf3};
+use crate::server::require_login;
+use super::{two_factor_enabled, BeginTwoFactorEnrollment, ConfirmTwoFactorEnrollment, DisableTwoFactor};
+
+/// Turn TOTP two-factor auth on or off for the logged-in user. Turning it on takes two steps: the
+/// server makes a secret, which gets shown as a QR code (and as text, for people who can't scan
+/// it), then the user types in a code from their app to prove it worked. Only then is the second
+/// factor required for logging in, and the user gets their recovery codes.
+#[component]
+pub fn TwoFactor() -> impl IntoView {
+    let begin:ServerAction<BeginTwoFactorEnrollment> = ServerAction::new();
+    let confirm:ServerAction<ConfirmTwoFactorEnrollment> = ServerAction::new();
+    let disable:ServerAction<DisableTwoFactor> = ServerAction::new();
+    let user = Resource::new(|| (), |_| require_login(None));
+    let enabled = Resource::new(
+        move || (confirm.version().get(), disable.version().get()),
+        |_| two_factor_enabled(),
+    );
+
+    let code_input = || view! {
+        <input
+            name="code"
+            type="text"
+            autocomplete="one-time-code"
+            required
+            class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+        />
+    };
+
+    // Shown while two-factor auth is on. Right after enrollment, this is also where the recovery
+    // codes show up.
+    let enabled_view = move || view! {
+        {move || confirm.value().get().and_then(|r| r.ok()).map(|codes| view! {
+            <p>"Save these recovery codes somewhere safe. Each one works once, in place of a code from your app:"</p>
+            <ul class="font-mono">
+                {codes.into_iter().map(|code| view! { <li>{code}</li> }).collect_view()}
+            </ul>
+        })}
+        <p>"Two-factor authentication is on."</p>
+        <ActionForm action=disable>
+            <div class="space-y-2">
+                <label class="block text-sm font-medium leading-6 text-gray-900">
+                    "To turn it off, enter a current code"
+                </label>
+                {code_input}
+                <input
+                    type="submit"
+                    class="rounded-md bg-gray-200 px-3 py-1.5 text-sm font-semibold text-gray-900 shadow-sm hover:bg-gray-300"
+                    value="turn off"
+                />
+            </div>
+        </ActionForm>
+        {move || disable.value().get().and_then(|r| r.err()).map(|e| view! { <p class="text-sm text-red-600">{e.to_string()}</p> })}
+    };
+
+    // Shown while two-factor auth is off: either the button to start, or the QR code and the form
+    // for the first code.
+    let setup_view = move || match begin.value().get() {
+        Some(Ok(enrollment)) => Either::Left(view! {
+            <p>"Scan this with your authenticator app:"</p>
+            <img src=format!("data:image/png;base64,{}", enrollment.qr_code_png) alt="TOTP QR code"/>
+            <p class="text-sm">"Or enter this key by hand: " <span class="font-mono">{enrollment.secret}</span></p>
+            <p class="text-sm break-all">
+                <a href=enrollment.otpauth_url.clone() class="text-indigo-600">{enrollment.otpauth_url.clone()}</a>
+            </p>
+            <ActionForm action=confirm>
+                <div class="space-y-2">
+                    <label class="block text-sm font-medium leading-6 text-gray-900">
+                        "Then enter the code it shows"
+                    </label>
+                    {code_input}
+                    <input
+                        type="submit"
+                        class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                        value="turn on"
+                    />
+                </div>
+            </ActionForm>
+            {move || confirm.value().get().and_then(|r| r.err()).map(|e| view! { <p class="text-sm text-red-600">{e.to_string()}</p> })}
+        }),
+        other => Either::Right(view! {
+            <p>"Two-factor authentication is off."</p>
+            <ActionForm action=begin>
+                <input
+                    type="submit"
+                    class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                    value="set it up"
+                />
+            </ActionForm>
+            {other.and_then(|r| r.err()).map(|e| view! { <p class="text-sm text-red-600">{e.to_string()}</p> })}
+        }),
+    };
+
+    let content = move || Suspend::new(async move {
+        match (user.await, enabled.await) {
+            (Ok(Some(_)), Ok(true)) => EitherOf3::A(enabled_view),
+            (Ok(Some(_)), Ok(false)) => EitherOf3::B(setup_view),
+            _ => EitherOf3::C(view! { <p>"You have to be logged in for this."</p> }),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Two-factor authentication"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Two-factor authentication
+                </h2>
+                <Transition fallback=|| view! { <p>"Loading..."</p> }>{content}</Transition>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,2 @@
+mod verify_email_ui; pub use self::verify_email_ui::*;
+mod verify_email_server; pub use self::verify_email_server::*;
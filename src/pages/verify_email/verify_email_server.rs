@@ -0,0 +1,55 @@
+use leptos::prelude::*;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature="ssr")] {
+        use tracing::{debug, info};
+        use axum_login::AuthSession;
+        use crate::backend::AppBackend;
+        use crate::state::AppState;
+        use crate::mail::Mail;
+        use crate::user::User;
+        use crate::error_template::AppError;
+
+        /// Issue a fresh verification token for the user and mail them the link. Users without an
+        /// email address are skipped, since there's nowhere to send it. This is called by
+        /// `register_new_user` as well as the "resend" button.
+        pub async fn send_verification_email(backend: &AppBackend, app_state: &AppState, user: &User) -> Result<(), AppError> {
+            let Some(email) = user.email.clone() else {
+                return Ok(());
+            };
+            let token = backend.issue_email_verification_token(user.id).await?;
+            let link = format!("{}/verify-email?token={token}", app_state.server_config.public_url);
+            app_state.mailer.send(&Mail {
+                to: email,
+                subject: "Verify your email address".into(),
+                body: format!("Welcome, {}! Follow this link to confirm that this is your email address:\n\n{link}",
+                    user.username),
+            }).await
+        }
+    }
+}
+
+/// Use up a verification token from a link. Returns true if the address is now verified, false if
+/// the link was invalid, expired or already used.
+#[server(name=VerifyEmailToken, prefix="/api", endpoint="verify_email")]
+pub async fn verify_email_token(token: String) -> Result<bool, ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user_id = auth.backend.verify_email(&token).await?;
+    if let Some(user_id) = user_id {
+        info!(user_id, "Verified an email address");
+    }
+    Ok(user_id.is_some())
+}
+
+/// Send a new verification link, for when the first one expired or got lost. Like the password
+/// reset request, this answers the same way whether or not the user exists.
+#[server(name=ResendVerificationEmail, prefix="/api", endpoint="resend_verification_email")]
+pub async fn resend_verification_email(username: String) -> Result<(), ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    match auth.backend.find_user_by_name(&username).await? {
+        Some(user) if !user.email_verified => send_verification_email(&auth.backend, &app_state, &user).await?,
+        _ => debug!(%username, "Not resending a verification email"),
+    }
+    Ok(())
+}
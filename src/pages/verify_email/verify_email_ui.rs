@@ -0,0 +1,62 @@
+
+use leptos::prelude::*;
+use leptos::either::Either;
+use leptos_router::hooks::use_query_map;
+use super::{verify_email_token, ResendVerificationEmail};
+
+/// The link in the verification mail lands here with `?token=...`. The token gets used up as soon
+/// as the page loads. If it doesn't work, the user can ask for another one.
+#[component]
+pub fn VerifyEmail() -> impl IntoView {
+    let qmap = use_query_map();
+    let token = move || qmap.with(|q| q.get("token").unwrap_or_default());
+    let verified = Resource::new(token, verify_email_token);
+    let resend:ServerAction<ResendVerificationEmail> = ServerAction::new();
+
+    let resend_form = move || view! {
+        <p>"This verification link is invalid or has expired. Enter your username to get a new one."</p>
+        <ActionForm action=resend>
+            <div class="mt-2 flex flex-row items-center space-x-2">
+                <input
+                    name="username"
+                    type="text"
+                    autocomplete="username"
+                    required
+                    class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6"
+                />
+                <input
+                    type="submit"
+                    class="rounded-md bg-indigo-600 px-3 py-1.5 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                    value="resend"
+                />
+            </div>
+        </ActionForm>
+        <Show when=move || matches!(resend.value().get(), Some(Ok(())))>
+            <p>"If that account has an unverified address, a new link is on its way."</p>
+        </Show>
+    };
+
+    let status = move || Suspend::new(async move {
+        match verified.await {
+            Ok(true) => Either::Left(view! {
+                <p>
+                    "Thanks, your email address is verified. "
+                    <a href="/login" class="font-semibold text-indigo-600 hover:text-indigo-500">"Log in"</a>
+                </p>
+            }),
+            _ => Either::Right(resend_form),
+        }
+    });
+
+    view! {
+        <leptos_meta::Title text="Verify email"></leptos_meta::Title>
+        <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+            <div class="sm:mx-auto sm:w-full sm:max-w-sm space-y-4">
+                <h2 class="mt-10 text-center text-2xl font-bold leading-9 tracking-tight text-gray-900">
+                    Email verification
+                </h2>
+                <Suspense fallback=|| view! { <p>"Checking your link..."</p> }>{status}</Suspense>
+            </div>
+        </div>
+    }
+}
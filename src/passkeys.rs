@@ -0,0 +1,88 @@
+use std::fmt;
+use std::sync::Arc;
+use webauthn_rs::prelude::*;
+use crate::config::ServerConfig;
+use crate::error_template::AppError;
+
+/// A passkey from the `webauthn_credentials` table, along with the bookkeeping around it.
+#[derive(Clone,Debug)]
+pub struct StoredPasskey {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub passkey: Passkey,
+}
+
+/// Does the WebAuthn side of passkeys: building the challenges that go to the browser, and
+/// checking what comes back. All of the actual crypto is webauthn-rs. The relying party (that's
+/// us) is identified by the host name in `public_url`, and responses are only accepted from that
+/// origin, so a passkey made for this site can't be used anywhere else.
+#[derive(Clone)]
+pub struct Passkeys {
+    webauthn: Arc<Webauthn>,
+}
+
+impl fmt::Debug for Passkeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Passkeys").finish_non_exhaustive()
+    }
+}
+
+fn webauthn_error(what: &str, e: WebauthnError) -> AppError {
+    AppError::InvalidData(format!("{what}: {e}"))
+}
+
+impl Passkeys {
+    pub fn from_config(config: &ServerConfig) -> Result<Self, AppError> {
+        let origin = Url::parse(&config.public_url)
+            .map_err(|e| AppError::InternalError(format!("public_url isn't a URL: {e}")))?;
+        let rp_id = origin.host_str()
+            .ok_or_else(|| AppError::InternalError("public_url has no host name".into()))?
+            .to_string();
+        let webauthn = WebauthnBuilder::new(&rp_id, &origin)
+            .and_then(|builder| builder.rp_name(&config.passkey_rp_name).build())
+            .map_err(|e| AppError::InternalError(format!("Passkey setup: {e}")))?;
+        Ok(Passkeys{ webauthn: Arc::new(webauthn) })
+    }
+
+    /// The first half of making a new passkey. The challenge goes to the browser, and the state
+    /// has to be kept (in the session) until the browser answers. `existing` keeps the user from
+    /// registering the same authenticator twice.
+    pub fn start_registration(&self, user_handle: Uuid, username: &str, existing: &[StoredPasskey])
+    -> Result<(CreationChallengeResponse, PasskeyRegistration), AppError> {
+        let exclude = existing.iter().map(|p| p.passkey.cred_id().clone()).collect::<Vec<_>>();
+        self.webauthn.start_passkey_registration(user_handle, username, username, Some(exclude))
+            .map_err(|e| webauthn_error("Start passkey registration", e))
+    }
+
+    /// Check the browser's answer to `start_registration` and return the new passkey.
+    pub fn finish_registration(&self, response: &RegisterPublicKeyCredential, state: &PasskeyRegistration)
+    -> Result<Passkey, AppError> {
+        self.webauthn.finish_passkey_registration(response, state)
+            .map_err(|e| webauthn_error("Passkey registration failed", e))
+    }
+
+    /// The first half of logging in with a passkey. This doesn't say which user is logging in;
+    /// the browser offers whatever passkeys it has for this site, and the answer says which one
+    /// was picked.
+    pub fn start_login(&self) -> Result<(RequestChallengeResponse, DiscoverableAuthentication), AppError> {
+        self.webauthn.start_discoverable_authentication()
+            .map_err(|e| webauthn_error("Start passkey login", e))
+    }
+
+    /// Find out which user handle and credential an answer to `start_login` claims to be from.
+    /// Nothing has been checked yet at this point!
+    pub fn identify(&self, response: &PublicKeyCredential) -> Result<(Uuid, Vec<u8>), AppError> {
+        self.webauthn.identify_discoverable_authentication(response)
+            .map(|(handle, cred_id)| (handle, cred_id.to_vec()))
+            .map_err(|e| webauthn_error("Unreadable passkey response", e))
+    }
+
+    /// Check the signature on an answer to `start_login` against the stored passkey.
+    pub fn finish_login(&self, response: &PublicKeyCredential, state: DiscoverableAuthentication, passkey: &Passkey)
+    -> Result<AuthenticationResult, AppError> {
+        self.webauthn.finish_discoverable_authentication(response, state, &[DiscoverableKey::from(passkey)])
+            .map_err(|e| webauthn_error("Passkey login failed", e))
+    }
+}
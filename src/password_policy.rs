@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::fmt;
+use serde::{Deserialize,Serialize};
+use crate::strength::Strength;
+
+/// The rules new passwords have to follow. This is the `[password_policy]` table in the config
+/// file; anything left out gets the default. It's checked when registering and whenever a password
+/// gets changed, never at login, so tightening it doesn't lock anybody out.
+///
+/// This isn't behind the ssr feature because the violations go to the browser (see
+/// `AppError::WeakPassword`), and the Register page lists them.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// In characters, not bytes
+    pub min_length: usize,
+    /// Also in characters. Hashing is slow on purpose, so without a cap, somebody could keep the
+    /// server busy by sending huge passwords.
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Anything that isn't a letter, digit or whitespace
+    pub require_symbol: bool,
+    /// A file of passwords nobody is allowed to use, one per line. Blank lines and lines starting
+    /// with `#` are skipped, and the comparison ignores case.
+    pub blocklist_file: Option<String>,
+    /// Refuse passwords that have the username in them (ignoring case)
+    pub reject_username: bool,
+    /// The lowest strength score (0 to 4, see strength.rs) a password can have. 0 turns this off.
+    pub min_score: u8,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            blocklist_file: None,
+            reject_username: true,
+            min_score: 2,
+        }
+    }
+}
+
+/// One way a password broke the rules in `PasswordPolicy`.
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub enum PolicyViolation {
+    TooShort(usize),
+    TooLong(usize),
+    MissingLowercase,
+    MissingUppercase,
+    MissingDigit,
+    MissingSymbol,
+    /// It's on the blocklist
+    TooCommon,
+    ContainsUsername,
+    /// The strength estimate came in under `min_score`. This has the whole estimate, so the
+    /// feedback can be shown along with it.
+    TooGuessable(Strength),
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooShort(min) => write!(f, "It has to be at least {min} characters long"),
+            PolicyViolation::TooLong(max) => write!(f, "It can't be more than {max} characters long"),
+            PolicyViolation::MissingLowercase => write!(f, "It needs a lowercase letter"),
+            PolicyViolation::MissingUppercase => write!(f, "It needs an uppercase letter"),
+            PolicyViolation::MissingDigit => write!(f, "It needs a digit"),
+            PolicyViolation::MissingSymbol => write!(f, "It needs a symbol"),
+            PolicyViolation::TooCommon => write!(f, "It's one of the most common passwords"),
+            PolicyViolation::ContainsUsername => write!(f, "It can't contain your username"),
+            PolicyViolation::TooGuessable(strength) => {
+                write!(f, "It's too easy to guess")?;
+                if let Some(warning) = &strength.warning {
+                    write!(f, " ({})", warning.trim_end_matches('.'))?;
+                }
+                for suggestion in &strength.suggestions {
+                    write!(f, ". {}", suggestion.trim_end_matches('.'))?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Everything that's wrong with `password`, in the order the rules are listed above. An empty
+    /// list means it's fine. `blocklist` should come from `load_blocklist`.
+    pub fn check(&self, username: &str, password: &str, blocklist: &HashSet<String>) -> Vec<PolicyViolation> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+        if length < self.min_length {
+            violations.push(PolicyViolation::TooShort(self.min_length));
+        }
+        if length > self.max_length {
+            violations.push(PolicyViolation::TooLong(self.max_length));
+        }
+        let classes = [
+            (self.require_lowercase, PolicyViolation::MissingLowercase, char::is_lowercase as fn(char) -> bool),
+            (self.require_uppercase, PolicyViolation::MissingUppercase, char::is_uppercase),
+            (self.require_digit, PolicyViolation::MissingDigit, |c: char| c.is_ascii_digit()),
+            (self.require_symbol, PolicyViolation::MissingSymbol, |c: char| !c.is_alphanumeric() && !c.is_whitespace()),
+        ];
+        for (required, violation, matches) in classes {
+            if required && !password.chars().any(matches) {
+                violations.push(violation);
+            }
+        }
+        let lowered = password.to_lowercase();
+        if blocklist.contains(&lowered) {
+            violations.push(PolicyViolation::TooCommon);
+        }
+        let username = username.trim().to_lowercase();
+        if self.reject_username && !username.is_empty() && lowered.contains(&username) {
+            violations.push(PolicyViolation::ContainsUsername);
+        }
+        // Everything above is cheap, but the estimate isn't, so it only gets made if it matters.
+        if self.min_score > 0 {
+            let strength = Strength::estimate(password, &[&username]);
+            if strength.score < self.min_score {
+                violations.push(PolicyViolation::TooGuessable(strength));
+            }
+        }
+        violations
+    }
+
+    /// Read `blocklist_file`, if there is one. This happens once, when the server starts.
+    #[cfg(feature="ssr")]
+    pub fn load_blocklist(&self) -> Result<HashSet<String>, crate::error_template::AppError> {
+        let Some(path) = &self.blocklist_file else {
+            return Ok(HashSet::new());
+        };
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| crate::error_template::AppError::InternalError(format!("Read password blocklist {path}: {e}")))?;
+        Ok(contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase)
+            .collect())
+    }
+}
@@ -0,0 +1,154 @@
+use argon2::{
+    password_hash::{
+        rand_core::OsRng,
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString
+    },
+    Algorithm, Argon2, Params, Version
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+use crate::config::ServerConfig;
+use crate::error_template::AppError;
+use crate::password_policy::PasswordPolicy;
+
+/// The kinds of stored hash that `verify` understands.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum HashKind {
+    /// `$argon2id$...`, and its older siblings argon2i and argon2d
+    Argon2,
+    /// `$scrypt$...`
+    Scrypt,
+    /// `$pbkdf2$...`, `$pbkdf2-sha256$...` or `$pbkdf2-sha512$...`
+    Pbkdf2,
+    /// `$2a$`, `$2b$` or `$2y$` followed by the cost. These aren't PHC strings, they're the older
+    /// crypt(3) format, so they get recognized by their prefix.
+    Bcrypt,
+}
+
+impl HashKind {
+    /// Figure out what kind of hash a `pass_hash` is, or None if it's nothing we can check.
+    pub fn of(stored: &str) -> Option<HashKind> {
+        if ["$2a$", "$2b$", "$2y$"].iter().any(|prefix| stored.starts_with(prefix)) {
+            return Some(HashKind::Bcrypt);
+        }
+        let hash = PasswordHash::new(stored).ok()?;
+        match hash.algorithm.as_str() {
+            "argon2id" | "argon2i" | "argon2d" => Some(HashKind::Argon2),
+            "scrypt" => Some(HashKind::Scrypt),
+            "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => Some(HashKind::Pbkdf2),
+            _ => None,
+        }
+    }
+}
+
+/// Hashes and checks passwords. New hashes are always Argon2id with the cost parameters from the
+/// config (`argon2_memory_kib`, `argon2_iterations` and `argon2_parallelism`). Checking a password
+/// uses whatever parameters are written in the stored hash instead, so raising the costs doesn't
+/// lock anybody out. Their hash just gets redone with the new costs the next time they log in,
+/// see `needs_rehash`.
+///
+/// Users imported from older systems (see import.rs) can have bcrypt, scrypt or PBKDF2 hashes.
+/// Those get checked with their own algorithm, and are replaced with Argon2id on the first
+/// successful login the same way.
+///
+/// This also knows the rules new passwords have to follow (see password_policy.rs).
+#[derive(Clone,Debug)]
+pub struct Passwords {
+    params: Params,
+    policy: PasswordPolicy,
+    /// Loaded from the policy's `blocklist_file` once, and shared between clones
+    blocklist: Arc<HashSet<String>>,
+    /// A hash of a password nobody has, made with the configured costs. See `verify_nobody`.
+    dummy_hash: String,
+}
+
+impl Passwords {
+    /// Fails if the config has parameters that Argon2 won't take (like a memory size smaller than
+    /// 8 KiB per lane), or if the password blocklist can't be read.
+    pub fn from_config(config: &ServerConfig) -> Result<Self, AppError> {
+        let params = Params::new(config.argon2_memory_kib, config.argon2_iterations, config.argon2_parallelism, None)
+            .map_err(|e| AppError::InternalError(format!("Argon2 parameters: {e}")))?;
+        let policy = config.password_policy.clone();
+        let blocklist = Arc::new(policy.load_blocklist()?);
+        let mut passwords = Passwords{ params, policy, blocklist, dummy_hash: String::new() };
+        passwords.dummy_hash = passwords.hash("no user has this password")?;
+        Ok(passwords)
+    }
+
+    /// Make sure a new password follows the policy. If it doesn't, the error lists every rule it
+    /// broke, not just the first.
+    pub fn check_policy(&self, username: &str, password: &str) -> Result<(), AppError> {
+        let violations = self.policy.check(username, password, &self.blocklist);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::WeakPassword(violations))
+        }
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Hash a password for storage. Argon2id is the recommended hashing algorithm at the time of
+    /// this code being published (2024). The salt is used to prevent certain attacks against
+    /// stored passwords (see the Internet for more). What comes back is the whole thing in the
+    /// standard string format, salt and parameters included, which is what goes in the database.
+    pub fn hash(&self, password: &str) -> Result<String, AppError> {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(self.argon2().hash_password(password.as_bytes(), &salt)
+            .map_err(|e| AppError::InternalError(format!("Password hashing error: {e}")))?
+            .to_string())
+    }
+
+    /// Check a password against a stored hash. A wrong password is Ok(false); an Err means the
+    /// stored hash itself is broken, or of a kind we don't know.
+    pub fn verify(&self, password: &str, stored: &str) -> Result<bool, AppError> {
+        let kind = HashKind::of(stored)
+            .ok_or_else(|| AppError::InternalError("Unsupported or corrupted password hash".into()))?;
+        let parse = || PasswordHash::new(stored)
+            .map_err(|e| AppError::InternalError(format!("Corrupted password hash: {e}")));
+        // Use the existing implementations to verify the password. I was doing this myself until
+        // I noticed that there is a PasswordVerifier trait, so this is better in every way. They
+        // take the algorithm and costs from the stored hash, not from `self.params`.
+        let verified = match kind {
+            HashKind::Argon2 => self.argon2().verify_password(password.as_bytes(), &parse()?),
+            HashKind::Scrypt => Scrypt.verify_password(password.as_bytes(), &parse()?),
+            HashKind::Pbkdf2 => Pbkdf2.verify_password(password.as_bytes(), &parse()?),
+            HashKind::Bcrypt => return bcrypt::verify(password, stored)
+                .map_err(|e| AppError::InternalError(format!("Corrupted bcrypt hash: {e}"))),
+        };
+        Ok(verified.is_ok())
+    }
+
+    /// Take as long as `verify` would, for a user that doesn't exist. Otherwise unknown usernames
+    /// would get their answer without the time it takes to run Argon2, and anybody timing the
+    /// login could tell which usernames are real.
+    pub fn verify_nobody(&self, password: &str) {
+        // The result doesn't matter, only the time it takes.
+        let _ = self.verify(password, &self.dummy_hash);
+    }
+
+    /// True if the stored hash is weaker than what `hash` would make now: a different algorithm
+    /// (or Argon2 version), or lower memory, iteration or parallelism costs than the config asks
+    /// for. Only call this after the password checked out, since redoing the hash needs it.
+    pub fn needs_rehash(&self, stored: &str) -> bool {
+        if HashKind::of(stored) != Some(HashKind::Argon2) {
+            return true;
+        }
+        let Ok(hash) = PasswordHash::new(stored) else {
+            return true;
+        };
+        if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+        match Params::try_from(&hash) {
+            Ok(params) => params.m_cost() < self.params.m_cost()
+                || params.t_cost() < self.params.t_cost()
+                || params.p_cost() < self.params.p_cost(),
+            Err(_) => true,
+        }
+    }
+}
@@ -0,0 +1,787 @@
+use sqlx::{FromRow, PgPool};
+use webauthn_rs::prelude::{Passkey, Uuid};
+use crate::user::*;
+use crate::auth_backend::AuthBackend;
+use crate::error_template::AppError;
+use crate::passkeys::StoredPasskey;
+use crate::store::*;
+use crate::tokens::to_hex;
+
+// This is the same store as sqlite_store.rs, for Postgres.
+//
+// The queries in here don't use the checked `query!` macros. Those need a database to check
+// against while compiling, and nobody wants to stand up a Postgres server to build the SQLite
+// version. The SQLite store gets the checking, and since the queries are nearly the same, that
+// catches most mistakes here too.
+
+/// The auth backend on top of Postgres.
+pub type PostgresBackend = AuthBackend<PostgresUserStore>;
+
+/// A user store that keeps everything in Postgres. Build with the `postgres` feature to use it.
+#[derive(Clone,Debug)]
+pub struct PostgresUserStore {
+    pub pool: PgPool,
+}
+
+impl PostgresUserStore {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresUserStore{pool}
+    }
+}
+
+#[derive(FromRow)]
+struct TotpQueryRow {
+    secret: String,
+    recovery_codes: String,
+    enabled_at: Option<i64>,
+}
+
+/// A row from the `user_sessions` table
+#[derive(FromRow)]
+struct SessionRow {
+    id: i64,
+    session_id: String,
+    user_id: DatabaseId,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    created_at: i64,
+    last_seen_at: i64,
+}
+
+impl From<SessionRow> for SessionEntry {
+    fn from(row: SessionRow) -> Self {
+        SessionEntry {
+            id: row.id,
+            session_id: row.session_id,
+            user_id: row.user_id,
+            user_agent: row.user_agent,
+            ip: row.ip,
+            created_at: row.created_at,
+            last_seen_at: row.last_seen_at,
+        }
+    }
+}
+
+/// A row from the `admin_audit` table
+#[derive(FromRow)]
+struct AuditRow {
+    id: i64,
+    created_at: i64,
+    actor_id: Option<DatabaseId>,
+    actor_name: String,
+    action: String,
+    target_id: Option<DatabaseId>,
+    target_name: Option<String>,
+    detail: Option<String>,
+}
+
+impl From<AuditRow> for AuditEntry {
+    fn from(row: AuditRow) -> Self {
+        AuditEntry {
+            id: row.id,
+            created_at: row.created_at,
+            actor_id: row.actor_id,
+            actor_name: row.actor_name,
+            action: row.action,
+            target_id: row.target_id,
+            target_name: row.target_name,
+            detail: row.detail,
+        }
+    }
+}
+
+/// A row from the `auth_events` table
+#[derive(FromRow)]
+struct AuthEventRow {
+    id: i64,
+    created_at: i64,
+    kind: String,
+    user_id: Option<DatabaseId>,
+    username: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    detail: Option<String>,
+}
+
+impl From<AuthEventRow> for AuthEvent {
+    fn from(row: AuthEventRow) -> Self {
+        AuthEvent {
+            id: row.id,
+            created_at: row.created_at,
+            kind: row.kind,
+            user_id: row.user_id,
+            username: row.username,
+            ip: row.ip,
+            user_agent: row.user_agent,
+            detail: row.detail,
+        }
+    }
+}
+
+/// A row from the `remember_tokens` table
+#[derive(FromRow)]
+struct RememberTokenRow {
+    id: i64,
+    user_id: DatabaseId,
+    selector: String,
+    validator_hash: String,
+    previous_validator_hash: Option<String>,
+    rotated_at: Option<i64>,
+    created_at: i64,
+    expires_at: i64,
+}
+
+impl From<RememberTokenRow> for RememberToken {
+    fn from(row: RememberTokenRow) -> Self {
+        RememberToken {
+            id: row.id,
+            user_id: row.user_id,
+            selector: row.selector,
+            validator_hash: row.validator_hash,
+            previous_validator_hash: row.previous_validator_hash,
+            rotated_at: row.rotated_at,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+        }
+    }
+}
+
+/// A row from the `webauthn_credentials` table, with the passkey still serialized.
+#[derive(FromRow)]
+struct PasskeyRow {
+    id: i64,
+    user_id: i64,
+    name: String,
+    passkey: String,
+    created_at: i64,
+    last_used_at: Option<i64>,
+}
+
+impl PasskeyRow {
+    fn into_stored(self) -> Result<StoredPasskey, AppError> {
+        Ok(StoredPasskey {
+            id: self.id,
+            name: self.name,
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            passkey: serde_json::from_str(&self.passkey)
+                .map_err(|e| AppError::InternalError(format!("Corrupted passkey: {e}")))?,
+        })
+    }
+}
+
+impl UserStore for PostgresUserStore {
+    /// The Postgres migrations live in their own directory, since the column types and such
+    /// aren't quite the same as SQLite's.
+    async fn migrate(&self) -> Result<(),AppError> {
+        Ok(sqlx::migrate!("db/pg_migrations")
+            .run(&self.pool)
+            .await
+            .map_err(|e| AppError::InternalError(format!("In migrations: {e}")))?)
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as("select * from users where username = $1")
+            .bind(username)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch user: {e}")))
+    }
+
+    async fn find_by_id(&self, id: DatabaseId) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as("select * from users where id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch user: {e}")))
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as("select * from users where email = $1")
+            .bind(email)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch user: {e}")))
+    }
+
+    async fn insert(&self, user: NewUser) -> Result<DatabaseId, AppError> {
+        sqlx::query_scalar("insert into users (username,pass_hash,email,session_secret) values ($1,$2,$3,$4) returning id")
+            .bind(user.username)
+            .bind(user.pass_hash.into_inner())
+            .bind(user.email)
+            .bind(user.session_secret.into_inner())
+            .fetch_one(&self.pool).await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() =>
+                    AppError::InvalidData("That username or email address is taken".into()),
+                e => AppError::InternalError(format!("Error inserting user: {e}")),
+            })
+    }
+
+    async fn update_password(&self, id: DatabaseId, pass_hash: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("update users set pass_hash = $1 where id = $2")
+            .bind(pass_hash)
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Update password: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn update_username(&self, id: DatabaseId, username: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("update users set username = $1 where id = $2")
+            .bind(username)
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() =>
+                    AppError::InvalidData("That username is taken".into()),
+                e => AppError::InternalError(format!("Update username: {e}")),
+            })?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_session_secret(&self, id: DatabaseId, secret: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("update users set session_secret = $1 where id = $2")
+            .bind(secret)
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Update session secret: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Everything else that belongs to the user goes along with them, thanks to the `on delete
+    /// cascade` on the foreign keys.
+    async fn delete(&self, id: DatabaseId) -> Result<bool, AppError> {
+        let result = sqlx::query("delete from users where id = $1")
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete user: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn mark_email_verified(&self, id: DatabaseId, at: i64) -> Result<(), AppError> {
+        sqlx::query("update users set verified_at = $1 where id = $2 and verified_at is null")
+            .bind(at)
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Mark email verified: {e}")))?;
+        Ok(())
+    }
+}
+
+impl PostgresUserStore {
+    /// Shared by `consume_token` and `consume_email_verification`, which needs it inside a
+    /// transaction.
+    async fn consume<'c, E>(kind: TokenKind, token_hash: &str, now: i64, executor: E) -> Result<Option<DatabaseId>, AppError>
+    where E: sqlx::PgExecutor<'c> {
+        let table = kind.table();
+        sqlx::query_scalar(&format!(
+            "update {table} set used_at = $1
+                where token_hash = $2 and used_at is null and expires_at > $1
+                returning user_id"))
+            .bind(now)
+            .bind(token_hash)
+            .fetch_optional(executor).await
+            .map_err(|e| AppError::InternalError(format!("Use token from {table}: {e}")))
+    }
+}
+
+impl TokenStore for PostgresUserStore {
+    async fn replace_token(&self, kind: TokenKind, user_id: DatabaseId, token_hash: &str, expires_at: i64, now: i64) -> Result<(), AppError> {
+        let table = kind.table();
+        let mut tx = self.pool.begin().await
+            .map_err(|e| AppError::InternalError(format!("Begin transaction: {e}")))?;
+        sqlx::query(&format!("delete from {table} where user_id = $1 or expires_at <= $2"))
+            .bind(user_id)
+            .bind(now)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Clear {table}: {e}")))?;
+        sqlx::query(&format!("insert into {table} (user_id, token_hash, expires_at) values ($1, $2, $3)"))
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(expires_at)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Insert into {table}: {e}")))?;
+        tx.commit().await
+            .map_err(|e| AppError::InternalError(format!("Commit transaction: {e}")))
+    }
+
+    async fn token_user(&self, kind: TokenKind, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        let table = kind.table();
+        sqlx::query_scalar(&format!(
+            "select user_id from {table} where token_hash = $1 and used_at is null and expires_at > $2"))
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch token from {table}: {e}")))
+    }
+
+    async fn consume_token(&self, kind: TokenKind, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        Self::consume(kind, token_hash, now, &self.pool).await
+    }
+
+    async fn consume_email_verification(&self, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| AppError::InternalError(format!("Begin transaction: {e}")))?;
+        let user_id = Self::consume(TokenKind::EmailVerification, token_hash, now, &mut *tx).await?;
+        if let Some(user_id) = user_id {
+            sqlx::query("update users set verified_at = $1 where id = $2")
+                .bind(now)
+                .bind(user_id)
+                .execute(&mut *tx).await
+                .map_err(|e| AppError::InternalError(format!("Mark email verified: {e}")))?;
+        }
+        tx.commit().await
+            .map_err(|e| AppError::InternalError(format!("Commit transaction: {e}")))?;
+        Ok(user_id)
+    }
+}
+
+impl TotpStore for PostgresUserStore {
+    async fn totp(&self, user_id: DatabaseId) -> Result<Option<TotpRow>, AppError> {
+        let row:Option<TotpQueryRow> = sqlx::query_as("select secret, recovery_codes, enabled_at from user_totp where user_id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch TOTP: {e}")))?;
+        Ok(row.map(|row| TotpRow{ secret: row.secret, recovery_codes: row.recovery_codes, enabled_at: row.enabled_at }))
+    }
+
+    async fn set_totp_secret(&self, user_id: DatabaseId, secret: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "insert into user_totp (user_id, secret) values ($1, $2)
+                on conflict(user_id) do update
+                set secret = excluded.secret, recovery_codes = '', enabled_at = null, last_used_step = null")
+            .bind(user_id)
+            .bind(secret)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Store TOTP secret: {e}")))?;
+        Ok(())
+    }
+
+    async fn enable_totp(&self, user_id: DatabaseId, enabled_at: i64, step: i64, recovery_codes: &str) -> Result<(), AppError> {
+        sqlx::query("update user_totp set enabled_at = $1, last_used_step = $2, recovery_codes = $3 where user_id = $4")
+            .bind(enabled_at)
+            .bind(step)
+            .bind(recovery_codes)
+            .bind(user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Enable TOTP: {e}")))?;
+        Ok(())
+    }
+
+    async fn delete_totp(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        sqlx::query("delete from user_totp where user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Disable TOTP: {e}")))?;
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, user_id: DatabaseId, step: i64) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "update user_totp set last_used_step = $1
+                where user_id = $2 and (last_used_step is null or last_used_step < $1)")
+            .bind(step)
+            .bind(user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Use TOTP code: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: DatabaseId, current: &str, remaining: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("update user_totp set recovery_codes = $1 where user_id = $2 and recovery_codes = $3")
+            .bind(remaining)
+            .bind(user_id)
+            .bind(current)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Use recovery code: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+impl OAuthStore for PostgresUserStore {
+    async fn link_oauth_account(&self, user_id: DatabaseId, provider: &str, subject: &str, email: Option<String>, now: i64) -> Result<(), AppError> {
+        sqlx::query("insert into oauth_accounts (user_id, provider, subject, email, created_at) values ($1, $2, $3, $4, $5)")
+            .bind(user_id)
+            .bind(provider)
+            .bind(subject)
+            .bind(email)
+            .bind(now)
+            .execute(&self.pool).await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() =>
+                    AppError::InvalidData("That account is already connected to another user".into()),
+                e => AppError::InternalError(format!("Link OAuth account: {e}")),
+            })?;
+        Ok(())
+    }
+
+    async fn find_by_oauth(&self, provider: &str, subject: &str) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as(
+            "select users.* from users
+                join oauth_accounts on oauth_accounts.user_id = users.id
+                where oauth_accounts.provider = $1 and oauth_accounts.subject = $2")
+            .bind(provider)
+            .bind(subject)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch OAuth user: {e}")))
+    }
+}
+
+impl PasskeyStore for PostgresUserStore {
+    async fn passkey_user_handle(&self, user_id: DatabaseId) -> Result<Option<Uuid>, AppError> {
+        let handle:Option<String> = sqlx::query_scalar("select user_handle from webauthn_credentials where user_id = $1 limit 1")
+            .bind(user_id)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch passkey user handle: {e}")))?;
+        handle.map(|handle| Uuid::parse_str(&handle)
+            .map_err(|e| AppError::InternalError(format!("Corrupted passkey user handle: {e}"))))
+            .transpose()
+    }
+
+    async fn list_passkeys(&self, user_id: DatabaseId) -> Result<Vec<StoredPasskey>, AppError> {
+        let rows:Vec<PasskeyRow> = sqlx::query_as(
+            "select id, user_id, name, passkey, created_at, last_used_at from webauthn_credentials
+                where user_id = $1 order by created_at, id")
+            .bind(user_id)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch passkeys: {e}")))?;
+        rows.into_iter().map(PasskeyRow::into_stored).collect()
+    }
+
+    async fn find_passkey(&self, credential_id: &str, user_handle: Uuid) -> Result<Option<(DatabaseId, StoredPasskey)>, AppError> {
+        let row:Option<PasskeyRow> = sqlx::query_as(
+            "select id, user_id, name, passkey, created_at, last_used_at from webauthn_credentials
+                where credential_id = $1 and user_handle = $2")
+            .bind(credential_id)
+            .bind(user_handle.to_string())
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch passkey: {e}")))?;
+        row.map(|row| Ok((row.user_id, row.into_stored()?))).transpose()
+    }
+
+    async fn insert_passkey(&self, user_id: DatabaseId, user_handle: Uuid, name: &str, passkey: &Passkey, now: i64) -> Result<(), AppError> {
+        let credential_id = to_hex(passkey.cred_id().as_ref());
+        let passkey = serde_json::to_string(passkey)
+            .map_err(|e| AppError::InternalError(format!("Serialize passkey: {e}")))?;
+        sqlx::query(
+            "insert into webauthn_credentials (user_id, user_handle, credential_id, name, passkey, created_at)
+                values ($1, $2, $3, $4, $5, $6)")
+            .bind(user_id)
+            .bind(user_handle.to_string())
+            .bind(credential_id)
+            .bind(name)
+            .bind(passkey)
+            .bind(now)
+            .execute(&self.pool).await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() =>
+                    AppError::InvalidData("That passkey is already registered".into()),
+                e => AppError::InternalError(format!("Insert passkey: {e}")),
+            })?;
+        Ok(())
+    }
+
+    async fn update_passkey(&self, id: i64, passkey: &Passkey, last_used_at: i64) -> Result<(), AppError> {
+        let passkey = serde_json::to_string(passkey)
+            .map_err(|e| AppError::InternalError(format!("Serialize passkey: {e}")))?;
+        sqlx::query("update webauthn_credentials set passkey = $1, last_used_at = $2 where id = $3")
+            .bind(passkey)
+            .bind(last_used_at)
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Update passkey: {e}")))?;
+        Ok(())
+    }
+
+    async fn delete_passkey(&self, user_id: DatabaseId, id: i64) -> Result<bool, AppError> {
+        let result = sqlx::query("delete from webauthn_credentials where id = $1 and user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete passkey: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+impl RoleStore for PostgresUserStore {
+    async fn user_roles(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        sqlx::query_scalar(
+            "select roles.name from roles
+                join user_roles on user_roles.role_id = roles.id
+                where user_roles.user_id = $1
+                order by roles.name")
+            .bind(user_id)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch roles: {e}")))
+    }
+
+    async fn grant_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        let role_id:i64 = sqlx::query_scalar("select id from roles where name = $1")
+            .bind(role)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch role: {e}")))?
+            .ok_or_else(|| AppError::InvalidData(format!("No such role: {role}")))?;
+        sqlx::query("insert into user_roles (user_id, role_id) values ($1, $2) on conflict do nothing")
+            .bind(user_id)
+            .bind(role_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Grant role: {e}")))?;
+        Ok(())
+    }
+
+    async fn revoke_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "delete from user_roles
+                where user_id = $1 and role_id = (select id from roles where name = $2)")
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Revoke role: {e}")))?;
+        Ok(())
+    }
+
+    async fn user_permissions(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        sqlx::query_scalar(
+            "select distinct permissions.name from permissions
+                join role_permissions on role_permissions.permission_id = permissions.id
+                join user_roles on user_roles.role_id = role_permissions.role_id
+                where user_roles.user_id = $1")
+            .bind(user_id)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch permissions: {e}")))
+    }
+}
+
+impl SessionIndexStore for PostgresUserStore {
+    async fn touch_session(&self, user_id: DatabaseId, session_id: &str, user_agent: Option<&str>, ip: &str, now: i64) -> Result<(), AppError> {
+        sqlx::query(
+            "insert into user_sessions (session_id, user_id, user_agent, ip, created_at, last_seen_at)
+                values ($1, $2, $3, $4, $5, $5)
+                on conflict (session_id) do update set
+                    user_id = excluded.user_id, user_agent = excluded.user_agent, ip = excluded.ip,
+                    last_seen_at = excluded.last_seen_at
+                where user_sessions.last_seen_at < excluded.last_seen_at - 60
+                    or user_sessions.user_id != excluded.user_id")
+            .bind(session_id)
+            .bind(user_id)
+            .bind(user_agent)
+            .bind(ip)
+            .bind(now)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Touch session: {e}")))?;
+        Ok(())
+    }
+
+    async fn list_sessions(&self, user_id: DatabaseId, since: i64) -> Result<Vec<SessionEntry>, AppError> {
+        sqlx::query("delete from user_sessions where last_seen_at < $1")
+            .bind(since)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Clean out sessions: {e}")))?;
+        let rows: Vec<SessionRow> = sqlx::query_as(
+            "select id, session_id, user_id, user_agent, ip, created_at, last_seen_at from user_sessions
+                where user_id = $1 order by last_seen_at desc")
+            .bind(user_id)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch sessions: {e}")))?;
+        Ok(rows.into_iter().map(SessionEntry::from).collect())
+    }
+
+    async fn remove_session(&self, user_id: DatabaseId, id: i64) -> Result<Option<SessionEntry>, AppError> {
+        let row: Option<SessionRow> = sqlx::query_as(
+            "delete from user_sessions where id = $1 and user_id = $2
+                returning id, session_id, user_id, user_agent, ip, created_at, last_seen_at")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Remove session: {e}")))?;
+        Ok(row.map(SessionEntry::from))
+    }
+
+    async fn forget_session(&self, session_id: &str) -> Result<(), AppError> {
+        sqlx::query("delete from user_sessions where session_id = $1")
+            .bind(session_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Forget session: {e}")))?;
+        Ok(())
+    }
+
+    async fn remove_user_sessions(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        sqlx::query("delete from user_sessions where user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Remove sessions: {e}")))?;
+        Ok(())
+    }
+}
+
+impl AdminStore for PostgresUserStore {
+    /// `strpos` rather than `like`, so a `%` or `_` in the query doesn't act as a wildcard.
+    async fn search_users(&self, query: &str, offset: i64, limit: i64) -> Result<(Vec<SqlUser>, i64), AppError> {
+        let filter = "where $1 = '' or strpos(lower(username), lower($1)) > 0 or strpos(lower(coalesce(email, '')), lower($1)) > 0";
+        let users = sqlx::query_as(&format!("select * from users {filter} order by id limit $2 offset $3"))
+            .bind(query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Search users: {e}")))?;
+        let total = sqlx::query_scalar(&format!("select count(*) from users {filter}"))
+            .bind(query)
+            .fetch_one(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Count users: {e}")))?;
+        Ok((users, total))
+    }
+
+    async fn set_disabled(&self, id: DatabaseId, disabled_at: Option<i64>) -> Result<bool, AppError> {
+        let result = sqlx::query("update users set disabled_at = $1 where id = $2")
+            .bind(disabled_at)
+            .bind(id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Disable user: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn insert_audit(&self, entry: NewAuditEntry) -> Result<(), AppError> {
+        sqlx::query(
+            "insert into admin_audit (created_at, actor_id, actor_name, action, target_id, target_name, detail)
+                values ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(entry.created_at)
+            .bind(entry.actor_id)
+            .bind(entry.actor_name)
+            .bind(entry.action)
+            .bind(entry.target_id)
+            .bind(entry.target_name)
+            .bind(entry.detail)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Write audit log: {e}")))?;
+        Ok(())
+    }
+
+    async fn list_audit(&self, offset: i64, limit: i64) -> Result<(Vec<AuditEntry>, i64), AppError> {
+        let rows: Vec<AuditRow> = sqlx::query_as(
+            "select id, created_at, actor_id, actor_name, action, target_id, target_name, detail from admin_audit
+                order by id desc limit $1 offset $2")
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch audit log: {e}")))?;
+        let total = sqlx::query_scalar("select count(*) from admin_audit")
+            .fetch_one(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Count audit log: {e}")))?;
+        Ok((rows.into_iter().map(AuditEntry::from).collect(), total))
+    }
+}
+
+impl AuthEventStore for PostgresUserStore {
+    async fn insert_auth_event(&self, event: NewAuthEvent) -> Result<(), AppError> {
+        sqlx::query(
+            "insert into auth_events (created_at, kind, user_id, username, ip, user_agent, detail)
+                values ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(event.created_at)
+            .bind(event.kind)
+            .bind(event.user_id)
+            .bind(event.username)
+            .bind(event.ip)
+            .bind(event.user_agent)
+            .bind(event.detail)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Write auth event: {e}")))?;
+        Ok(())
+    }
+
+    async fn list_auth_events(&self, filter: &AuthEventFilter, offset: i64, limit: i64) -> Result<(Vec<AuthEvent>, i64), AppError> {
+        // The casts are there because Postgres can't tell what type a parameter is from `$1 is null`.
+        let condition = "where ($1::text is null or kind = $1)
+            and ($2::bigint is null or user_id = $2)
+            and ($3::text is null or strpos(lower(coalesce(username, '')), lower($3)) > 0)
+            and ($4::text is null or ip = $4)";
+        let rows: Vec<AuthEventRow> = sqlx::query_as(&format!(
+            "select id, created_at, kind, user_id, username, ip, user_agent, detail from auth_events
+                {condition} order by id desc limit $5 offset $6"))
+            .bind(&filter.kind)
+            .bind(filter.user_id)
+            .bind(&filter.username)
+            .bind(&filter.ip)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch auth events: {e}")))?;
+        let total = sqlx::query_scalar(&format!("select count(*) from auth_events {condition}"))
+            .bind(&filter.kind)
+            .bind(filter.user_id)
+            .bind(&filter.username)
+            .bind(&filter.ip)
+            .fetch_one(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Count auth events: {e}")))?;
+        Ok((rows.into_iter().map(AuthEvent::from).collect(), total))
+    }
+
+    async fn prune_auth_events(&self, before: i64) -> Result<u64, AppError> {
+        let result = sqlx::query("delete from auth_events where created_at < $1")
+            .bind(before)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Prune auth events: {e}")))?;
+        Ok(result.rows_affected())
+    }
+}
+
+impl RememberTokenStore for PostgresUserStore {
+    async fn insert_remember_token(&self, token: NewRememberToken, now: i64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| AppError::InternalError(format!("Begin transaction: {e}")))?;
+        sqlx::query("delete from remember_tokens where expires_at <= $1")
+            .bind(now)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Clear remember tokens: {e}")))?;
+        sqlx::query(
+            "insert into remember_tokens (user_id, selector, validator_hash, created_at, expires_at)
+                values ($1, $2, $3, $4, $5)")
+            .bind(token.user_id)
+            .bind(token.selector)
+            .bind(token.validator_hash)
+            .bind(token.created_at)
+            .bind(token.expires_at)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Insert remember token: {e}")))?;
+        tx.commit().await
+            .map_err(|e| AppError::InternalError(format!("Commit transaction: {e}")))
+    }
+
+    async fn find_remember_token(&self, selector: &str, now: i64) -> Result<Option<RememberToken>, AppError> {
+        let row: Option<RememberTokenRow> = sqlx::query_as(
+            "select id, user_id, selector, validator_hash, previous_validator_hash, rotated_at, created_at, expires_at
+                from remember_tokens where selector = $1 and expires_at > $2")
+            .bind(selector)
+            .bind(now)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch remember token: {e}")))?;
+        Ok(row.map(RememberToken::from))
+    }
+
+    /// The check and the update happen in the same statement, like `consume_token`.
+    async fn rotate_remember_token(&self, selector: &str, current_hash: &str, new_hash: &str, now: i64) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "update remember_tokens set previous_validator_hash = validator_hash, validator_hash = $1, rotated_at = $2
+                where selector = $3 and validator_hash = $4")
+            .bind(new_hash)
+            .bind(now)
+            .bind(selector)
+            .bind(current_hash)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Rotate remember token: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_remember_token(&self, selector: &str) -> Result<(), AppError> {
+        sqlx::query("delete from remember_tokens where selector = $1")
+            .bind(selector)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete remember token: {e}")))?;
+        Ok(())
+    }
+
+    async fn delete_user_remember_tokens(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        sqlx::query("delete from remember_tokens where user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete remember tokens: {e}")))?;
+        Ok(())
+    }
+}
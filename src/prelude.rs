@@ -1,4 +1,5 @@
 pub use crate::{
     pages::*,
     auth::*,
+    components::*,
 };
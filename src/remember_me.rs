@@ -0,0 +1,105 @@
+use axum::http::{header, request::Parts, HeaderMap, HeaderValue};
+use axum_login::AuthSession;
+use leptos::prelude::*;
+use leptos_axum::ResponseOptions;
+use crate::auth_backend::RememberedLogin;
+use crate::auth_events::AuthEventKind;
+use crate::backend::AppBackend;
+use crate::config::ServerConfig;
+use crate::error_template::AppError;
+use crate::state::ClientInfo;
+use crate::user::DatabaseId;
+
+// The cookie side of "remember me". Sessions end after `session_timeout_seconds` without a
+// request, like always. A browser that was logged in with the box ticked also gets a cookie of its
+// own, and when it shows up without a live session, `server_func_handler` in main.rs hands it to
+// `restore_login`, which logs the browser back in and swaps the cookie for a new one. How the
+// tokens work, and how a stolen one gets noticed, is in auth_backend.rs.
+
+/// The name of the cookie.
+pub const REMEMBER_COOKIE: &str = "remember_me";
+
+/// The cookie's value from a request's headers, if it has one.
+pub fn remember_cookie(headers: &HeaderMap) -> Option<String> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == REMEMBER_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+/// A `Set-Cookie` header for the cookie. The cookie is only good for a login, so scripts don't get
+/// to see it, and it's only sent with https when the site is on https.
+fn set_cookie(value: &str, max_age: i64, config: &ServerConfig) -> HeaderValue {
+    let secure = if config.public_url.starts_with("https://") { "; Secure" } else { "" };
+    HeaderValue::from_str(&format!("{REMEMBER_COOKIE}={value}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}"))
+        .expect("remember-me cookies are made of hex digits and a colon")
+}
+
+/// Set the cookie to a value from `AuthBackend::issue_remember_token`.
+pub fn cookie_header(value: &str, config: &ServerConfig) -> HeaderValue {
+    set_cookie(value, config.remember_me_ttl_seconds, config)
+}
+
+/// Make the browser forget the cookie.
+pub fn clear_cookie_header(config: &ServerConfig) -> HeaderValue {
+    set_cookie("", 0, config)
+}
+
+/// True if the response already sets the cookie.
+pub fn sets_cookie(headers: &HeaderMap) -> bool {
+    headers.get_all(header::SET_COOKIE).iter()
+        .any(|value| value.as_bytes().starts_with(format!("{REMEMBER_COOKIE}=").as_bytes()))
+}
+
+/// Give this browser a cookie that keeps the user logged in. This is for server functions, since
+/// it sends the cookie along with their response.
+pub async fn remember_browser(backend: &AppBackend, user_id: DatabaseId) -> Result<(), AppError> {
+    let value = backend.issue_remember_token(user_id).await?;
+    let response: ResponseOptions = use_context().expect("response options not provided");
+    response.append_header(header::SET_COOKIE, cookie_header(&value, &backend.config));
+    Ok(())
+}
+
+/// Throw away this browser's remembered login, if it has one: the token comes out of the database
+/// and the cookie out of the browser. Also for server functions.
+pub async fn forget_browser(backend: &AppBackend) -> Result<(), AppError> {
+    let parts: Option<Parts> = use_context();
+    let Some(cookie) = parts.and_then(|parts| remember_cookie(&parts.headers)) else {
+        return Ok(());
+    };
+    backend.forget_remember_token(&cookie).await?;
+    let response: ResponseOptions = use_context().expect("response options not provided");
+    response.append_header(header::SET_COOKIE, clear_cookie_header(&backend.config));
+    Ok(())
+}
+
+/// If nobody is logged in and the request has the cookie, log its user back in. Returns the
+/// `Set-Cookie` header that has to go out with the response, if the cookie changed or has to go.
+pub async fn restore_login(auth: &mut AuthSession<AppBackend>, headers: &HeaderMap, client: &ClientInfo)
+-> Result<Option<HeaderValue>, AppError> {
+    if auth.user.is_some() {
+        return Ok(None);
+    }
+    let Some(cookie) = remember_cookie(headers) else {
+        return Ok(None);
+    };
+    match auth.backend.use_remember_token(&cookie).await? {
+        RememberedLogin::Valid{ user, cookie } => {
+            let header = cookie.map(|value| cookie_header(&value, &auth.backend.config));
+            auth.login(&user).await
+                .map_err(|e| AppError::InternalError(format!("Log in: {e}")))?;
+            auth.backend.record_event(AuthEventKind::LoginSucceeded, Some(user.id), Some(&user.username), Some(client), Some("remember me")).await;
+            tracing::info!(user_id = user.id, username = %user.username, "Logged back in with a remember-me cookie");
+            Ok(header)
+        },
+        RememberedLogin::Invalid => Ok(Some(clear_cookie_header(&auth.backend.config))),
+        RememberedLogin::Reused{ user_id } => {
+            let username = auth.backend.find_user_by_id(user_id).await?.map(|user| user.username);
+            auth.backend.record_event(AuthEventKind::RememberTokenReused, Some(user_id), username.as_deref(), Some(client), None).await;
+            tracing::warn!(user_id, ip = %client.ip, "A replaced remember-me cookie came back, logged the user out everywhere");
+            Ok(Some(clear_cookie_header(&auth.backend.config)))
+        },
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+use serde::{Deserialize,Serialize};
+
+/// A password, hash, key or anything else that must never end up in a log. `{:?}` and `{}` both
+/// print `<redacted>`, so it's safe to hand to `tracing` (or `dbg!`, or a derived `Debug` on
+/// whatever struct it's in) without thinking twice. Getting at the value takes a call to
+/// `expose`, which is easy to spot in review.
+///
+/// It (de)serializes as the value itself, so config fields and such can be wrapped without
+/// changing what the file looks like. That also means serializing it gives the secret away; only
+/// do that on purpose.
+#[derive(Clone,Default,PartialEq,Eq,Serialize,Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    /// The actual value. Don't log it.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
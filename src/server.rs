@@ -1,15 +1,137 @@
 use leptos::prelude::*;
-use leptos::logging::log;
+use serde::{Serialize,Deserialize};
 use crate::user::User;
+use crate::error_template::AppError;
 use cfg_if::cfg_if;
 
 cfg_if!{
     if #[cfg(feature="ssr")] {
-        use super::{sqlite_backend::SqliteBackend};
-        use axum_login::{AuthSession,AuthnBackend};
+        use crate::backend::AppBackend;
+        use axum_login::{AuthSession,AuthnBackend,AuthzBackend};
+        use crate::auth_events::AuthEventKind;
+        use crate::credentials::Credentials;
+        use crate::remember_me::{forget_browser, remember_browser};
+        use crate::state::{AppState, ClientInfo};
+        use crate::throttle::LoginThrottle;
+        use crate::user::DatabaseId;
+        use crate::tokens::now;
+
+        /// The session key axum_login keeps the logged-in user's id under (see main.rs). Sessions
+        /// from before users were identified by their database id have the username under
+        /// `LEGACY_AUTH_DATA_KEY` instead, which was axum_login's default; see
+        /// `upgrade_legacy_session`.
+        pub const AUTH_DATA_KEY: &str = "axum-login.user-id";
+        const LEGACY_AUTH_DATA_KEY: &str = "axum-login.data";
+
+        /// What axum_login used to keep in the session, when `User::id` was the username.
+        #[derive(Deserialize)]
+        struct LegacyAuthData {
+            user_id: Option<String>,
+            auth_hash: Option<Vec<u8>>,
+        }
+
+        /// Move a session from the days of username ids over to the database id. If the old data
+        /// still checks out (the user exists and the auth hash matches what it was back then, see
+        /// `find_legacy_session_user`), the user gets logged in again under the new key. Either
+        /// way the old data is gone afterward, so this only does any work once per session.
+        /// main.rs calls this before every server function.
+        pub async fn upgrade_legacy_session(
+            auth: &mut AuthSession<AppBackend>,
+            session: &tower_sessions::Session,
+        ) -> Result<(),AppError> {
+            let legacy = session.remove::<LegacyAuthData>(LEGACY_AUTH_DATA_KEY).await
+                .map_err(|e| AppError::InternalError(format!("Read old session data: {e}")))?;
+            let Some(LegacyAuthData{ user_id: Some(username), auth_hash }) = legacy else {
+                return Ok(());
+            };
+            if auth.user.is_some() {
+                return Ok(());
+            }
+            let user = match auth_hash {
+                Some(auth_hash) => auth.backend.find_legacy_session_user(&username, &auth_hash).await?,
+                None => None,
+            };
+            match user {
+                Some(user) => {
+                    auth.login(&user).await
+                        .map_err(|e| AppError::InternalError(format!("Log in: {e}")))?;
+                    tracing::info!(%username, user_id = user.id, "Moved an old session over to the user id");
+                },
+                None => tracing::info!(%username, "Dropped an old session, it doesn't check out anymore"),
+            }
+            Ok(())
+        }
+
+        /// The session key where `login_user` parks a user who still owes us a second factor.
+        const PENDING_SECOND_FACTOR_KEY: &str = "pending_second_factor";
+        /// How long the user gets to type in their code before they have to start over.
+        const SECOND_FACTOR_TIMEOUT_SECONDS: i64 = 5*60;
+
+        /// A user who got their password right but hasn't sent their TOTP code yet. This lives in
+        /// the session between `login_user` and `complete_login`.
+        #[derive(Clone,Debug,Serialize,Deserialize)]
+        struct PendingSecondFactor {
+            user_id: DatabaseId,
+            started_at: i64,
+            /// How the first step was done, for the event log. Logins that were already pending
+            /// when this was added don't have it.
+            #[serde(default)]
+            method: String,
+            /// Whether "remember me" was ticked
+            #[serde(default)]
+            remember: bool,
+        }
+
+        /// The last step of every way of logging in, once the user has proven who they are. Users
+        /// with TOTP turned on still owe us a code, so they get parked in the session (which does
+        /// *not* log them in) until `complete_login` gets it. Everybody else is logged in right away.
+        /// `method` is a word for the event log about how they proved it, like "password". With
+        /// `remember`, the browser also gets a "remember me" cookie once the login is done (see
+        /// remember_me.rs), which only works from a server function.
+        pub(crate) async fn finish_login(
+            auth: &mut AuthSession<AppBackend>,
+            session: &tower_sessions::Session,
+            user: User,
+            client: &ClientInfo,
+            method: &str,
+            remember: bool,
+        ) -> Result<LoginOutcome,AppError> {
+            if auth.backend.totp_enabled(user.id).await? {
+                session.insert(PENDING_SECOND_FACTOR_KEY, PendingSecondFactor{
+                    user_id: user.id,
+                    started_at: now(),
+                    method: method.to_string(),
+                    remember,
+                }).await
+                    .map_err(|e| AppError::InternalError(format!("Save pending login: {e}")))?;
+                return Ok(LoginOutcome::SecondFactorRequired);
+            }
+            // This is where the AuthSession actually finds out that the user is logged in. This
+            // will also be the first place where you actually get a session id sent back to the
+            // browser unless you've done other stuff with your sessions elsewhere.
+            auth.login(&user).await
+                .map_err(|e| AppError::InternalError(format!("Log in: {e}")))?;
+            auth.backend.record_event(AuthEventKind::LoginSucceeded, Some(user.id), Some(&user.username), Some(client), Some(method)).await;
+            if remember {
+                remember_browser(&auth.backend, user.id).await?;
+            }
+            Ok(LoginOutcome::LoggedIn(user))
+        }
     }
 }
 
+/// What happened when somebody tried to log in with their password.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub enum LoginOutcome {
+    /// All done, this user is logged in now
+    LoggedIn(User),
+    /// The password was right, but the user has two-factor auth turned on, so they need to send a
+    /// code to `complete_login` before they're actually logged in
+    SecondFactorRequired,
+    /// Wrong username or password
+    Failed,
+}
+
 
 /// require_login returns Some(user) if the user is logged in, and returns None otherwise. As a
 /// side-effect, it redirects the user to `/login` so that access can be authorized. By default,
@@ -37,11 +159,11 @@ pub async fn require_login(mut next:Option<String>) -> Result<Option<User>,Serve
         }
     };
     if let Some(user) = get_user().await? {
-        log!("require_login found user {}", user.username);
+        tracing::debug!(user_id = user.id, "require_login found a user");
         return Ok(Some(user))
     } else {
         use leptos_router::hooks::use_navigate;
-        log!("require_login: no logged-in user, redirecting to /login?c={return_to}");
+        tracing::debug!(%return_to, "require_login: no logged-in user, redirecting to the login page");
         // I want to be able to redirect, this is the way. 
         let nav = use_navigate();
         // 'c' in this stands for "next". Or maybe "continue", something like that... This call
@@ -51,72 +173,211 @@ pub async fn require_login(mut next:Option<String>) -> Result<Option<User>,Serve
     }
 }
     
+/// require_permission works like `require_login` (and sends you to `/login` the same way if nobody
+/// is logged in), but it only returns `Some(user)` if the user also has `permission`. A logged-in
+/// user without the permission gets `None` and stays where they are, so the page can tell them
+/// they aren't allowed in.
+#[allow(unused)]
+pub async fn require_permission(permission:String, next:Option<String>) -> Result<Option<User>,ServerFnError> {
+    match require_login(next).await? {
+        Some(user) if has_permission(permission).await? => Ok(Some(user)),
+        _ => Ok(None),
+    }
+}
+
+/// has_permission asks the `AuthzBackend` whether the logged-in user has been granted
+/// `permission` through one of their roles. It's always false when nobody is logged in. Use this
+/// from components (see `components::RequirePermission`); server functions that need to refuse
+/// service should call `ensure_permission` instead.
+#[server(name=HasPermission,prefix="/api",endpoint="has_permission")]
+pub async fn has_permission(permission: String) -> Result<bool,ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("session not provided");
+    match auth.user.as_ref() {
+        Some(user) => Ok(auth.backend.has_perm(user, permission).await?),
+        None => Ok(false),
+    }
+}
+
+/// ensure_permission is the server-side gate for other server functions. It returns the
+/// logged-in user if they have `permission`, or an error (`Unauthorized` if nobody is logged in,
+/// `Forbidden` if they lack the permission), so a protected server function can just start with
+/// `let user = ensure_permission("admin.access").await?;`
+#[cfg(feature="ssr")]
+pub async fn ensure_permission(permission: &str) -> Result<User,AppError> {
+    let auth: AuthSession<AppBackend> = use_context()
+        .ok_or_else(|| AppError::InternalError("auth session not provided".into()))?;
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    if auth.backend.has_perm(&user, permission.to_string()).await? {
+        Ok(user)
+    } else {
+        Err(AppError::Forbidden(permission.to_string()))
+    }
+}
+
 /// get_user tries to retrieve the user from the session. If there is a logged-in user,
 /// it will return `Some(user)`, otherwise it returns `None`. This is useful for checking
 /// login status in components before rendering stuff that either assumes a user, or shouldn't
 /// be accessible to the unauthorized.
 #[server(name=GetUser,prefix="/api",endpoint="get_user")]
 pub async fn get_user() -> Result<Option<User>,ServerFnError> {
-    let session: AuthSession<SqliteBackend> = use_context().expect("session not provided");
-    //log!("Session user: {:#?}", session.user.as_ref().map(|u| u.username));
+    let session: AuthSession<AppBackend> = use_context().expect("session not provided");
     Ok(session.user.clone())
 }
 
 /// Check the credentials and log the user in. This is the central purpose of this example! See the pages/login.rs
-/// file for an example of how this one is used.
+/// file for an example of how this one is used. Users with two-factor auth turned on aren't logged
+/// in yet when this returns `SecondFactorRequired`; that takes a call to `complete_login`.
+///
+/// `remember` is the "remember me" box, which keeps the browser logged in after its session runs
+/// out. It's a checkbox, so it's missing from the form when it isn't ticked.
 #[server(name=LoginUser,prefix="/api",endpoint="login")]
-pub async fn login_user(username: String, password: String) -> Result<Option<User>,ServerFnError> {
+pub async fn login_user(username: String, password: String, #[server(default)] remember: bool) -> Result<LoginOutcome,ServerFnError> {
     // Note that you can still use `leptos_axum::extract().await?` if you want, but since we
     // called `provide_context` from the `server_fn_handler` in `main`, we can do it this way
     // and it feels faster. Get the AuthSession.
-    let mut auth :AuthSession<SqliteBackend> = use_context().unwrap();
+    let mut auth :AuthSession<AppBackend> = use_context().unwrap();
     // If you want access to the actual session, you'll have to extract it separately because I couldn't
     // find a good way to get it out of the auth session.
     let session:tower_sessions::Session = use_context().unwrap();//leptos_axum::extract().await?;
-    // Advanced debugging tools
-    log!("Logging in user as '{username}'/'{password}'");
-    log!("Session id = {:?}",session.id());
-    // The SqliteBackend we defined has the `Self::Credential` type set to a `(String,String)` tuple
-    // which is meant to be the username/password pair. This is just an example, you probably want
-    // something more robust to handle different auth scenarios like Oauth and whatnot. Maybe I'll add
-    // those in later if I can figure out how.
-    let user = auth.backend.authenticate((username,password)).await?;
-
-    // If the authentication was successful, we actually have to tell the AuthSession that the user
-    // is now logged in. This happens when we call `auth.login(user)`. This will also be the first
-    // place where you actually get a session id sent back to the browser unless you've done other stuff
-    // with your sessions elsewhere.
-    if let Some(user) = user.as_ref() {
-        auth.login(user).await?;
-        Ok(Some(user.clone()))
-    } else {
-        // If anything else happened other than a successful auth, just return a failure.
-        Ok(None)
+    tracing::debug!(%username, session_id = ?session.id(), "Password login");
+    // The AppBackend we defined has the `Self::Credential` type set to the `Credentials` enum,
+    // which has a variant for each way of logging in. This page is the password one.
+    //
+    // The backend throttles failures per username. On top of that, one address only gets so many
+    // misses no matter which usernames it tries.
+    let client: ClientInfo = use_context().expect("client info not provided");
+    let ip_key = LoginThrottle::ip_key(client.ip);
+    let attempt = match auth.backend.throttle.check(&[&ip_key]) {
+        Ok(()) => auth.backend.authenticate(Credentials::password(username.clone(), password)).await,
+        Err(e) => Err(e),
+    };
+    let user = match attempt {
+        Ok(Some(user)) => user,
+        // If anything else happened other than a successful auth, just return a failure. Whatever
+        // it was goes into the event log, under the user it was aimed at if there is one, so it's
+        // in their own login history too.
+        failed => {
+            let target = auth.backend.find_user_by_name(&username).await?.map(|user| user.id);
+            let (kind, detail) = match &failed {
+                Err(e @ AppError::TooManyAttempts(_)) => (AuthEventKind::LockedOut, e.to_string()),
+                Err(e) => (AuthEventKind::LoginFailed, format!("password: {e}")),
+                Ok(_) => (AuthEventKind::LoginFailed, "password: wrong username or password".to_string()),
+            };
+            auth.backend.record_event(kind, target, Some(&username), Some(&client), Some(&detail)).await;
+            return match failed {
+                Err(e) => Err(e.into()),
+                Ok(_) => {
+                    auth.backend.throttle.record_failure(&[&ip_key]);
+                    Ok(LoginOutcome::Failed)
+                },
+            };
+        },
+    };
+
+    // The password was right, but for users with TOTP turned on that's only half of it.
+    // `finish_login` sorts that out.
+    Ok(finish_login(&mut auth, &session, user, &client, "password", remember).await?)
+}
+
+/// Whether somebody in this session got past the first step of logging in (a password, or one of
+/// the other ways) and still has to send a code to `complete_login`. The login page asks this when
+/// it loads, because some login methods end with a redirect to it instead of a `LoginOutcome`.
+#[server(name=SecondFactorPending,prefix="/api",endpoint="second_factor_pending")]
+pub async fn second_factor_pending() -> Result<bool,ServerFnError> {
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    Ok(session.get::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY).await?
+        .is_some_and(|pending| now() - pending.started_at <= SECOND_FACTOR_TIMEOUT_SECONDS))
+}
+
+/// The second half of a two-factor login. `code` is either the current code from the user's
+/// authenticator app or one of their recovery codes. Returns the user once they're logged in,
+/// or `None` if the code was wrong (they can try again until the pending login times out).
+#[server(name=CompleteLogin,prefix="/api",endpoint="complete_login")]
+pub async fn complete_login(code: String) -> Result<Option<User>,ServerFnError> {
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let Some(pending) = session.get::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY).await? else {
+        return Err(AppError::InvalidData("There's no login waiting for a code, please log in again".into()).into());
+    };
+    if now() - pending.started_at > SECOND_FACTOR_TIMEOUT_SECONDS {
+        session.remove::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY).await?;
+        return Err(AppError::InvalidData("That took too long, please log in again".into()).into());
+    }
+    let client: ClientInfo = use_context().expect("client info not provided");
+    let user = auth.backend.find_user_by_id(pending.user_id).await?.ok_or(AppError::NotFound)?;
+    let method = format!("{} and a second factor", pending.method);
+    match auth.backend.verify_second_factor(user.id, &code).await {
+        Ok(true) => {},
+        Ok(false) => {
+            let detail = format!("{method}: wrong code");
+            auth.backend.record_event(AuthEventKind::LoginFailed, Some(user.id), Some(&user.username), Some(&client), Some(&detail)).await;
+            return Ok(None);
+        },
+        Err(e) => {
+            let kind = match e {
+                AppError::TooManyAttempts(_) => AuthEventKind::LockedOut,
+                _ => AuthEventKind::LoginFailed,
+            };
+            let detail = format!("{method}: {e}");
+            auth.backend.record_event(kind, Some(user.id), Some(&user.username), Some(&client), Some(&detail)).await;
+            return Err(e.into());
+        },
+    }
+    session.remove::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY).await?;
+    // An admin could have disabled the user since the first step.
+    if auth.backend.is_disabled(user.id).await? {
+        let detail = format!("{method}: {}", AppError::AccountDisabled);
+        auth.backend.record_event(AuthEventKind::LoginFailed, Some(user.id), Some(&user.username), Some(&client), Some(&detail)).await;
+        return Err(AppError::AccountDisabled.into());
     }
+    auth.login(&user).await?;
+    auth.backend.record_event(AuthEventKind::LoginSucceeded, Some(user.id), Some(&user.username), Some(&client), Some(&method)).await;
+    if pending.remember {
+        remember_browser(&auth.backend, user.id).await?;
+    }
+    Ok(Some(user))
 }
 
 
 /// Add a user to the database and log them in, because I get annoyed by sites that let me register and then
 /// make me log in separately after that. Give me a break! This function is called from the Register component
 /// which is in pages/register.rs.
+///
+/// If an email address was given, a verification link gets mailed to it. When the server config has
+/// `require_verified_email` turned on, the new user is *not* logged in (they couldn't log in again
+/// anyway until they click the link), and this returns `None`.
+///
+/// The error is an `AppError` rather than a `ServerFnError` so that a password that breaks the
+/// policy arrives as `AppError::WeakPassword`, and the Register page can list what's wrong with it.
 #[server(name=RegisterNewUser,prefix="/api",endpoint="register")]
-pub async fn register_new_user(username: String, password: String) -> Result<Option<User>,ServerFnError> {
+pub async fn register_new_user(username: String, password: String, email: String) -> Result<Option<User>,AppError> {
+    use crate::pages::send_verification_email;
     // Extract the auth_session and session. You could also use `leptos_axum::extract().await` here,
     // but this seems nicer.
-    let mut auth_session:AuthSession<SqliteBackend> = use_context().expect("auth-session not provided");
+    let mut auth_session:AuthSession<AppBackend> = use_context().expect("auth-session not provided");
     let session:tower_sessions::Session = use_context().unwrap();
+    let app_state:AppState = use_context().expect("app state not provided");
+    // The email field is optional on the form, so it shows up as an empty string when it's skipped.
+    let email = Some(email.trim().to_string()).filter(|e| !e.is_empty());
     // The backend handles all of the password hashing and whatnot. Just call add_user and then go write
     // the backend, and it's all done!
-    let user = auth_session.backend.add_user(username,password).await?;
+    let user = auth_session.backend.add_user(username,password,email).await?;
+
+    if let Some(user) = user.as_ref() {
+        let client: ClientInfo = use_context().expect("client info not provided");
+        auth_session.backend.record_event(AuthEventKind::Registered, Some(user.id), Some(&user.username), Some(&client), None).await;
+        send_verification_email(&auth_session.backend, &app_state, user).await?;
+    }
+    if app_state.server_config.require_verified_email {
+        return Ok(None);
+    }
 
-    log!("get_user returned {user:#?}");
     if let Some(user) = user {
         // Tell the AuthSession that we're logged-in now and it should behave accordingly. This will set the
         // session id and send it to the browser as a side-effect (before now you likely had no session id in the browser).
-        log!("calling auth_session.login(user)");
-        dbg!(auth_session.login(&user).await)?;
-        log!("AuthSession user after register: {}", auth_session.user.as_ref().unwrap().username);
-        log!("Register - session id = {:#?}", session.id());
+        auth_session.login(&user).await
+            .map_err(|e| AppError::InternalError(format!("Log in: {e}")))?;
+        tracing::info!(user_id = user.id, username = %user.username, session_id = ?session.id(), "Registered and logged in");
         Ok(Some(user))
     } else {
         // Something went wrong? Fail silently!
@@ -124,4 +385,46 @@ pub async fn register_new_user(username: String, password: String) -> Result<Opt
     }
 }
 
+/// Log the current user out and get rid of their session completely. `AuthSession::logout` forgets
+/// the user, flushing the `Session` clears whatever else was stored in it, and deleting it from the
+/// session store makes sure the row in the sessions table is gone even if the cookie gets replayed
+/// later. A "remember me" cookie goes too, or the next request would log right back in. Logging
+/// out when nobody is logged in isn't an error, it just doesn't do much.
+#[server(name=LogoutUser,prefix="/api",endpoint="logout")]
+pub async fn logout_user() -> Result<(),ServerFnError> {
+    use tower_sessions::SessionStore;
+    let mut auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let session: tower_sessions::Session = use_context().expect("session not provided");
+    let app_state: AppState = use_context().expect("app state not provided");
+    // Grab the id first, because after the flush the session doesn't have one anymore.
+    let session_id = session.id();
+    forget_browser(&auth.backend).await?;
+    if let Some(user) = auth.logout().await? {
+        tracing::info!(user_id = user.id, username = %user.username, "Logged out");
+        // `delete_account` logs out through here too, and by then there's nobody left to log it
+        // about.
+        if auth.backend.find_user_by_id(user.id).await?.is_some() {
+            let client: Option<ClientInfo> = use_context();
+            auth.backend.record_event(AuthEventKind::LoggedOut, Some(user.id), Some(&user.username), client.as_ref(), None).await;
+        }
+    }
+    session.flush().await?;
+    if let Some(session_id) = session_id {
+        app_state.session_store.delete(&session_id).await?;
+        auth.backend.forget_session(&session_id.to_string()).await?;
+    }
+    Ok(())
+}
 
+/// Log the current user out of every session they have, on every device, this one included. The
+/// other sessions aren't found and deleted one by one; rotating the session secret makes every one
+/// of them fail the `session_auth_hash` check the next time it's used. This session is cleaned up
+/// like a normal logout.
+#[server(name=SignOutEverywhere,prefix="/api",endpoint="sign_out_everywhere")]
+pub async fn sign_out_everywhere() -> Result<(),ServerFnError> {
+    let auth: AuthSession<AppBackend> = use_context().expect("auth-session not provided");
+    let user = auth.user.clone().ok_or(AppError::Unauthorized)?;
+    auth.backend.rotate_session_secret(user.id).await?;
+    tracing::info!(user_id = user.id, username = %user.username, "Signed out everywhere");
+    logout_user().await
+}
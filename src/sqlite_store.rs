@@ -0,0 +1,608 @@
+use cfg_if::cfg_if;
+
+cfg_if!{
+    if #[cfg(feature="ssr")] {
+        use sqlx;
+        use sqlx::SqlitePool;
+        use crate::user::*;
+        use crate::auth_backend::AuthBackend;
+        use crate::passkeys::StoredPasskey;
+        use crate::store::*;
+        use crate::tokens::to_hex;
+        use webauthn_rs::prelude::{Passkey, Uuid};
+    }
+}
+use crate::error_template::AppError;
+
+pub static DB_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"),"/db/database.sqlite3");
+pub static MIGRATIONS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"),"/db/migrations");
+
+/// The auth backend on top of SQLite. This used to be a backend all by itself, before the storing
+/// and the deciding got split apart (see auth_backend.rs).
+pub type SqliteBackend = AuthBackend<SqliteUserStore>;
+
+/// This is a barebones example of a user store using sqlite3. The queries are checked against
+/// `db/database.sqlite3` when compiling, which is why that file is in the repo.
+#[derive(Clone,Debug)]
+pub struct SqliteUserStore {
+    pub pool: SqlitePool,
+}
+
+impl SqliteUserStore {
+    /// The "connection pool" is provided from the caller. In this case, the caller is `main`, so
+    /// check `main.rs` for details.
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteUserStore{pool}
+    }
+}
+
+/// A passkey row, with the passkey still serialized.
+struct PasskeyRow {
+    id: i64,
+    name: String,
+    passkey: String,
+    created_at: i64,
+    last_used_at: Option<i64>,
+}
+
+impl PasskeyRow {
+    fn into_stored(self) -> Result<StoredPasskey, AppError> {
+        Ok(StoredPasskey {
+            id: self.id,
+            name: self.name,
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            passkey: serde_json::from_str(&self.passkey)
+                .map_err(|e| AppError::InternalError(format!("Corrupted passkey: {e}")))?,
+        })
+    }
+}
+
+impl UserStore for SqliteUserStore {
+    /// Run `sqlx::migrate!` to make sure the database is up to date with the expected
+    /// schema.
+    async fn migrate(&self) -> Result<(),AppError> {
+        Ok(sqlx::migrate!("db/migrations")
+            .run(&self.pool)
+            .await
+            .map_err(|e| AppError::InternalError(format!("In migrations: {e}")))?)
+    }
+
+    async fn find_by_username(&self, username: &str) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as!(SqlUser, "select * from users where username = $1", username)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch user: {e}")))
+    }
+
+    async fn find_by_id(&self, id: DatabaseId) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as!(SqlUser, "select * from users where id = $1", id)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch user: {e}")))
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as!(SqlUser, "select * from users where email = $1", email)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch user: {e}")))
+    }
+
+    async fn insert(&self, user: NewUser) -> Result<DatabaseId, AppError> {
+        // `returning` hands back the new rowid. Other databases will have other ways of returning
+        // this to you.
+        sqlx::query_scalar!(r#"insert into users (username,pass_hash,email,session_secret) values ($1,$2,$3,$4) returning id as "id!""#,
+            user.username,
+            user.pass_hash.expose(),
+            user.email,
+            user.session_secret.expose(),
+        ).fetch_one(&self.pool).await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() =>
+                AppError::InvalidData("That username or email address is taken".into()),
+            e => AppError::InternalError(format!("Error inserting user: {e}")),
+        })
+    }
+
+    async fn update_password(&self, id: DatabaseId, pass_hash: &str) -> Result<bool, AppError> {
+        let result = sqlx::query!("update users set pass_hash = $1 where id = $2", pass_hash, id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Update password: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn update_username(&self, id: DatabaseId, username: &str) -> Result<bool, AppError> {
+        let result = sqlx::query!("update users set username = $1 where id = $2", username, id)
+            .execute(&self.pool).await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() =>
+                    AppError::InvalidData("That username is taken".into()),
+                e => AppError::InternalError(format!("Update username: {e}")),
+            })?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_session_secret(&self, id: DatabaseId, secret: &str) -> Result<bool, AppError> {
+        let result = sqlx::query!("update users set session_secret = $1 where id = $2", secret, id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Update session secret: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Everything else that belongs to the user goes along with them, thanks to the `on delete
+    /// cascade` on the foreign keys. sqlx turns foreign keys on for SQLite connections.
+    async fn delete(&self, id: DatabaseId) -> Result<bool, AppError> {
+        let result = sqlx::query!("delete from users where id = $1", id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete user: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn mark_email_verified(&self, id: DatabaseId, at: i64) -> Result<(), AppError> {
+        sqlx::query!("update users set verified_at = $1 where id = $2 and verified_at is null", at, id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Mark email verified: {e}")))?;
+        Ok(())
+    }
+}
+
+// The token queries are built at runtime because the table name depends on the kind of token, and
+// the checked macros only take literal SQL. The table names all come from `TokenKind::table`,
+// never from a request.
+impl TokenStore for SqliteUserStore {
+    async fn replace_token(&self, kind: TokenKind, user_id: DatabaseId, token_hash: &str, expires_at: i64, now: i64) -> Result<(), AppError> {
+        let table = kind.table();
+        let mut tx = self.pool.begin().await
+            .map_err(|e| AppError::InternalError(format!("Begin transaction: {e}")))?;
+        sqlx::query(&format!("delete from {table} where user_id = $1 or expires_at <= $2"))
+            .bind(user_id)
+            .bind(now)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Clear {table}: {e}")))?;
+        sqlx::query(&format!("insert into {table} (user_id, token_hash, expires_at) values ($1, $2, $3)"))
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(expires_at)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Insert into {table}: {e}")))?;
+        tx.commit().await
+            .map_err(|e| AppError::InternalError(format!("Commit transaction: {e}")))
+    }
+
+    async fn token_user(&self, kind: TokenKind, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        let table = kind.table();
+        sqlx::query_scalar(&format!(
+            "select user_id from {table} where token_hash = $1 and used_at is null and expires_at > $2"))
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch token from {table}: {e}")))
+    }
+
+    /// The check and the update happen in the same statement, so two requests racing with the
+    /// same token can't both get a user id back.
+    async fn consume_token(&self, kind: TokenKind, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        let table = kind.table();
+        sqlx::query_scalar(&format!(
+            "update {table} set used_at = $1
+                where token_hash = $2 and used_at is null and expires_at > $1
+                returning user_id"))
+            .bind(now)
+            .bind(token_hash)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Use token from {table}: {e}")))
+    }
+
+    /// The token and the user are updated in one transaction so a token can't get used up
+    /// without the verification sticking.
+    async fn consume_email_verification(&self, token_hash: &str, now: i64) -> Result<Option<DatabaseId>, AppError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| AppError::InternalError(format!("Begin transaction: {e}")))?;
+        let user_id:Option<DatabaseId> = sqlx::query_scalar!(
+            r#"update email_verification_tokens set used_at = $1
+                where token_hash = $2 and used_at is null and expires_at > $1
+                returning user_id as "user_id!""#, now, token_hash
+        ).fetch_optional(&mut *tx).await
+        .map_err(|e| AppError::InternalError(format!("Use verification token: {e}")))?;
+        if let Some(user_id) = user_id {
+            sqlx::query!("update users set verified_at = $1 where id = $2", now, user_id)
+                .execute(&mut *tx).await
+                .map_err(|e| AppError::InternalError(format!("Mark email verified: {e}")))?;
+        }
+        tx.commit().await
+            .map_err(|e| AppError::InternalError(format!("Commit transaction: {e}")))?;
+        Ok(user_id)
+    }
+}
+
+impl TotpStore for SqliteUserStore {
+    async fn totp(&self, user_id: DatabaseId) -> Result<Option<TotpRow>, AppError> {
+        sqlx::query_as!(TotpRow,
+            "select secret, recovery_codes, enabled_at from user_totp where user_id = $1", user_id
+        ).fetch_optional(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch TOTP: {e}")))
+    }
+
+    async fn set_totp_secret(&self, user_id: DatabaseId, secret: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            "insert into user_totp (user_id, secret) values ($1, $2)
+                on conflict(user_id) do update
+                set secret = excluded.secret, recovery_codes = '', enabled_at = null, last_used_step = null",
+            user_id, secret
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Store TOTP secret: {e}")))?;
+        Ok(())
+    }
+
+    async fn enable_totp(&self, user_id: DatabaseId, enabled_at: i64, step: i64, recovery_codes: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            "update user_totp set enabled_at = $1, last_used_step = $2, recovery_codes = $3 where user_id = $4",
+            enabled_at, step, recovery_codes, user_id
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Enable TOTP: {e}")))?;
+        Ok(())
+    }
+
+    async fn delete_totp(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        sqlx::query!("delete from user_totp where user_id = $1", user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Disable TOTP: {e}")))?;
+        Ok(())
+    }
+
+    /// The update only goes through if the row still looks the way the caller expects, so two
+    /// requests can't use the same code at once.
+    async fn use_totp_step(&self, user_id: DatabaseId, step: i64) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "update user_totp set last_used_step = $1
+                where user_id = $2 and (last_used_step is null or last_used_step < $1)",
+            step, user_id
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Use TOTP code: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: DatabaseId, current: &str, remaining: &str) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "update user_totp set recovery_codes = $1 where user_id = $2 and recovery_codes = $3",
+            remaining, user_id, current
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Use recovery code: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+impl OAuthStore for SqliteUserStore {
+    async fn link_oauth_account(&self, user_id: DatabaseId, provider: &str, subject: &str, email: Option<String>, now: i64) -> Result<(), AppError> {
+        sqlx::query!(
+            "insert into oauth_accounts (user_id, provider, subject, email, created_at) values ($1, $2, $3, $4, $5)",
+            user_id, provider, subject, email, now
+        ).execute(&self.pool).await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() =>
+                AppError::InvalidData("That account is already connected to another user".into()),
+            e => AppError::InternalError(format!("Link OAuth account: {e}")),
+        })?;
+        Ok(())
+    }
+
+    async fn find_by_oauth(&self, provider: &str, subject: &str) -> Result<Option<SqlUser>, AppError> {
+        sqlx::query_as!(SqlUser,
+            r#"select users.id as "id!", users.username as "username!", users.pass_hash as "pass_hash!",
+                    users.email, users.verified_at, users.session_secret, users.disabled_at
+                from users
+                join oauth_accounts on oauth_accounts.user_id = users.id
+                where oauth_accounts.provider = $1 and oauth_accounts.subject = $2"#,
+            provider, subject
+        ).fetch_optional(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch OAuth user: {e}")))
+    }
+}
+
+impl PasskeyStore for SqliteUserStore {
+    async fn passkey_user_handle(&self, user_id: DatabaseId) -> Result<Option<Uuid>, AppError> {
+        let handle = sqlx::query_scalar!(
+            "select user_handle from webauthn_credentials where user_id = $1 limit 1", user_id
+        ).fetch_optional(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch passkey user handle: {e}")))?;
+        handle.map(|handle| Uuid::parse_str(&handle)
+            .map_err(|e| AppError::InternalError(format!("Corrupted passkey user handle: {e}"))))
+            .transpose()
+    }
+
+    async fn list_passkeys(&self, user_id: DatabaseId) -> Result<Vec<StoredPasskey>, AppError> {
+        sqlx::query_as!(PasskeyRow,
+            "select id, name, passkey, created_at, last_used_at from webauthn_credentials
+                where user_id = $1 order by created_at, id", user_id
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch passkeys: {e}")))?
+        .into_iter()
+        .map(PasskeyRow::into_stored)
+        .collect()
+    }
+
+    async fn find_passkey(&self, credential_id: &str, user_handle: Uuid) -> Result<Option<(DatabaseId, StoredPasskey)>, AppError> {
+        let handle = user_handle.to_string();
+        let row = sqlx::query!(
+            "select id, user_id, name, passkey, created_at, last_used_at from webauthn_credentials
+                where credential_id = $1 and user_handle = $2",
+            credential_id, handle
+        ).fetch_optional(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch passkey: {e}")))?;
+        row.map(|row| Ok((row.user_id, PasskeyRow {
+            id: row.id,
+            name: row.name,
+            passkey: row.passkey,
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+        }.into_stored()?))).transpose()
+    }
+
+    async fn insert_passkey(&self, user_id: DatabaseId, user_handle: Uuid, name: &str, passkey: &Passkey, now: i64) -> Result<(), AppError> {
+        let handle = user_handle.to_string();
+        let credential_id = to_hex(passkey.cred_id().as_ref());
+        let passkey = serde_json::to_string(passkey)
+            .map_err(|e| AppError::InternalError(format!("Serialize passkey: {e}")))?;
+        sqlx::query!(
+            "insert into webauthn_credentials (user_id, user_handle, credential_id, name, passkey, created_at)
+                values ($1, $2, $3, $4, $5, $6)",
+            user_id, handle, credential_id, name, passkey, now
+        ).execute(&self.pool).await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() =>
+                AppError::InvalidData("That passkey is already registered".into()),
+            e => AppError::InternalError(format!("Insert passkey: {e}")),
+        })?;
+        Ok(())
+    }
+
+    async fn update_passkey(&self, id: i64, passkey: &Passkey, last_used_at: i64) -> Result<(), AppError> {
+        let passkey = serde_json::to_string(passkey)
+            .map_err(|e| AppError::InternalError(format!("Serialize passkey: {e}")))?;
+        sqlx::query!("update webauthn_credentials set passkey = $1, last_used_at = $2 where id = $3", passkey, last_used_at, id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Update passkey: {e}")))?;
+        Ok(())
+    }
+
+    async fn delete_passkey(&self, user_id: DatabaseId, id: i64) -> Result<bool, AppError> {
+        let result = sqlx::query!("delete from webauthn_credentials where id = $1 and user_id = $2", id, user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete passkey: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+impl RoleStore for SqliteUserStore {
+    async fn user_roles(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        sqlx::query_scalar!(
+            r#"select roles.name as "name!" from roles
+                join user_roles on user_roles.role_id = roles.id
+                where user_roles.user_id = $1
+                order by roles.name"#, user_id
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch roles: {e}")))
+    }
+
+    async fn grant_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        let role_id:i64 = sqlx::query_scalar!("select id from roles where name = $1", role)
+            .fetch_optional(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Fetch role: {e}")))?
+            .ok_or_else(|| AppError::InvalidData(format!("No such role: {role}")))?;
+        sqlx::query!("insert or ignore into user_roles (user_id, role_id) values ($1, $2)", user_id, role_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Grant role: {e}")))?;
+        Ok(())
+    }
+
+    async fn revoke_role(&self, user_id: DatabaseId, role: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            "delete from user_roles
+                where user_id = $1 and role_id = (select id from roles where name = $2)", user_id, role
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Revoke role: {e}")))?;
+        Ok(())
+    }
+
+    async fn user_permissions(&self, user_id: DatabaseId) -> Result<Vec<String>, AppError> {
+        sqlx::query_scalar!(
+            r#"select distinct permissions.name as "name!" from permissions
+                join role_permissions on role_permissions.permission_id = permissions.id
+                join user_roles on user_roles.role_id = role_permissions.role_id
+                where user_roles.user_id = $1"#, user_id
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch permissions: {e}")))
+    }
+}
+
+impl SessionIndexStore for SqliteUserStore {
+    async fn touch_session(&self, user_id: DatabaseId, session_id: &str, user_agent: Option<&str>, ip: &str, now: i64) -> Result<(), AppError> {
+        sqlx::query!(
+            "insert into user_sessions (session_id, user_id, user_agent, ip, created_at, last_seen_at)
+                values ($1, $2, $3, $4, $5, $6)
+                on conflict(session_id) do update set
+                    user_id = excluded.user_id, user_agent = excluded.user_agent, ip = excluded.ip,
+                    last_seen_at = excluded.last_seen_at
+                where user_sessions.last_seen_at < excluded.last_seen_at - 60
+                    or user_sessions.user_id != excluded.user_id",
+            session_id, user_id, user_agent, ip, now, now
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Touch session: {e}")))?;
+        Ok(())
+    }
+
+    async fn list_sessions(&self, user_id: DatabaseId, since: i64) -> Result<Vec<SessionEntry>, AppError> {
+        sqlx::query!("delete from user_sessions where last_seen_at < $1", since)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Clean out sessions: {e}")))?;
+        sqlx::query_as!(SessionEntry,
+            "select id, session_id, user_id, user_agent, ip, created_at, last_seen_at from user_sessions
+                where user_id = $1 order by last_seen_at desc", user_id
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch sessions: {e}")))
+    }
+
+    async fn remove_session(&self, user_id: DatabaseId, id: i64) -> Result<Option<SessionEntry>, AppError> {
+        sqlx::query_as!(SessionEntry,
+            "delete from user_sessions where id = $1 and user_id = $2
+                returning id, session_id, user_id, user_agent, ip, created_at, last_seen_at", id, user_id
+        ).fetch_optional(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Remove session: {e}")))
+    }
+
+    async fn forget_session(&self, session_id: &str) -> Result<(), AppError> {
+        sqlx::query!("delete from user_sessions where session_id = $1", session_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Forget session: {e}")))?;
+        Ok(())
+    }
+
+    async fn remove_user_sessions(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        sqlx::query!("delete from user_sessions where user_id = $1", user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Remove sessions: {e}")))?;
+        Ok(())
+    }
+}
+
+impl AdminStore for SqliteUserStore {
+    /// `instr` rather than `like`, so a `%` or `_` in the query doesn't act as a wildcard.
+    async fn search_users(&self, query: &str, offset: i64, limit: i64) -> Result<(Vec<SqlUser>, i64), AppError> {
+        let users = sqlx::query_as!(SqlUser,
+            "select * from users
+                where $1 = '' or instr(lower(username), lower($1)) > 0 or instr(lower(coalesce(email, '')), lower($1)) > 0
+                order by id limit $2 offset $3",
+            query, limit, offset
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Search users: {e}")))?;
+        let total = sqlx::query_scalar!(
+            r#"select count(*) as "count!: i64" from users
+                where $1 = '' or instr(lower(username), lower($1)) > 0 or instr(lower(coalesce(email, '')), lower($1)) > 0"#,
+            query
+        ).fetch_one(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Count users: {e}")))?;
+        Ok((users, total))
+    }
+
+    async fn set_disabled(&self, id: DatabaseId, disabled_at: Option<i64>) -> Result<bool, AppError> {
+        let result = sqlx::query!("update users set disabled_at = $1 where id = $2", disabled_at, id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Disable user: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn insert_audit(&self, entry: NewAuditEntry) -> Result<(), AppError> {
+        sqlx::query!(
+            "insert into admin_audit (created_at, actor_id, actor_name, action, target_id, target_name, detail)
+                values ($1, $2, $3, $4, $5, $6, $7)",
+            entry.created_at, entry.actor_id, entry.actor_name, entry.action, entry.target_id, entry.target_name, entry.detail
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Write audit log: {e}")))?;
+        Ok(())
+    }
+
+    async fn list_audit(&self, offset: i64, limit: i64) -> Result<(Vec<AuditEntry>, i64), AppError> {
+        let entries = sqlx::query_as!(AuditEntry,
+            "select id, created_at, actor_id, actor_name, action, target_id, target_name, detail from admin_audit
+                order by id desc limit $1 offset $2",
+            limit, offset
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch audit log: {e}")))?;
+        let total = sqlx::query_scalar!(r#"select count(*) as "count!: i64" from admin_audit"#)
+            .fetch_one(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Count audit log: {e}")))?;
+        Ok((entries, total))
+    }
+}
+
+impl AuthEventStore for SqliteUserStore {
+    async fn insert_auth_event(&self, event: NewAuthEvent) -> Result<(), AppError> {
+        sqlx::query!(
+            "insert into auth_events (created_at, kind, user_id, username, ip, user_agent, detail)
+                values ($1, $2, $3, $4, $5, $6, $7)",
+            event.created_at, event.kind, event.user_id, event.username, event.ip, event.user_agent, event.detail
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Write auth event: {e}")))?;
+        Ok(())
+    }
+
+    async fn list_auth_events(&self, filter: &AuthEventFilter, offset: i64, limit: i64) -> Result<(Vec<AuthEvent>, i64), AppError> {
+        let events = sqlx::query_as!(AuthEvent,
+            "select id, created_at, kind, user_id, username, ip, user_agent, detail from auth_events
+                where ($1 is null or kind = $1)
+                    and ($2 is null or user_id = $2)
+                    and ($3 is null or instr(lower(coalesce(username, '')), lower($3)) > 0)
+                    and ($4 is null or ip = $4)
+                order by id desc limit $5 offset $6",
+            filter.kind, filter.user_id, filter.username, filter.ip, limit, offset
+        ).fetch_all(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch auth events: {e}")))?;
+        let total = sqlx::query_scalar!(
+            r#"select count(*) as "count!: i64" from auth_events
+                where ($1 is null or kind = $1)
+                    and ($2 is null or user_id = $2)
+                    and ($3 is null or instr(lower(coalesce(username, '')), lower($3)) > 0)
+                    and ($4 is null or ip = $4)"#,
+            filter.kind, filter.user_id, filter.username, filter.ip
+        ).fetch_one(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Count auth events: {e}")))?;
+        Ok((events, total))
+    }
+
+    async fn prune_auth_events(&self, before: i64) -> Result<u64, AppError> {
+        let result = sqlx::query!("delete from auth_events where created_at < $1", before)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Prune auth events: {e}")))?;
+        Ok(result.rows_affected())
+    }
+}
+
+impl RememberTokenStore for SqliteUserStore {
+    async fn insert_remember_token(&self, token: NewRememberToken, now: i64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| AppError::InternalError(format!("Begin transaction: {e}")))?;
+        sqlx::query!("delete from remember_tokens where expires_at <= $1", now)
+            .execute(&mut *tx).await
+            .map_err(|e| AppError::InternalError(format!("Clear remember tokens: {e}")))?;
+        sqlx::query!(
+            "insert into remember_tokens (user_id, selector, validator_hash, created_at, expires_at)
+                values ($1, $2, $3, $4, $5)",
+            token.user_id, token.selector, token.validator_hash, token.created_at, token.expires_at
+        ).execute(&mut *tx).await
+        .map_err(|e| AppError::InternalError(format!("Insert remember token: {e}")))?;
+        tx.commit().await
+            .map_err(|e| AppError::InternalError(format!("Commit transaction: {e}")))
+    }
+
+    async fn find_remember_token(&self, selector: &str, now: i64) -> Result<Option<RememberToken>, AppError> {
+        sqlx::query_as!(RememberToken,
+            "select id, user_id, selector, validator_hash, previous_validator_hash, rotated_at, created_at, expires_at
+                from remember_tokens where selector = $1 and expires_at > $2",
+            selector, now
+        ).fetch_optional(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Fetch remember token: {e}")))
+    }
+
+    /// The check and the update happen in the same statement, like `consume_token`.
+    async fn rotate_remember_token(&self, selector: &str, current_hash: &str, new_hash: &str, now: i64) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "update remember_tokens set previous_validator_hash = validator_hash, validator_hash = $1, rotated_at = $2
+                where selector = $3 and validator_hash = $4",
+            new_hash, now, selector, current_hash
+        ).execute(&self.pool).await
+        .map_err(|e| AppError::InternalError(format!("Rotate remember token: {e}")))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_remember_token(&self, selector: &str) -> Result<(), AppError> {
+        sqlx::query!("delete from remember_tokens where selector = $1", selector)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete remember token: {e}")))?;
+        Ok(())
+    }
+
+    async fn delete_user_remember_tokens(&self, user_id: DatabaseId) -> Result<(), AppError> {
+        sqlx::query!("delete from remember_tokens where user_id = $1", user_id)
+            .execute(&self.pool).await
+            .map_err(|e| AppError::InternalError(format!("Delete remember tokens: {e}")))?;
+        Ok(())
+    }
+}
@@ -3,8 +3,12 @@ cfg_if::cfg_if!{
     if #[cfg(feature="ssr")] {
         use axum::extract::FromRef;
         use leptos::prelude::*;
-        use sqlx::SqlitePool;
+        use crate::backend::{DbPool, SessionStore};
+        use std::net::{IpAddr, SocketAddr};
+        use std::sync::Arc;
         use crate::config::ServerConfig;
+        use crate::mail::MailSender;
+        use crate::oauth::OAuthClients;
         
         /// This holds stuff I need to pass through to my server-side handler functions. YOU
         /// HAVE TO DERIVE `FromRef` ON THIS!!!! If you get an error message about LeptosOptions
@@ -12,9 +16,40 @@ cfg_if::cfg_if!{
         /// cursing before I remembered seeing something about that in one of the docs.
         #[derive(Clone,Debug,FromRef)]
         pub struct AppState {
-            pub pool: SqlitePool,
+            pub pool: DbPool,
+            /// The same store that the session layer uses, for when a session has to be removed
+            /// from the database on purpose instead of waiting for it to expire.
+            pub session_store: SessionStore,
             pub leptos_options: LeptosOptions,
             pub server_config: ServerConfig,
+            /// Sends the emails with password reset links and such. See mail.rs.
+            pub mailer: Arc<dyn MailSender>,
+            /// For logging in with OpenID Connect providers. See oauth.rs.
+            pub oauth: OAuthClients,
+        }
+
+        /// Who's on the other end of the current request, as far as we can tell. This gets put
+        /// into the context for server functions by `server_func_handler` in main.rs.
+        #[derive(Clone,Debug)]
+        pub struct ClientInfo {
+            /// The address the connection came from. If the app is behind a reverse proxy, this
+            /// is the proxy's address.
+            pub ip: IpAddr,
+            /// Whatever the browser put in the User-Agent header, if anything
+            pub user_agent: Option<String>,
+        }
+
+        impl ClientInfo {
+            /// Read it off a request: the address the connection came from, and its headers.
+            pub fn new(addr: SocketAddr, headers: &axum::http::HeaderMap) -> Self {
+                ClientInfo {
+                    ip: addr.ip(),
+                    user_agent: headers
+                        .get(axum::http::header::USER_AGENT)
+                        .and_then(|ua| ua.to_str().ok())
+                        .map(String::from),
+                }
+            }
         }
     }
 }
@@ -0,0 +1,324 @@
+use std::future::Future;
+use webauthn_rs::prelude::{Passkey, Uuid};
+use crate::error_template::AppError;
+use crate::passkeys::StoredPasskey;
+use crate::secret::Secret;
+use crate::user::{DatabaseId, SqlUser};
+
+// These traits are everything `AuthBackend` (see auth_backend.rs) needs from a database. The
+// stores only keep things and find them again. Hashing passwords, making tokens, checking codes
+// and deciding who gets to log in all happen in the backend, so a new store only has to get the
+// storing right.
+//
+// The methods are written out as `fn ... -> impl Future + Send` instead of `async fn` because
+// axum_login needs the backend's futures to be Send, and for a generic store that has to be
+// promised here in the trait. Implementations can still use `async fn`.
+//
+// There's one trait per kind of thing being stored, and `Store` is all of them together. The
+// implementations are sqlite_store.rs, postgres_store.rs and memory_store.rs.
+
+/// What it takes to make a new row in the users table. The password is already hashed.
+#[derive(Clone,Debug)]
+pub struct NewUser {
+    pub username: String,
+    pub pass_hash: Secret<String>,
+    pub email: Option<String>,
+    pub session_secret: Secret<String>,
+}
+
+/// Users themselves.
+pub trait UserStore: Clone + Send + Sync + 'static {
+    /// Bring the database up to date with the schema this version of the app expects.
+    fn migrate(&self) -> impl Future<Output = Result<(), AppError>> + Send;
+    fn find_by_username(&self, username: &str) -> impl Future<Output = Result<Option<SqlUser>, AppError>> + Send;
+    fn find_by_id(&self, id: DatabaseId) -> impl Future<Output = Result<Option<SqlUser>, AppError>> + Send;
+    /// Email addresses are unique, so there's at most one.
+    fn find_by_email(&self, email: &str) -> impl Future<Output = Result<Option<SqlUser>, AppError>> + Send;
+    /// Add a user and return their new id. A username that's taken is an `InvalidData` error.
+    fn insert(&self, user: NewUser) -> impl Future<Output = Result<DatabaseId, AppError>> + Send;
+    /// Replace the password hash. Returns false if there's no such user.
+    fn update_password(&self, id: DatabaseId, pass_hash: &str) -> impl Future<Output = Result<bool, AppError>> + Send;
+    /// Rename the user. A username that's taken is an `InvalidData` error. Returns false if there's
+    /// no such user.
+    fn update_username(&self, id: DatabaseId, username: &str) -> impl Future<Output = Result<bool, AppError>> + Send;
+    /// Replace the session secret, which ends all of the user's sessions. Returns false if there's
+    /// no such user.
+    fn set_session_secret(&self, id: DatabaseId, secret: &str) -> impl Future<Output = Result<bool, AppError>> + Send;
+    /// Remove the user, along with everything else in the store that belongs to them. Returns
+    /// false if there was no such user.
+    fn delete(&self, id: DatabaseId) -> impl Future<Output = Result<bool, AppError>> + Send;
+    /// Set `verified_at`, unless the address was verified already.
+    fn mark_email_verified(&self, id: DatabaseId, at: i64) -> impl Future<Output = Result<(), AppError>> + Send;
+}
+
+/// The different kinds of single-use tokens that get mailed to users. They all work the same way,
+/// each in a table of its own.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum TokenKind {
+    PasswordReset,
+    EmailVerification,
+    MagicLink,
+}
+
+impl TokenKind {
+    /// The table that tokens of this kind live in, for the SQL stores.
+    pub fn table(self) -> &'static str {
+        match self {
+            TokenKind::PasswordReset => "password_reset_tokens",
+            TokenKind::EmailVerification => "email_verification_tokens",
+            TokenKind::MagicLink => "magic_link_tokens",
+        }
+    }
+}
+
+/// The mailed tokens. Only their hashes ever get here.
+pub trait TokenStore: Clone + Send + Sync + 'static {
+    /// Store a new token for the user. Any older tokens of the same kind that the user had are
+    /// removed, so only the newest one works, and expired ones (anybody's) get cleaned out.
+    fn replace_token(&self, kind: TokenKind, user_id: DatabaseId, token_hash: &str, expires_at: i64, now: i64)
+    -> impl Future<Output = Result<(), AppError>> + Send;
+    /// The user a token belongs to, if it's unused and hasn't expired. This doesn't use it up.
+    fn token_user(&self, kind: TokenKind, token_hash: &str, now: i64)
+    -> impl Future<Output = Result<Option<DatabaseId>, AppError>> + Send;
+    /// Use a token up, returning its user if it was still good. Two requests racing with the same
+    /// token must not both get a user back.
+    fn consume_token(&self, kind: TokenKind, token_hash: &str, now: i64)
+    -> impl Future<Output = Result<Option<DatabaseId>, AppError>> + Send;
+    /// Use up an email verification token and mark the user's address verified, both or neither.
+    fn consume_email_verification(&self, token_hash: &str, now: i64)
+    -> impl Future<Output = Result<Option<DatabaseId>, AppError>> + Send;
+}
+
+/// A user's TOTP settings, from the `user_totp` table.
+#[derive(Clone,Debug)]
+pub struct TotpRow {
+    /// Encrypted, see totp.rs
+    pub secret: String,
+    /// Hashes of the unused recovery codes, one per line
+    pub recovery_codes: String,
+    pub enabled_at: Option<i64>,
+}
+
+/// TOTP secrets and recovery codes.
+pub trait TotpStore: Clone + Send + Sync + 'static {
+    fn totp(&self, user_id: DatabaseId) -> impl Future<Output = Result<Option<TotpRow>, AppError>> + Send;
+    /// Store a new (not yet enabled) secret, throwing away whatever TOTP settings the user had.
+    fn set_totp_secret(&self, user_id: DatabaseId, secret: &str) -> impl Future<Output = Result<(), AppError>> + Send;
+    fn enable_totp(&self, user_id: DatabaseId, enabled_at: i64, step: i64, recovery_codes: &str)
+    -> impl Future<Output = Result<(), AppError>> + Send;
+    fn delete_totp(&self, user_id: DatabaseId) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// Record that a code for `step` was used. Only works (returns true) if `step` is later than
+    /// the last one used.
+    fn use_totp_step(&self, user_id: DatabaseId, step: i64) -> impl Future<Output = Result<bool, AppError>> + Send;
+    /// Swap the recovery codes for `remaining`, but only if they're still `current`. Returns false
+    /// if somebody else got there first.
+    fn replace_recovery_codes(&self, user_id: DatabaseId, current: &str, remaining: &str)
+    -> impl Future<Output = Result<bool, AppError>> + Send;
+}
+
+/// Links between local users and accounts at OAuth providers.
+pub trait OAuthStore: Clone + Send + Sync + 'static {
+    /// An account that's already linked to somebody is an `InvalidData` error.
+    fn link_oauth_account(&self, user_id: DatabaseId, provider: &str, subject: &str, email: Option<String>, now: i64)
+    -> impl Future<Output = Result<(), AppError>> + Send;
+    fn find_by_oauth(&self, provider: &str, subject: &str) -> impl Future<Output = Result<Option<SqlUser>, AppError>> + Send;
+}
+
+/// Passkeys (WebAuthn credentials). Credential ids are hex encoded.
+pub trait PasskeyStore: Clone + Send + Sync + 'static {
+    /// The user handle the user's passkeys share, if they have any.
+    fn passkey_user_handle(&self, user_id: DatabaseId) -> impl Future<Output = Result<Option<Uuid>, AppError>> + Send;
+    /// Oldest first
+    fn list_passkeys(&self, user_id: DatabaseId) -> impl Future<Output = Result<Vec<StoredPasskey>, AppError>> + Send;
+    /// The passkey with this credential id and user handle, and the user it belongs to.
+    fn find_passkey(&self, credential_id: &str, user_handle: Uuid)
+    -> impl Future<Output = Result<Option<(DatabaseId, StoredPasskey)>, AppError>> + Send;
+    /// A credential id that's already registered is an `InvalidData` error.
+    fn insert_passkey(&self, user_id: DatabaseId, user_handle: Uuid, name: &str, passkey: &Passkey, now: i64)
+    -> impl Future<Output = Result<(), AppError>> + Send;
+    /// Save the passkey again after a login updated its counter.
+    fn update_passkey(&self, id: i64, passkey: &Passkey, last_used_at: i64) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// Returns false if the user doesn't have a passkey with that id.
+    fn delete_passkey(&self, user_id: DatabaseId, id: i64) -> impl Future<Output = Result<bool, AppError>> + Send;
+}
+
+/// Roles and the permissions they carry.
+pub trait RoleStore: Clone + Send + Sync + 'static {
+    /// Role names, sorted
+    fn user_roles(&self, user_id: DatabaseId) -> impl Future<Output = Result<Vec<String>, AppError>> + Send;
+    /// The role has to exist already, or it's an `InvalidData` error. Granting a role twice is fine.
+    fn grant_role(&self, user_id: DatabaseId, role: &str) -> impl Future<Output = Result<(), AppError>> + Send;
+    fn revoke_role(&self, user_id: DatabaseId, role: &str) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// Every permission the user gets from any of their roles
+    fn user_permissions(&self, user_id: DatabaseId) -> impl Future<Output = Result<Vec<String>, AppError>> + Send;
+}
+
+/// One of a user's sessions, from the `user_sessions` table.
+#[derive(Clone,Debug)]
+pub struct SessionEntry {
+    /// Our own id for the entry. This is what gets shown to the browser, never `session_id`.
+    pub id: i64,
+    /// The id tower-sessions gave the session, which is also what's in the session cookie
+    pub session_id: String,
+    pub user_id: DatabaseId,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+}
+
+/// Which sessions belong to which user. The sessions themselves are in the session store; this
+/// is only an index next to it.
+pub trait SessionIndexStore: Clone + Send + Sync + 'static {
+    /// Note that the user's session was used at `now`. A session we haven't seen gets a new entry;
+    /// one we have gets its `last_seen_at`, user agent and IP updated, but only if it was last
+    /// seen more than a minute ago, since this happens on every request.
+    fn touch_session(&self, user_id: DatabaseId, session_id: &str, user_agent: Option<&str>, ip: &str, now: i64)
+    -> impl Future<Output = Result<(), AppError>> + Send;
+    /// The user's sessions that have been seen since `since`, most recently seen first. Entries
+    /// older than that (anybody's) are cleaned out, since their sessions have expired.
+    fn list_sessions(&self, user_id: DatabaseId, since: i64) -> impl Future<Output = Result<Vec<SessionEntry>, AppError>> + Send;
+    /// Remove the user's entry with this id, and return it. None if the user has no such entry.
+    fn remove_session(&self, user_id: DatabaseId, id: i64) -> impl Future<Output = Result<Option<SessionEntry>, AppError>> + Send;
+    /// Remove the entry for a session that's over, like after a logout.
+    fn forget_session(&self, session_id: &str) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// Remove all of the user's entries.
+    fn remove_user_sessions(&self, user_id: DatabaseId) -> impl Future<Output = Result<(), AppError>> + Send;
+}
+
+/// Something an admin did in the admin console, for the `admin_audit` table. The names are
+/// copies, so the record still makes sense after the users are gone.
+#[derive(Clone,Debug)]
+pub struct NewAuditEntry {
+    pub created_at: i64,
+    pub actor_id: DatabaseId,
+    pub actor_name: String,
+    /// Short and fixed, like `disable_user`
+    pub action: String,
+    pub target_id: Option<DatabaseId>,
+    pub target_name: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// A row from the `admin_audit` table. `actor_id` is None once the admin's own account is gone.
+#[derive(Clone,Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: i64,
+    pub actor_id: Option<DatabaseId>,
+    pub actor_name: String,
+    pub action: String,
+    pub target_id: Option<DatabaseId>,
+    pub target_name: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// What the admin console needs, beyond what the other traits already do.
+pub trait AdminStore: Clone + Send + Sync + 'static {
+    /// Users whose username or email address contains `query`, ignoring case, in order of id.
+    /// Returns `limit` of them, skipping the first `offset`, and how many matched in all. An empty
+    /// query matches everybody.
+    fn search_users(&self, query: &str, offset: i64, limit: i64)
+    -> impl Future<Output = Result<(Vec<SqlUser>, i64), AppError>> + Send;
+    /// Set `disabled_at`, or clear it with None. Returns false if there's no such user.
+    fn set_disabled(&self, id: DatabaseId, disabled_at: Option<i64>) -> impl Future<Output = Result<bool, AppError>> + Send;
+    fn insert_audit(&self, entry: NewAuditEntry) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// Newest first, paged like `search_users`, with the total count.
+    fn list_audit(&self, offset: i64, limit: i64) -> impl Future<Output = Result<(Vec<AuditEntry>, i64), AppError>> + Send;
+}
+
+/// A row for the `auth_events` table (see auth_events.rs). `kind` is `AuthEventKind::as_str`.
+#[derive(Clone,Debug)]
+pub struct NewAuthEvent {
+    pub created_at: i64,
+    pub kind: String,
+    pub user_id: Option<DatabaseId>,
+    /// The username, or for a failed login, whatever was typed in as one
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// A row from the `auth_events` table. `user_id` is None once the user is gone, or if there never
+/// was one.
+#[derive(Clone,Debug)]
+pub struct AuthEvent {
+    pub id: i64,
+    pub created_at: i64,
+    pub kind: String,
+    pub user_id: Option<DatabaseId>,
+    pub username: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// Which events `list_auth_events` returns. Each field that's None matches everything.
+#[derive(Clone,Debug,Default)]
+pub struct AuthEventFilter {
+    pub kind: Option<String>,
+    pub user_id: Option<DatabaseId>,
+    /// Matches usernames that contain this, ignoring case
+    pub username: Option<String>,
+    /// Has to match exactly
+    pub ip: Option<String>,
+}
+
+/// The authentication event log.
+pub trait AuthEventStore: Clone + Send + Sync + 'static {
+    fn insert_auth_event(&self, event: NewAuthEvent) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// The events that match `filter`, newest first. Returns `limit` of them, skipping the first
+    /// `offset`, and how many matched in all.
+    fn list_auth_events(&self, filter: &AuthEventFilter, offset: i64, limit: i64)
+    -> impl Future<Output = Result<(Vec<AuthEvent>, i64), AppError>> + Send;
+    /// Delete the events from before `before`, and return how many there were.
+    fn prune_auth_events(&self, before: i64) -> impl Future<Output = Result<u64, AppError>> + Send;
+}
+
+/// A row for the `remember_tokens` table. Only the validator's hash gets here.
+#[derive(Clone,Debug)]
+pub struct NewRememberToken {
+    pub user_id: DatabaseId,
+    pub selector: String,
+    pub validator_hash: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// A row from the `remember_tokens` table.
+#[derive(Clone,Debug)]
+pub struct RememberToken {
+    pub id: i64,
+    pub user_id: DatabaseId,
+    pub selector: String,
+    pub validator_hash: String,
+    /// The hash the validator had before it was last rotated, if it has been
+    pub previous_validator_hash: Option<String>,
+    pub rotated_at: Option<i64>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// "Remember me" tokens, the ones that log a browser back in after its session is gone.
+pub trait RememberTokenStore: Clone + Send + Sync + 'static {
+    /// Expired tokens (anybody's) get cleaned out at the same time.
+    fn insert_remember_token(&self, token: NewRememberToken, now: i64) -> impl Future<Output = Result<(), AppError>> + Send;
+    /// The token with this selector, if it hasn't expired.
+    fn find_remember_token(&self, selector: &str, now: i64) -> impl Future<Output = Result<Option<RememberToken>, AppError>> + Send;
+    /// Swap the validator hash for `new_hash`, keeping `current_hash` as the previous one, but only
+    /// if it's still `current_hash`. Two requests racing with the same cookie must not both get
+    /// true back.
+    fn rotate_remember_token(&self, selector: &str, current_hash: &str, new_hash: &str, now: i64)
+    -> impl Future<Output = Result<bool, AppError>> + Send;
+    fn delete_remember_token(&self, selector: &str) -> impl Future<Output = Result<(), AppError>> + Send;
+    fn delete_user_remember_tokens(&self, user_id: DatabaseId) -> impl Future<Output = Result<(), AppError>> + Send;
+}
+
+/// Everything at once. This is what `AuthBackend` is generic over.
+pub trait Store: UserStore + TokenStore + TotpStore + OAuthStore + PasskeyStore + RoleStore + SessionIndexStore
+    + AdminStore + AuthEventStore + RememberTokenStore + std::fmt::Debug {}
+
+impl<S> Store for S
+where S: UserStore + TokenStore + TotpStore + OAuthStore + PasskeyStore + RoleStore + SessionIndexStore
+    + AdminStore + AuthEventStore + RememberTokenStore + std::fmt::Debug {}
@@ -0,0 +1,46 @@
+use serde::{Deserialize,Serialize};
+
+/// How hard a password would be to guess, and what would make it harder. This comes from zxcvbn,
+/// which looks for dictionary words, names, keyboard walks (`qwerty`), sequences (`abc`, `6543`),
+/// repeats, dates and such, and estimates how many guesses it would take to get through them.
+///
+/// It compiles for both csr and ssr, so the meter on the Register page shows the same score that
+/// the password policy on the server checks (`min_score`, see password_policy.rs).
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub struct Strength {
+    /// 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    /// What's wrong with it, like "This is a top-10 common password."
+    pub warning: Option<String>,
+    /// How to fix it, like "Avoid sequences."
+    pub suggestions: Vec<String>,
+}
+
+impl Strength {
+    /// The best possible score
+    pub const MAX_SCORE: u8 = 4;
+
+    /// Estimate the strength of `password`. `user_inputs` are other things the user typed in (the
+    /// username, the email address), which count as dictionary words for this password.
+    pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+        // zxcvbn doesn't have anything useful to say about an empty password.
+        if password.is_empty() {
+            return Strength{ score: 0, warning: None, suggestions: Vec::new() };
+        }
+        let entropy = zxcvbn::zxcvbn(password, user_inputs);
+        let feedback = entropy.feedback();
+        Strength {
+            score: entropy.score() as u8,
+            warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+            suggestions: feedback
+                .map(|f| f.suggestions().iter().map(ToString::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The score as a percentage, for the width of a meter. Even a score of 0 gets a sliver, so
+    /// there's something to see once the user starts typing.
+    pub fn percent(&self) -> u8 {
+        (self.score + 1) * 20
+    }
+}
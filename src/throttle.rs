@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use crate::config::ServerConfig;
+use crate::error_template::AppError;
+use crate::tokens::now;
+
+/// Once there are this many keys being tracked, old ones get cleaned out on the next failure.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Failures recorded for one key.
+#[derive(Clone,Debug,Default)]
+struct Attempts {
+    failures: u32,
+    last_failure: i64,
+    locked_until: i64,
+}
+
+/// Keeps track of failed logins so that passwords (and TOTP codes) can't be guessed as fast as the
+/// server can answer. Every attempt is filed under one or more keys, like the username and the
+/// client's IP address. After `login_max_attempts` failures for a key, that key gets locked out
+/// for `login_lockout_seconds`, and every failure after that doubles the lockout, up to
+/// `login_max_lockout_seconds`. A key that hasn't failed for that long is forgotten.
+///
+/// This lives in memory, so a restart forgets everything, and each server process counts
+/// separately. That's fine for one server; more than that would need this in the database.
+#[derive(Clone,Debug)]
+pub struct LoginThrottle {
+    attempts: Arc<Mutex<HashMap<String, Attempts>>>,
+    max_attempts: u32,
+    lockout_seconds: i64,
+    max_lockout_seconds: i64,
+}
+
+impl LoginThrottle {
+    pub fn new(config: &ServerConfig) -> Self {
+        LoginThrottle {
+            attempts: Default::default(),
+            max_attempts: config.login_max_attempts,
+            lockout_seconds: config.login_lockout_seconds,
+            max_lockout_seconds: config.login_max_lockout_seconds,
+        }
+    }
+
+    /// The key for attempts at logging in as a particular user. Usernames are case-folded so
+    /// `Alice` and `alice` share a counter.
+    pub fn user_key(username: &str) -> String {
+        format!("user:{}", username.to_lowercase())
+    }
+
+    /// The key for all of the attempts coming from one address.
+    pub fn ip_key(ip: IpAddr) -> String {
+        format!("ip:{ip}")
+    }
+
+    /// The key for second factor attempts on a user's account.
+    pub fn second_factor_key(user_id: i64) -> String {
+        format!("2fa:{user_id}")
+    }
+
+    /// Return `TooManyAttempts` if any of the keys are locked out right now. Call this *before*
+    /// checking the password, otherwise the lockout doesn't stop anything.
+    pub fn check(&self, keys: &[&str]) -> Result<(), AppError> {
+        let now = now();
+        let attempts = self.attempts.lock().expect("login throttle lock poisoned");
+        let locked_until = keys.iter()
+            .filter_map(|key| attempts.get(*key))
+            .map(|a| a.locked_until)
+            .max()
+            .unwrap_or(0);
+        if locked_until > now {
+            Err(AppError::TooManyAttempts(locked_until - now))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Count a failure against every one of the keys.
+    pub fn record_failure(&self, keys: &[&str]) {
+        let now = now();
+        let mut attempts = self.attempts.lock().expect("login throttle lock poisoned");
+        if attempts.len() > PRUNE_THRESHOLD {
+            attempts.retain(|_, a| now - a.last_failure < self.max_lockout_seconds);
+        }
+        for key in keys {
+            let entry = attempts.entry(key.to_string()).or_default();
+            // Old failures are forgiven
+            if now - entry.last_failure >= self.max_lockout_seconds {
+                *entry = Attempts::default();
+            }
+            entry.failures += 1;
+            entry.last_failure = now;
+            if entry.failures >= self.max_attempts {
+                let doublings = (entry.failures - self.max_attempts).min(30);
+                let lockout = self.lockout_seconds.saturating_mul(1i64 << doublings).min(self.max_lockout_seconds);
+                entry.locked_until = now + lockout;
+            }
+        }
+    }
+
+    /// Forget the failures for these keys after a successful login. Don't clear the IP key here:
+    /// somebody with one working account could otherwise reset their counter between guesses at
+    /// other people's passwords.
+    pub fn record_success(&self, keys: &[&str]) {
+        let mut attempts = self.attempts.lock().expect("login throttle lock poisoned");
+        for key in keys {
+            attempts.remove(*key);
+        }
+    }
+}
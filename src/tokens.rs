@@ -0,0 +1,38 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Make a new random token suitable for putting in a link: 32 bytes from the OS random number
+/// generator, hex encoded. Hand this to the user and store only `hash_token(&token)`.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// Hash a token for storage. The tokens are long and random, so a plain SHA-256 is enough here;
+/// there's no need for a slow salted hash like the one used for passwords. The point is only that
+/// somebody who can read the database can't use what they find in it.
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+/// The current time as a unix timestamp in seconds, which is how the token tables store times.
+pub fn now() -> i64 {
+    time::OffsetDateTime::now_utc().unix_timestamp()
+}
+
+/// Lowercase hex encoding, which is how tokens and token hashes are written everywhere.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The other direction from `to_hex`. Returns `None` if the string isn't valid hex.
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i+2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
@@ -0,0 +1,89 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use aes_gcm::aead::rand_core::RngCore;
+use totp_rs::{Algorithm, TOTP};
+use crate::error_template::AppError;
+use crate::tokens::{from_hex, to_hex};
+
+/// Seconds per TOTP code. 30 is what every authenticator app assumes.
+pub const STEP_SECONDS: u64 = 30;
+
+/// How many recovery codes a user gets when they turn on two-factor auth.
+pub const RECOVERY_CODE_COUNT: usize = 8;
+
+/// Encrypts TOTP secrets before they go into the `user_totp` table. Unlike passwords, these can't
+/// just be hashed, because the server needs the actual secret to compute the expected codes. The
+/// key comes from `totp_encryption_key` in the server config, so a copy of the database alone isn't
+/// enough to generate anybody's codes.
+pub struct TotpCipher {
+    cipher: Aes256Gcm,
+}
+
+impl TotpCipher {
+    /// Build the cipher from a hex encoded 32 byte key.
+    pub fn from_hex_key(key: &str) -> Result<Self, AppError> {
+        let key = from_hex(key)
+            .filter(|k| k.len() == 32)
+            .ok_or_else(|| AppError::InternalError("totp_encryption_key has to be 64 hex characters".into()))?;
+        Ok(TotpCipher{ cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)) })
+    }
+
+    /// Encrypt the secret with a fresh nonce. The result is the hex encoded nonce followed by the
+    /// ciphertext.
+    pub fn encrypt(&self, secret: &[u8]) -> Result<String, AppError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, secret)
+            .map_err(|e| AppError::InternalError(format!("Encrypt TOTP secret: {e}")))?;
+        Ok(format!("{}{}", to_hex(&nonce), to_hex(&ciphertext)))
+    }
+
+    /// Undo `encrypt`.
+    pub fn decrypt(&self, stored: &str) -> Result<Vec<u8>, AppError> {
+        let bytes = from_hex(stored)
+            .filter(|b| b.len() > 12)
+            .ok_or_else(|| AppError::InternalError("Corrupted TOTP secret".into()))?;
+        let (nonce, ciphertext) = bytes.split_at(12);
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| AppError::InternalError(format!("Decrypt TOTP secret: {e}")))
+    }
+}
+
+/// Make a new random 160 bit secret, which is the size RFC 4226 recommends.
+pub fn new_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Set up the RFC 6238 parameters for a secret: SHA-1, 6 digits and 30 second steps, because
+/// that's what authenticator apps actually support. The issuer and account name end up in the
+/// `otpauth://` URI, which is what the app shows to the user.
+pub fn totp_for(secret: Vec<u8>, issuer: &str, account_name: &str) -> Result<TOTP, AppError> {
+    TOTP::new(Algorithm::SHA1, 6, 0, STEP_SECONDS, secret, Some(issuer.to_string()), account_name.to_string())
+        .map_err(|e| AppError::InternalError(format!("TOTP setup: {e}")))
+}
+
+/// If `code` is right for the current time step or one step on either side (clocks drift, and
+/// people type slowly), return the number of the step it matched. The caller remembers the last
+/// step that was used so that the same code can't be used twice.
+pub fn matching_step(totp: &TOTP, code: &str, now: i64) -> Option<i64> {
+    let code = code.trim();
+    let step = now / STEP_SECONDS as i64;
+    [step - 1, step, step + 1]
+        .into_iter()
+        .find(|step| totp.check(code, (*step as u64) * STEP_SECONDS))
+}
+
+/// Make a fresh set of recovery codes, formatted like `1a2b3-c4d5e` so they're easy to copy down.
+pub fn new_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            OsRng.fill_bytes(&mut bytes);
+            let hex = to_hex(&bytes);
+            format!("{}-{}", &hex[..5], &hex[5..])
+        })
+        .collect()
+}
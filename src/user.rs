@@ -1,5 +1,6 @@
 
 use serde::{Serialize,Deserialize};
+use crate::secret::Secret;
 
 
 pub type DatabaseId = i64;
@@ -8,42 +9,65 @@ use cfg_if::cfg_if;
 cfg_if!{
     if #[cfg(feature="ssr")] {
         //use leptos::prelude::*;
+        use std::fmt;
         use axum_login::AuthUser;
-        use leptos::logging::log;
         use crate::error_template::AppError;
         use sqlx::prelude::FromRow;
-        use password_hash::PasswordHash;
 
         // This trait is used by axum_login to keep track of whether
         // a user is authenticated or not.
         impl AuthUser for User {
-            type Id=String;
-            /// id needs to return something that can uniquely identify the user.
-            /// I could use the id field, but I'm using the username so I can spot it
-            /// in data to see where it gets sent.
+            type Id=DatabaseId;
+            /// id needs to return something that can uniquely identify the user. This goes into
+            /// the session, and `get_user` looks the user up by it on every request. It used to
+            /// be the username, which was handy for spotting it in session data, but then renaming
+            /// a user broke all of their sessions. The database id never changes.
             fn id(&self) -> Self::Id {
-                self.username.clone()
+                self.id
             }
 
-            /// I *think* this is what is used to generate the actual session id that
-            /// makes it way into the cookie. One thing for sure: it needs to be the same
-            /// every time you call get_user, or it will invalidate your session. I had a
-            /// bug in which I was storing and loading the whole password hash, but only
-            /// checking the actual hash data, and it would log a user in and work as long
-            /// as they stayed on the same page. As soon as they navigated away from the page,
-            /// it would load the buggy id from the database and kill the session.
+            /// This has to be the same every time `get_user` loads the user, or the session gets
+            /// thrown out. That's also how logging somebody out everywhere works: change it, and
+            /// every session made with the old one stops working. It comes from the
+            /// `session_secret` column, so it only changes when we mean it to. I used to use the
+            /// password hash for this, but then rehashing a password with new parameters logged
+            /// everybody out.
             fn session_auth_hash(&self) -> &[u8] {
-                self.session_auth_hash.as_ref()
+                self.session_auth_hash.expose()
             }
         }
 
         /// SqlUser represents a row from the users table. It's only used by the
-        /// SqliteBackend in order to support `get_user` and `authenticate`
-        #[derive(Clone,PartialEq,Debug,FromRow)]
+        /// backends (see backend.rs) in order to support `get_user` and `authenticate`
+        #[derive(Clone,PartialEq,FromRow)]
         pub struct SqlUser {
             pub id: DatabaseId,
             pub username: String,
             pub pass_hash: String,
+            pub email: Option<String>,
+            /// Unix timestamp of when the email address was verified
+            pub verified_at: Option<i64>,
+            /// Random, and only changed to log the user out everywhere
+            pub session_secret: String,
+            /// Unix timestamp of when an admin disabled the account, if one did
+            pub disabled_at: Option<i64>,
+        }
+
+        /// Written by hand so the password hash and session secret never make it into a log. The
+        /// fields can't be `Secret`s themselves, because the checked queries in sqlite_store.rs
+        /// fill them in straight from the columns.
+        impl fmt::Debug for SqlUser {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("SqlUser")
+                    .field("id", &self.id)
+                    .field("username", &self.username)
+                    .field("pass_hash", &Secret::new(()))
+                    .field("email", &self.email)
+                    .field("verified_at", &self.verified_at)
+                    .field("session_secret", &Secret::new(()))
+                    .field("disabled_at", &self.disabled_at)
+                    .finish()
+            }
         }
 
         impl SqlUser {
@@ -51,21 +75,13 @@ cfg_if!{
             /// Convert the database row into a user object that the AuthSession
             /// can use.
             pub fn to_user(self) -> Result<User,AppError> {
-                // parse the hash data out of the string representation that we kept in the database
-                let PasswordHash{hash,..} = PasswordHash::parse(&self.pass_hash,password_hash::Encoding::B64)
-                    .map_err(|e| AppError::InternalError(format!("Decode password: {e}")))?;
-                // This is where we dig into the password hash data structure and pull out just
-                // the actual hash bytes that came out of argon2. These are used to identify the session
-                // so that this user always gets the same session data.
-                let hash:Vec<u8> = hash.map(|output| {
-                    output.as_bytes().to_owned()
-                }).ok_or_else(||AppError::InternalError("Badly formatted password hash".into()))?;
-                
-                log!("Got user {self:?}");
+                tracing::trace!(user_id = self.id, username = %self.username, "Loaded user");
                 Ok(User {
                     id: self.id,
                     username: self.username,
-                    session_auth_hash: hash,
+                    email: self.email,
+                    email_verified: self.verified_at.is_some(),
+                    session_auth_hash: Secret::new(self.session_secret.into_bytes()),
                 }
             )
             }
@@ -86,10 +102,18 @@ pub struct User {
     /// User-facing username, has a unique constraint in the db so we can use it to id users
     pub username: String,
 
-    /// This is computed with Argon2id, but it's only a *piece* of the entire thing returned
-    /// by the hash function. You should be able to use whatever you want here as long as you
-    /// can keep it stable between page loads. Personally, I don't like using the password hash
-    /// but that's how they do it in the example so it's probably fine.
-    pub session_auth_hash: Vec<u8>,
+    /// Optional, but needed for anything that involves sending mail to the user
+    pub email: Option<String>,
+
+    /// True once the user has followed the link in their verification email
+    pub email_verified: bool,
+
+    /// The user's `session_secret` from the database. It has to stay the same between page loads,
+    /// and changing it in the database logs the user out of every session they have.
+    ///
+    /// `User` gets sent to the browser, and this mustn't go with it, so it's never serialized.
+    /// axum-login doesn't need it to be: the session only keeps the id and the hash itself.
+    #[serde(skip)]
+    pub session_auth_hash: Secret<Vec<u8>>,
 }
 
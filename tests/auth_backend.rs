@@ -0,0 +1,624 @@
+#![cfg(feature="ssr")]
+
+mod common;
+
+use argon2::PasswordHash;
+use axum_login::{AuthnBackend, AuthzBackend};
+use leptos_axum_login::auth_backend::RememberedLogin;
+use leptos_axum_login::auth_events::AuthEventKind;
+use leptos_axum_login::credentials::Credentials;
+use leptos_axum_login::error_template::AppError;
+use leptos_axum_login::import::{import_users, ImportedUser};
+use leptos_axum_login::memory_store::MemoryBackend;
+use leptos_axum_login::password_policy::PolicyViolation;
+use leptos_axum_login::secret::Secret;
+use leptos_axum_login::state::ClientInfo;
+use leptos_axum_login::store::{AuthEventFilter, Store};
+use leptos_axum_login::strength::Strength;
+use leptos_axum_login::user::User;
+
+// The auth backend on top of the memory store. The store is supposed to behave like the SQL ones,
+// so these go through the backend's public methods the way the server functions do, rather than
+// poking at the store.
+
+const PASSWORD: &str = "correct horse battery staple";
+
+async fn add_user(backend: &MemoryBackend, username: &str) -> User {
+    backend.add_user(username.into(), PASSWORD.into(), None).await
+        .expect("a fine user")
+        .expect("and it comes back")
+}
+
+async fn add_alice(backend: &MemoryBackend) -> User {
+    backend.add_user("alice".into(), PASSWORD.into(), Some("alice@example.com".into())).await
+        .expect("alice is a fine user")
+        .expect("and she comes back")
+}
+
+#[tokio::test]
+async fn add_user_and_log_in_with_the_password() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    assert_eq!(alice.username, "alice");
+    assert!(!alice.email_verified);
+
+    let user = backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap();
+    assert_eq!(user.map(|u| u.id), Some(alice.id));
+    let user = backend.authenticate(Credentials::password("alice", "not the password")).await.unwrap();
+    assert!(user.is_none());
+    let user = backend.authenticate(Credentials::password("nobody", PASSWORD)).await.unwrap();
+    assert!(user.is_none());
+}
+
+#[tokio::test]
+async fn add_user_checks_the_new_user() {
+    let backend = common::backend();
+    add_alice(&backend).await;
+    let taken = backend.add_user("alice".into(), PASSWORD.into(), None).await;
+    assert!(matches!(taken, Err(AppError::InvalidData(_))), "{taken:?}");
+    let weak = backend.add_user("bob".into(), "short".into(), None).await;
+    assert!(matches!(weak, Err(AppError::WeakPassword(_))), "{weak:?}");
+    let short = backend.add_user("b".into(), PASSWORD.into(), None).await;
+    assert!(matches!(short, Err(AppError::InvalidData(_))), "{short:?}");
+}
+
+#[tokio::test]
+async fn the_password_policy_lists_everything_wrong() {
+    let mut config = common::config();
+    config.password_policy.require_digit = true;
+    config.password_policy.blocklist_file = Some("db/common-passwords.txt".into());
+    // The strength estimate would pile on to all of these, see the next test.
+    config.password_policy.min_score = 0;
+    let backend = common::backend_with(config);
+    let result = backend.add_user("bob".into(), "Password".into(), None).await;
+    let Err(AppError::WeakPassword(violations)) = result else {
+        panic!("{result:?}");
+    };
+    assert_eq!(violations, vec![PolicyViolation::MissingDigit, PolicyViolation::TooCommon]);
+    let result = backend.add_user("bob".into(), "bobsecret".into(), None).await;
+    let Err(AppError::WeakPassword(violations)) = result else {
+        panic!("{result:?}");
+    };
+    assert_eq!(violations, vec![PolicyViolation::MissingDigit, PolicyViolation::ContainsUsername]);
+    assert!(backend.add_user("bob".into(), "1 horse battery staple".into(), None).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn guessable_passwords_are_refused() {
+    let backend = common::backend();
+    // Long enough and not on any list, but a keyboard walk.
+    let result = backend.add_user("bob".into(), "qwertyuiop".into(), None).await;
+    let Err(AppError::WeakPassword(violations)) = result else {
+        panic!("{result:?}");
+    };
+    let [PolicyViolation::TooGuessable(strength)] = violations.as_slice() else {
+        panic!("{violations:?}");
+    };
+    assert!(strength.score < backend.config.password_policy.min_score);
+    assert!(Strength::estimate(PASSWORD, &["bob"]).score >= backend.config.password_policy.min_score);
+}
+
+#[tokio::test]
+async fn password_reset_tokens_work_once() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let old_token = backend.issue_password_reset_token(alice.id).await.unwrap();
+    let token = backend.issue_password_reset_token(alice.id).await.unwrap();
+    // Only the newest link works.
+    assert_eq!(backend.verify_password_reset_token(&old_token).await.unwrap(), None);
+    assert_eq!(backend.verify_password_reset_token(&token).await.unwrap(), Some(alice.id));
+    // Checking it doesn't use it up, but using it does.
+    assert_eq!(backend.consume_password_reset_token(&token).await.unwrap(), Some(alice.id));
+    assert_eq!(backend.consume_password_reset_token(&token).await.unwrap(), None);
+    assert_eq!(backend.verify_password_reset_token("not a token").await.unwrap(), None);
+
+    backend.set_password(alice.id, "staple battery horse correct".into()).await.unwrap();
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::password("alice", "staple battery horse correct")).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn expired_password_reset_tokens_dont_work() {
+    let mut config = common::config();
+    config.password_reset_token_ttl_seconds = 0;
+    let backend = common::backend_with(config);
+    let alice = add_alice(&backend).await;
+    let token = backend.issue_password_reset_token(alice.id).await.unwrap();
+    assert_eq!(backend.consume_password_reset_token(&token).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn email_verification_tokens_verify_the_email_once() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    assert!(!alice.email_verified);
+    let token = backend.issue_email_verification_token(alice.id).await.unwrap();
+    assert_eq!(backend.verify_email(&token).await.unwrap(), Some(alice.id));
+    assert_eq!(backend.verify_email(&token).await.unwrap(), None);
+    let alice = backend.find_user_by_name("alice").await.unwrap().unwrap();
+    assert!(alice.email_verified);
+}
+
+#[tokio::test]
+async fn unverified_emails_cant_log_in_when_verification_is_required() {
+    let mut config = common::config();
+    config.require_verified_email = true;
+    let backend = common::backend_with(config);
+    assert!(matches!(backend.add_user("bob".into(), PASSWORD.into(), None).await,
+        Err(AppError::InvalidData(_))));
+    let alice = add_alice(&backend).await;
+    assert!(matches!(backend.authenticate(Credentials::password("alice", PASSWORD)).await,
+        Err(AppError::EmailNotVerified)));
+    // A wrong password is still just a wrong password.
+    assert!(backend.authenticate(Credentials::password("alice", "wrong")).await.unwrap().is_none());
+
+    let token = backend.issue_email_verification_token(alice.id).await.unwrap();
+    backend.verify_email(&token).await.unwrap();
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn totp_takes_one_code_per_step_and_recovery_codes_once() {
+    let mut config = common::config();
+    config.totp_encryption_key = Some(Secret::new("00".repeat(32)));
+    let backend = common::backend_with(config);
+    let alice = add_alice(&backend).await;
+    let totp = backend.begin_totp_enrollment(&alice).await.unwrap();
+    assert!(!backend.totp_enabled(alice.id).await.unwrap());
+    assert_eq!(backend.confirm_totp_enrollment(alice.id, "000000x").await.unwrap(), None);
+
+    let code = totp.generate_current().unwrap();
+    let recovery_codes = backend.confirm_totp_enrollment(alice.id, &code).await.unwrap()
+        .expect("the current code confirms the enrollment");
+    assert_eq!(recovery_codes.len(), 8);
+    assert!(backend.totp_enabled(alice.id).await.unwrap());
+    // The code that confirmed the enrollment has been used.
+    assert!(!backend.verify_second_factor(alice.id, &code).await.unwrap());
+    assert!(!backend.verify_second_factor(alice.id, "not a code").await.unwrap());
+
+    assert!(backend.verify_second_factor(alice.id, &recovery_codes[0]).await.unwrap());
+    assert!(!backend.verify_second_factor(alice.id, &recovery_codes[0]).await.unwrap());
+    assert!(backend.verify_second_factor(alice.id, &recovery_codes[1].to_uppercase()).await.unwrap());
+
+    backend.disable_totp(alice.id).await.unwrap();
+    assert!(!backend.totp_enabled(alice.id).await.unwrap());
+    assert!(!backend.verify_second_factor(alice.id, &recovery_codes[2]).await.unwrap());
+}
+
+#[tokio::test]
+async fn totp_needs_a_key_in_the_config() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    assert!(matches!(backend.begin_totp_enrollment(&alice).await, Err(AppError::InvalidData(_))));
+}
+
+#[tokio::test]
+async fn magic_links_log_in_once_and_verify_the_email() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let token = backend.issue_magic_link_token(alice.id).await.unwrap();
+    let user = backend.authenticate(Credentials::MagicLink(token.clone())).await.unwrap()
+        .expect("the link logs alice in");
+    assert_eq!(user.id, alice.id);
+    assert!(user.email_verified);
+    assert!(backend.authenticate(Credentials::MagicLink(token)).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::MagicLink("not a token".into())).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn too_many_wrong_passwords_lock_the_username_out() {
+    let mut config = common::config();
+    config.login_max_attempts = 2;
+    let backend = common::backend_with(config);
+    add_alice(&backend).await;
+    for _ in 0..2 {
+        assert!(backend.authenticate(Credentials::password("alice", "wrong")).await.unwrap().is_none());
+    }
+    // Now even the right password doesn't get looked at, for any spelling of the name.
+    assert!(matches!(backend.authenticate(Credentials::password("alice", PASSWORD)).await,
+        Err(AppError::TooManyAttempts(seconds)) if seconds > 0));
+    assert!(matches!(backend.authenticate(Credentials::password("ALICE", PASSWORD)).await,
+        Err(AppError::TooManyAttempts(_))));
+
+    // Usernames that don't exist get locked out the same way.
+    for _ in 0..2 {
+        assert!(backend.authenticate(Credentials::password("nobody", "wrong")).await.unwrap().is_none());
+    }
+    assert!(matches!(backend.authenticate(Credentials::password("nobody", "wrong")).await,
+        Err(AppError::TooManyAttempts(_))));
+}
+
+#[tokio::test]
+async fn a_good_login_resets_the_count() {
+    let mut config = common::config();
+    config.login_max_attempts = 2;
+    let backend = common::backend_with(config);
+    add_alice(&backend).await;
+    assert!(backend.authenticate(Credentials::password("alice", "wrong")).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_some());
+    assert!(backend.authenticate(Credentials::password("alice", "wrong")).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_some());
+}
+
+#[test]
+fn credentials_keep_their_secrets_out_of_debug_output() {
+    let debug = format!("{:?}", Credentials::password("alice", PASSWORD));
+    assert!(debug.contains("alice"));
+    assert!(!debug.contains(PASSWORD));
+    let debug = format!("{:?}", Credentials::MagicLink("a secret token".into()));
+    assert!(!debug.contains("a secret token"));
+}
+
+#[tokio::test]
+async fn secrets_stay_out_of_debug_output() {
+    let mut config = common::config();
+    config.totp_encryption_key = Some(Secret::new("00".repeat(32)));
+    let backend = common::backend_with(config);
+    let alice = add_alice(&backend).await;
+    let row = backend.store.find_by_username("alice").await.unwrap().unwrap();
+    for debug in [format!("{row:?}"), format!("{alice:?}"), format!("{:?}", backend.config)] {
+        assert!(debug.contains("<redacted>"), "{debug}");
+        assert!(!debug.contains(&row.pass_hash), "{debug}");
+        assert!(!debug.contains(&row.session_secret), "{debug}");
+        assert!(!debug.contains(&"00".repeat(32)), "{debug}");
+    }
+    // Getting at the value is still easy when it's meant to be.
+    assert_eq!(alice.session_auth_hash.expose(), row.session_secret.as_bytes());
+}
+
+#[tokio::test]
+async fn rotating_the_session_secret_changes_the_session_auth_hash() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    // It stays put otherwise, or every page load would log alice out.
+    let again = backend.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_eq!(again.session_auth_hash, alice.session_auth_hash);
+    backend.rotate_session_secret(alice.id).await.unwrap();
+    let after = backend.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_ne!(after.session_auth_hash, alice.session_auth_hash);
+}
+
+#[tokio::test]
+async fn the_session_secret_stays_on_the_server() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let json = serde_json::to_value(&alice).unwrap();
+    assert_eq!(json["username"], "alice");
+    assert!(json.get("session_auth_hash").is_none(), "{json}");
+}
+
+#[tokio::test]
+async fn renaming_keeps_the_user() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    add_user(&backend, "bob").await;
+    backend.change_username(alice.id, "alicia".into()).await.unwrap();
+    let renamed = backend.get_user(&alice.id).await.unwrap().expect("the id still finds her");
+    assert_eq!(renamed.username, "alicia");
+    assert_eq!(renamed.session_auth_hash, alice.session_auth_hash);
+    assert!(backend.authenticate(Credentials::password("alicia", PASSWORD)).await.unwrap().is_some());
+    assert!(backend.find_user_by_name("alice").await.unwrap().is_none());
+
+    let taken = backend.change_username(alice.id, "bob".into()).await;
+    assert!(matches!(taken, Err(AppError::InvalidData(_))), "{taken:?}");
+}
+
+#[tokio::test]
+async fn old_sessions_are_checked_against_the_password_hash() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    // What a session from before user ids kept: the hash bytes out of the password hash.
+    let pass_hash = backend.store.find_by_username("alice").await.unwrap().unwrap().pass_hash;
+    let old_auth_hash = PasswordHash::new(&pass_hash).unwrap().hash.unwrap().as_bytes().to_vec();
+    let user = backend.find_legacy_session_user("alice", &old_auth_hash).await.unwrap();
+    assert_eq!(user.map(|u| u.id), Some(alice.id));
+    assert!(backend.find_legacy_session_user("alice", alice.session_auth_hash.expose()).await.unwrap().is_none());
+    assert!(backend.find_legacy_session_user("nobody", &old_auth_hash).await.unwrap().is_none());
+
+    // A new password means the old sessions are done.
+    backend.set_password(alice.id, "staple battery horse correct".into()).await.unwrap();
+    assert!(backend.find_legacy_session_user("alice", &old_auth_hash).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn weaker_password_hashes_are_redone_at_login() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let old_hash = backend.store.find_by_username("alice").await.unwrap().unwrap().pass_hash;
+    assert!(!backend.passwords.needs_rehash(&old_hash));
+
+    // The same users, with the costs turned up.
+    let mut config = common::config();
+    config.argon2_iterations = 2;
+    let stronger = MemoryBackend::new(backend.store.clone(), config);
+    assert!(stronger.passwords.needs_rehash(&old_hash));
+    // A wrong password doesn't get anything redone.
+    assert!(stronger.authenticate(Credentials::password("alice", "wrong")).await.unwrap().is_none());
+    assert_eq!(stronger.store.find_by_username("alice").await.unwrap().unwrap().pass_hash, old_hash);
+
+    let user = stronger.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap()
+        .expect("the old hash still checks out");
+    // Same password, so nobody gets logged out.
+    assert_eq!(user.session_auth_hash, alice.session_auth_hash);
+    let new_hash = stronger.store.find_by_username("alice").await.unwrap().unwrap().pass_hash;
+    assert_ne!(new_hash, old_hash);
+    assert!(!stronger.passwords.needs_rehash(&new_hash));
+    assert!(stronger.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn imported_users_log_in_with_their_old_hashes() {
+    let backend = common::backend();
+    add_alice(&backend).await;
+    let imported = |username: &str, pass_hash: String| ImportedUser {
+        username: username.into(),
+        pass_hash: Secret::new(pass_hash),
+        email: None,
+        email_verified: None,
+    };
+    let users = vec![
+        imported("carol", bcrypt::hash(PASSWORD, 4).unwrap()),
+        imported("dave", "$md5$not-something-we-check".into()),
+        imported("alice", bcrypt::hash(PASSWORD, 4).unwrap()),
+    ];
+    let report = import_users(&backend.store, users).await.unwrap();
+    assert_eq!(report.imported, 1);
+    let skipped = report.skipped.iter().map(|(username, _)| username.as_str()).collect::<Vec<_>>();
+    assert_eq!(skipped, vec!["dave", "alice"]);
+
+    assert!(backend.authenticate(Credentials::password("carol", "wrong")).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::password("carol", PASSWORD)).await.unwrap().is_some());
+    // The bcrypt hash got swapped for Argon2id, and still takes the same password.
+    let pass_hash = backend.store.find_by_username("carol").await.unwrap().unwrap().pass_hash;
+    assert!(pass_hash.starts_with("$argon2id$"), "{pass_hash}");
+    assert!(backend.authenticate(Credentials::password("carol", PASSWORD)).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn roles_carry_permissions() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    assert!(backend.get_user_roles(alice.id).await.unwrap().is_empty());
+    assert!(!backend.has_perm(&alice, "admin.access".into()).await.unwrap());
+
+    backend.grant_role(alice.id, "admin").await.unwrap();
+    // Granting it twice is fine.
+    backend.grant_role(alice.id, "admin").await.unwrap();
+    assert_eq!(backend.get_user_roles(alice.id).await.unwrap(), vec!["admin".to_string()]);
+    assert!(backend.has_perm(&alice, "admin.access".into()).await.unwrap());
+    assert!(backend.has_perm(&alice, "admin.users".into()).await.unwrap());
+    assert!(!backend.has_perm(&alice, "something.else".into()).await.unwrap());
+    assert!(backend.grant_role(alice.id, "no such role").await.is_err());
+
+    backend.revoke_role(alice.id, "admin").await.unwrap();
+    assert!(!backend.has_perm(&alice, "admin.access".into()).await.unwrap());
+}
+
+#[tokio::test]
+async fn changing_the_password_needs_the_current_one() {
+    let mut config = common::config();
+    config.login_max_attempts = 2;
+    let backend = common::backend_with(config);
+    let alice = add_alice(&backend).await;
+    let wrong = backend.change_password(alice.id, "not the password", "staple battery horse correct".into()).await;
+    assert!(matches!(wrong, Err(AppError::InvalidData(_))), "{wrong:?}");
+    // The new password still has to follow the policy.
+    let weak = backend.change_password(alice.id, PASSWORD, "short".into()).await;
+    assert!(matches!(weak, Err(AppError::WeakPassword(_))), "{weak:?}");
+
+    backend.change_password(alice.id, PASSWORD, "staple battery horse correct".into()).await.unwrap();
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::password("alice", "staple battery horse correct")).await.unwrap().is_some());
+    // Every session the user had is over.
+    let changed = backend.get_user(&alice.id).await.unwrap().unwrap();
+    assert_ne!(changed.session_auth_hash, alice.session_auth_hash);
+
+    // Wrong guesses here count toward the login lockout.
+    for _ in 0..2 {
+        let _ = backend.change_password(alice.id, "wrong", "staple battery horse correct".into()).await;
+    }
+    let locked = backend.change_password(alice.id, "staple battery horse correct", "another horse battery staple".into()).await;
+    assert!(matches!(locked, Err(AppError::TooManyAttempts(_))), "{locked:?}");
+}
+
+#[tokio::test]
+async fn deleting_the_account_needs_the_password() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let wrong = backend.delete_account(alice.id, "not the password").await;
+    assert!(matches!(wrong, Err(AppError::InvalidData(_))), "{wrong:?}");
+    assert!(backend.get_user(&alice.id).await.unwrap().is_some());
+
+    backend.delete_account(alice.id, PASSWORD).await.unwrap();
+    assert!(backend.get_user(&alice.id).await.unwrap().is_none());
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_none());
+    // The name is free for somebody else now.
+    let again = add_alice(&backend).await;
+    assert_ne!(again.id, alice.id);
+}
+
+#[tokio::test]
+async fn sessions_are_listed_and_revoked() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    backend.touch_session(alice.id, "session-a", Some("Firefox"), "127.0.0.1").await.unwrap();
+    backend.touch_session(alice.id, "session-b", Some("Chrome"), "127.0.0.2").await.unwrap();
+    backend.touch_session(alice.id, "session-c", None, "127.0.0.3").await.unwrap();
+    let sessions = backend.list_sessions(alice.id).await.unwrap();
+    assert_eq!(sessions.len(), 3);
+
+    let firefox = sessions.iter().find(|s| s.session_id == "session-a").unwrap();
+    assert_eq!(firefox.user_agent.as_deref(), Some("Firefox"));
+    let revoked = backend.revoke_session(alice.id, firefox.id).await.unwrap()
+        .expect("alice has that session");
+    assert_eq!(revoked.session_id, "session-a");
+    // A logout takes its session off the list too.
+    backend.forget_session("session-c").await.unwrap();
+    let left = backend.list_sessions(alice.id).await.unwrap();
+    assert_eq!(left.iter().map(|s| s.session_id.as_str()).collect::<Vec<_>>(), vec!["session-b"]);
+
+    // Nobody else gets to revoke it.
+    let bob = add_user(&backend, "bob").await;
+    assert!(backend.revoke_session(bob.id, left[0].id).await.unwrap().is_none());
+
+    // Signing out everywhere forgets every session.
+    backend.rotate_session_secret(alice.id).await.unwrap();
+    assert!(backend.list_sessions(alice.id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn disabled_users_cant_log_in() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    backend.touch_session(alice.id, "session-a", None, "127.0.0.1").await.unwrap();
+    backend.set_disabled(alice.id, true).await.unwrap();
+    assert!(backend.is_disabled(alice.id).await.unwrap());
+    let result = backend.authenticate(Credentials::password("alice", PASSWORD)).await;
+    assert!(matches!(result, Err(AppError::AccountDisabled)), "{result:?}");
+    // Only somebody with the right password finds out.
+    assert!(backend.authenticate(Credentials::password("alice", "wrong")).await.unwrap().is_none());
+    let token = backend.issue_magic_link_token(alice.id).await.unwrap();
+    let result = backend.authenticate(Credentials::MagicLink(token)).await;
+    assert!(matches!(result, Err(AppError::AccountDisabled)), "{result:?}");
+    // The sessions she had are over.
+    assert!(backend.get_user(&alice.id).await.unwrap().is_none());
+    assert!(backend.list_sessions(alice.id).await.unwrap().is_empty());
+
+    backend.set_disabled(alice.id, false).await.unwrap();
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn force_password_reset_replaces_the_password() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let token = backend.force_password_reset(alice.id).await.unwrap();
+    assert!(backend.authenticate(Credentials::password("alice", PASSWORD)).await.unwrap().is_none());
+    assert_eq!(backend.verify_password_reset_token(&token).await.unwrap(), Some(alice.id));
+    let changed = backend.get_user(&alice.id).await.unwrap().unwrap();
+    assert_ne!(changed.session_auth_hash, alice.session_auth_hash);
+}
+
+#[tokio::test]
+async fn users_are_searched_a_page_at_a_time() {
+    let backend = common::backend();
+    add_alice(&backend).await;
+    for username in ["bob", "bobby", "carol"] {
+        add_user(&backend, username).await;
+    }
+    let (page, total) = backend.search_users("BOB", 0, 1).await.unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(page.iter().map(|u| u.username.as_str()).collect::<Vec<_>>(), vec!["bob"]);
+    let (page, _) = backend.search_users("bob", 1, 1).await.unwrap();
+    assert_eq!(page.iter().map(|u| u.username.as_str()).collect::<Vec<_>>(), vec!["bobby"]);
+    // Email addresses count too, and an empty search finds everybody.
+    let (page, _) = backend.search_users("example.com", 0, 10).await.unwrap();
+    assert_eq!(page.iter().map(|u| u.username.as_str()).collect::<Vec<_>>(), vec!["alice"]);
+    assert_eq!(backend.search_users("", 0, 10).await.unwrap().1, 4);
+    // Way past the end is just empty, even for offsets that don't fit anywhere.
+    assert!(backend.search_users("", i64::MAX, 10).await.unwrap().0.is_empty());
+}
+
+#[tokio::test]
+async fn the_audit_log_is_newest_first() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let bob = add_user(&backend, "bob").await;
+    backend.audit(&alice, "disable_user", Some((bob.id, &bob.username)), None).await.unwrap();
+    backend.audit(&alice, "delete_user", Some((bob.id, &bob.username)), Some("1 sessions ended".into())).await.unwrap();
+    backend.delete_user(bob.id).await.unwrap();
+    let (entries, total) = backend.list_audit(0, 10).await.unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(entries.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), vec!["delete_user", "disable_user"]);
+    // The names stay after the user is gone.
+    assert_eq!(entries[0].actor_name, "alice");
+    assert_eq!(entries[0].target_name.as_deref(), Some("bob"));
+}
+
+#[tokio::test]
+async fn auth_events_are_recorded_and_filtered() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let client = ClientInfo{ ip: "127.0.0.1".parse().unwrap(), user_agent: Some("Firefox".into()) };
+    let elsewhere = ClientInfo{ ip: "10.0.0.1".parse().unwrap(), user_agent: None };
+    backend.record_event(AuthEventKind::Registered, Some(alice.id), Some("alice"), Some(&client), None).await;
+    backend.record_event(AuthEventKind::LoginFailed, Some(alice.id), Some("alice"), Some(&elsewhere), Some("password: wrong")).await;
+    backend.record_event(AuthEventKind::LoginSucceeded, Some(alice.id), Some("alice"), Some(&client), Some("password")).await;
+    backend.record_event(AuthEventKind::LoginFailed, None, Some("nobody"), Some(&elsewhere), None).await;
+
+    let alices = AuthEventFilter{ user_id: Some(alice.id), ..Default::default() };
+    let (events, total) = backend.list_auth_events(&alices, 0, 10).await.unwrap();
+    assert_eq!(total, 3);
+    let kinds: Vec<_> = events.iter().map(|e| AuthEventKind::parse(&e.kind)).collect();
+    assert_eq!(kinds, vec![Some(AuthEventKind::LoginSucceeded), Some(AuthEventKind::LoginFailed), Some(AuthEventKind::Registered)]);
+    assert_eq!(events[0].user_agent.as_deref(), Some("Firefox"));
+
+    let failures = AuthEventFilter{ kind: Some(AuthEventKind::LoginFailed.as_str().into()), ..Default::default() };
+    assert_eq!(backend.list_auth_events(&failures, 0, 10).await.unwrap().1, 2);
+    let from_there = AuthEventFilter{ ip: Some("10.0.0.1".into()), username: Some("NOBO".into()), ..Default::default() };
+    let (events, _) = backend.list_auth_events(&from_there, 0, 10).await.unwrap();
+    assert_eq!(events.iter().map(|e| e.username.as_deref()).collect::<Vec<_>>(), vec![Some("nobody")]);
+    // A page at a time.
+    let (page, total) = backend.list_auth_events(&AuthEventFilter::default(), 3, 2).await.unwrap();
+    assert_eq!((page.len(), total), (1, 4));
+
+    // Nothing is old enough to go yet.
+    assert_eq!(backend.prune_auth_events().await.unwrap(), 0);
+}
+
+/// Use a remember-me cookie and expect it to log alice in. Returns the replacement cookie.
+async fn remembered(backend: &MemoryBackend, cookie: &str, alice: &User) -> Option<String> {
+    match backend.use_remember_token(cookie).await.unwrap() {
+        RememberedLogin::Valid{ user, cookie } => {
+            assert_eq!(user.id, alice.id);
+            cookie
+        },
+        other => panic!("expected a login, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn remember_me_cookies_rotate() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let first = backend.issue_remember_token(alice.id).await.unwrap();
+    let second = remembered(&backend, &first, &alice).await.expect("the cookie gets replaced");
+    assert_ne!(first, second);
+    // A request that left before the new cookie arrived still gets in, without a new cookie.
+    assert_eq!(remembered(&backend, &first, &alice).await, None);
+    let third = remembered(&backend, &second, &alice).await.expect("the cookie gets replaced");
+    assert_ne!(second, third);
+
+    assert!(matches!(backend.use_remember_token("garbage").await.unwrap(), RememberedLogin::Invalid));
+    backend.forget_remember_token(&third).await.unwrap();
+    assert!(matches!(backend.use_remember_token(&third).await.unwrap(), RememberedLogin::Invalid));
+}
+
+#[tokio::test]
+async fn reused_remember_me_cookies_log_everybody_out() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    backend.touch_session(alice.id, "session-a", None, "127.0.0.1").await.unwrap();
+    let stolen = backend.issue_remember_token(alice.id).await.unwrap();
+    let other = backend.issue_remember_token(alice.id).await.unwrap();
+    let second = remembered(&backend, &stolen, &alice).await.unwrap();
+    let third = remembered(&backend, &second, &alice).await.unwrap();
+
+    // Two rotations back is no longer a race with the real browser, it's a copy.
+    let result = backend.use_remember_token(&stolen).await.unwrap();
+    assert!(matches!(result, RememberedLogin::Reused{ user_id } if user_id == alice.id), "{result:?}");
+    assert!(matches!(backend.use_remember_token(&third).await.unwrap(), RememberedLogin::Invalid));
+    assert!(matches!(backend.use_remember_token(&other).await.unwrap(), RememberedLogin::Invalid));
+    assert!(backend.list_sessions(alice.id).await.unwrap().is_empty());
+    let after = backend.find_user_by_id(alice.id).await.unwrap().unwrap();
+    assert_ne!(after.session_auth_hash, alice.session_auth_hash);
+}
+
+#[tokio::test]
+async fn remember_me_cookies_stop_working_for_disabled_users() {
+    let backend = common::backend();
+    let alice = add_alice(&backend).await;
+    let cookie = backend.issue_remember_token(alice.id).await.unwrap();
+    backend.set_disabled(alice.id, true).await.unwrap();
+    assert!(matches!(backend.use_remember_token(&cookie).await.unwrap(), RememberedLogin::Invalid));
+}
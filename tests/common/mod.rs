@@ -0,0 +1,27 @@
+// Not every test file uses every helper.
+#![allow(dead_code)]
+
+use leptos_axum_login::config::ServerConfig;
+use leptos_axum_login::memory_store::{MemoryBackend, MemoryUserStore};
+
+// What the tests share: a config and a backend on top of the memory store, so none of them need
+// a database.
+
+/// The config with every default, except that password hashing is made cheap. The default argon2
+/// costs are there to slow down attackers, and they'd slow down the tests just the same.
+pub fn config() -> ServerConfig {
+    let mut config: ServerConfig = toml::from_str("").expect("every config setting has a default");
+    config.argon2_memory_kib = 1024;
+    config.argon2_iterations = 1;
+    config
+}
+
+/// A backend with `config()` and an empty store.
+pub fn backend() -> MemoryBackend {
+    backend_with(config())
+}
+
+/// A backend with an empty store and the given config.
+pub fn backend_with(config: ServerConfig) -> MemoryBackend {
+    MemoryBackend::new(MemoryUserStore::new(), config)
+}
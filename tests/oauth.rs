@@ -0,0 +1,189 @@
+#![cfg(feature="ssr")]
+
+mod common;
+
+use std::collections::HashMap;
+use leptos_axum_login::config::{MockOidcConfig, OAuthProviderConfig};
+use leptos_axum_login::error_template::AppError;
+use leptos_axum_login::memory_store::MemoryBackend;
+use leptos_axum_login::mock_oidc::MockOidc;
+use leptos_axum_login::oauth::{oauth_user, CallbackParams, OAuthClients, PendingOAuth, ProviderIdentity};
+use leptos_axum_login::secret::Secret;
+use openidconnect::{reqwest, url::Url};
+use tokio::net::TcpListener;
+
+// OpenID Connect logins against the mock provider from mock_oidc.rs, which gets a port of its
+// own for each test. The browser's part (following the redirect and filling in the mock's login
+// page) is done with a plain HTTP client; everything else goes through the same `OAuthClients`
+// and `oauth_user` that `oauth_callback` uses.
+
+const CLIENT_ID: &str = "test-client";
+const CLIENT_SECRET: &str = "test-client-secret";
+
+/// Start the mock provider, and return the `[[oauth_providers]]` entry that points at it.
+async fn mock_provider() -> OAuthProviderConfig {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}/mock-oidc", listener.local_addr().unwrap());
+    let mock = MockOidc::new(issuer.clone(), &MockOidcConfig {
+        client_id: CLIENT_ID.into(),
+        client_secret: Secret::new(CLIENT_SECRET.into()),
+    });
+    let app = axum::Router::new().nest("/mock-oidc", mock.router::<()>());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    OAuthProviderConfig {
+        name: "mock".into(),
+        display_name: "Mock".into(),
+        issuer,
+        client_id: CLIENT_ID.into(),
+        client_secret: Some(Secret::new(CLIENT_SECRET.into())),
+        scopes: vec!["openid".into(), "email".into(), "profile".into()],
+    }
+}
+
+fn query(url: &Url) -> HashMap<String, String> {
+    url.query_pairs().into_owned().collect()
+}
+
+/// Do what the browser does between `oauth_start` and the callback: take the login page's form,
+/// sign in as `subject`, and return the query string the provider sends back. `nonce` stands in
+/// for the one the login was started with, for pretending to be a provider that got it wrong.
+async fn sign_in(url: &Url, subject: &str, email: &str, nonce: Option<&str>) -> CallbackParams {
+    let started = query(url);
+    let form = [
+        ("redirect_uri", started["redirect_uri"].as_str()),
+        ("state", started["state"].as_str()),
+        ("nonce", nonce.unwrap_or(started["nonce"].as_str())),
+        ("code_challenge", started["code_challenge"].as_str()),
+        ("subject", subject),
+        ("email", email),
+    ];
+    let mut action = url.clone();
+    action.set_query(None);
+    let http = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = http.post(action).form(&form).send().await.unwrap();
+    let location = response.headers()[reqwest::header::LOCATION].to_str().unwrap();
+    let back = query(&Url::parse(location).unwrap());
+    CallbackParams {
+        code: back.get("code").cloned(),
+        state: back.get("state").cloned(),
+        error: back.get("error").cloned(),
+        error_description: back.get("error_description").cloned(),
+    }
+}
+
+/// Start a login, the way `oauth_start` does.
+async fn start(clients: &OAuthClients, backend: &MemoryBackend, provider: &OAuthProviderConfig) -> (Url, PendingOAuth) {
+    clients.authorize_url(provider, &backend.config.public_url).await.unwrap()
+}
+
+/// A whole login at the provider as `subject`, checked the way `oauth_callback` checks it.
+async fn log_in(clients: &OAuthClients, backend: &MemoryBackend, provider: &OAuthProviderConfig, subject: &str, email: &str)
+-> ProviderIdentity {
+    let (url, pending) = start(clients, backend, provider).await;
+    let params = sign_in(&url, subject, email, None).await;
+    clients.verify(provider, &backend.config.public_url, pending, params).await.unwrap()
+}
+
+#[tokio::test]
+async fn the_first_login_makes_a_user() {
+    // A policy that a random hex password can't pass, which mustn't matter for these users.
+    let mut config = common::config();
+    config.password_policy.require_uppercase = true;
+    config.password_policy.require_symbol = true;
+    let backend = common::backend_with(config);
+    let provider = mock_provider().await;
+    let clients = OAuthClients::new();
+
+    let identity = log_in(&clients, &backend, &provider, "newbie", "newbie@example.com").await;
+    assert_eq!(identity.subject, "newbie");
+    assert_eq!(identity.email.as_deref(), Some("newbie@example.com"));
+    let (user, created) = oauth_user(&backend, &provider, identity).await.unwrap();
+    assert!(created);
+    assert_eq!(user.username, "newbie");
+    assert_eq!(user.email.as_deref(), Some("newbie@example.com"));
+    // The provider checked the address, so there's no need for a verification link.
+    assert!(user.email_verified);
+
+    // The next time, it's the same user.
+    let identity = log_in(&clients, &backend, &provider, "newbie", "newbie@example.com").await;
+    let (again, created) = oauth_user(&backend, &provider, identity).await.unwrap();
+    assert!(!created);
+    assert_eq!(again.id, user.id);
+}
+
+#[tokio::test]
+async fn a_taken_username_gets_a_number() {
+    let backend = common::backend();
+    let provider = mock_provider().await;
+    let clients = OAuthClients::new();
+    backend.add_user("sam".into(), "correct horse battery staple".into(), None).await.unwrap();
+
+    let identity = log_in(&clients, &backend, &provider, "sam", "").await;
+    assert_eq!(identity.email, None);
+    let (user, created) = oauth_user(&backend, &provider, identity).await.unwrap();
+    assert!(created);
+    assert_eq!(user.username, "sam2");
+}
+
+#[tokio::test]
+async fn a_state_mismatch_is_refused() {
+    let backend = common::backend();
+    let provider = mock_provider().await;
+    let clients = OAuthClients::new();
+    let (url, pending) = start(&clients, &backend, &provider).await;
+    let mut params = sign_in(&url, "mallory", "mallory@example.com", None).await;
+    params.state = Some("forged".into());
+    let result = clients.verify(&provider, &backend.config.public_url, pending, params).await;
+    assert!(matches!(&result, Err(AppError::InvalidData(message)) if message.contains("didn't match")), "{result:?}");
+}
+
+#[tokio::test]
+async fn an_answer_to_another_login_is_refused() {
+    let backend = common::backend();
+    let provider = mock_provider().await;
+    let clients = OAuthClients::new();
+    let (url, _) = start(&clients, &backend, &provider).await;
+    let (_, other) = start(&clients, &backend, &provider).await;
+    let params = sign_in(&url, "mallory", "mallory@example.com", None).await;
+    let result = clients.verify(&provider, &backend.config.public_url, other, params).await;
+    assert!(matches!(result, Err(AppError::InvalidData(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn the_nonce_is_checked() {
+    let backend = common::backend();
+    let provider = mock_provider().await;
+    let clients = OAuthClients::new();
+    let (url, pending) = start(&clients, &backend, &provider).await;
+    // Everything else is right, but the ID token carries somebody else's nonce.
+    let params = sign_in(&url, "mallory", "mallory@example.com", Some("replayed-nonce")).await;
+    let result = clients.verify(&provider, &backend.config.public_url, pending, params).await;
+    assert!(matches!(&result, Err(AppError::InvalidData(message)) if message.starts_with("Bad ID token")), "{result:?}");
+}
+
+#[tokio::test]
+async fn an_existing_verified_email_is_linked_only_by_its_owner() {
+    let backend = common::backend();
+    let provider = mock_provider().await;
+    let clients = OAuthClients::new();
+    let alice = backend.add_user("alice".into(), "correct horse battery staple".into(), Some("alice@example.com".into())).await
+        .unwrap().unwrap();
+    backend.mark_email_verified(alice.id).await.unwrap();
+
+    // Somebody at the provider with alice's address doesn't get her account, or a new one.
+    let identity = log_in(&clients, &backend, &provider, "alice-at-mock", "alice@example.com").await;
+    let result = oauth_user(&backend, &provider, identity.clone()).await;
+    assert!(matches!(result, Err(AppError::InvalidData(_))), "{result:?}");
+    assert!(backend.find_user_by_name("alice-at-mock").await.unwrap().is_none());
+
+    // Once alice connects it herself (that's the callback with her logged in), it logs her in.
+    backend.link_oauth_account(alice.id, &provider.name, &identity.subject, identity.email.clone()).await.unwrap();
+    let identity = log_in(&clients, &backend, &provider, "alice-at-mock", "alice@example.com").await;
+    let (user, created) = oauth_user(&backend, &provider, identity).await.unwrap();
+    assert!(!created);
+    assert_eq!(user.id, alice.id);
+    assert!(user.email_verified);
+}
@@ -0,0 +1,117 @@
+#![cfg(feature="ssr")]
+
+mod common;
+
+use axum_login::AuthnBackend;
+use leptos_axum_login::credentials::Credentials;
+use leptos_axum_login::memory_store::MemoryBackend;
+use leptos_axum_login::user::User;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_authenticator_rs::WebauthnAuthenticator;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse, Url, Uuid};
+use webauthn_rs_proto::AllowCredentials;
+
+// Passkeys from start to finish, with webauthn-authenticator-rs standing in for the browser and
+// the security key: register one the way `finish_passkey_registration` does, then log in with it
+// through `Credentials::Passkey` like `finish_passkey_login`.
+
+type Authenticator = WebauthnAuthenticator<SoftPasskey>;
+
+/// A fresh authenticator. It claims to have verified the user (there's nobody to ask), which
+/// passkeys require.
+fn authenticator() -> Authenticator {
+    WebauthnAuthenticator::new(SoftPasskey::new(true))
+}
+
+async fn add_user(backend: &MemoryBackend, username: &str) -> User {
+    backend.add_user(username.into(), "correct horse battery staple".into(), None).await
+        .expect("a fine user")
+        .expect("and it comes back")
+}
+
+fn origin(backend: &MemoryBackend) -> Url {
+    Url::parse(&backend.config.public_url).unwrap()
+}
+
+/// Register a new passkey for the user, and return what the authenticator sent back.
+async fn register(backend: &MemoryBackend, authenticator: &mut Authenticator, user: &User) -> RegisterPublicKeyCredential {
+    let handle = backend.passkey_user_handle(user.id).await.unwrap();
+    let existing = backend.list_passkeys(user.id).await.unwrap();
+    let (challenge, state) = backend.passkeys.start_registration(handle, &user.username, &existing).unwrap();
+    let response = authenticator.do_registration(origin(backend), challenge)
+        .expect("the soft passkey registers");
+    let passkey = backend.passkeys.finish_registration(&response, &state).unwrap();
+    backend.add_passkey(user.id, handle, "soft passkey", &passkey).await.unwrap();
+    response
+}
+
+/// Answer a login challenge with the passkey from `registration`, claiming it belongs to
+/// `user_handle`. `SoftPasskey` only answers for credentials it's told about, and doesn't keep user
+/// handles, so this does the part of a discoverable credential that it can't: picking the
+/// credential, and filling in the handle.
+fn sign(
+    backend: &MemoryBackend,
+    authenticator: &mut Authenticator,
+    mut challenge: RequestChallengeResponse,
+    registration: &RegisterPublicKeyCredential,
+    user_handle: Uuid,
+) -> PublicKeyCredential {
+    challenge.public_key.allow_credentials.push(AllowCredentials {
+        type_: "public-key".into(),
+        id: registration.raw_id.clone(),
+        transports: None,
+    });
+    let mut response = authenticator.do_authentication(origin(backend), challenge)
+        .expect("the soft passkey signs");
+    response.response.user_handle = Some(user_handle.as_bytes().to_vec().into());
+    response
+}
+
+#[tokio::test]
+async fn register_a_passkey_and_log_in_with_it() {
+    let backend = common::backend();
+    let alice = add_user(&backend, "alice").await;
+    let mut authenticator = authenticator();
+    let registration = register(&backend, &mut authenticator, &alice).await;
+    let passkeys = backend.list_passkeys(alice.id).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+    assert_eq!(passkeys[0].last_used_at, None);
+
+    let handle = backend.passkey_user_handle(alice.id).await.unwrap();
+    let (challenge, state) = backend.passkeys.start_login().unwrap();
+    let response = sign(&backend, &mut authenticator, challenge, &registration, handle);
+    let user = backend.authenticate(Credentials::Passkey{ response, state }).await.unwrap();
+    assert_eq!(user.map(|u| u.id), Some(alice.id));
+    assert!(backend.list_passkeys(alice.id).await.unwrap()[0].last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn an_answer_to_another_challenge_is_refused() {
+    let backend = common::backend();
+    let alice = add_user(&backend, "alice").await;
+    let mut authenticator = authenticator();
+    let registration = register(&backend, &mut authenticator, &alice).await;
+    let handle = backend.passkey_user_handle(alice.id).await.unwrap();
+
+    let (challenge, _) = backend.passkeys.start_login().unwrap();
+    let (_, other_state) = backend.passkeys.start_login().unwrap();
+    let response = sign(&backend, &mut authenticator, challenge, &registration, handle);
+    let user = backend.authenticate(Credentials::Passkey{ response, state: other_state }).await.unwrap();
+    assert!(user.is_none());
+}
+
+#[tokio::test]
+async fn a_passkey_only_logs_in_its_own_user() {
+    let backend = common::backend();
+    let alice = add_user(&backend, "alice").await;
+    let bob = add_user(&backend, "bob").await;
+    let mut authenticator = authenticator();
+    let registration = register(&backend, &mut authenticator, &alice).await;
+
+    // Alice's passkey, but the answer says it's bob's.
+    let bobs_handle = backend.passkey_user_handle(bob.id).await.unwrap();
+    let (challenge, state) = backend.passkeys.start_login().unwrap();
+    let response = sign(&backend, &mut authenticator, challenge, &registration, bobs_handle);
+    let user = backend.authenticate(Credentials::Passkey{ response, state }).await.unwrap();
+    assert!(user.is_none());
+}